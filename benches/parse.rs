@@ -0,0 +1,71 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use tracing_gui::logs::Logs;
+
+/// Lines shaped like tracing-subscriber's json output, with enough variety
+/// in the messages and spans that interning has some real work to do.
+fn corpus(lines: usize) -> Vec<String> {
+    let levels = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+    (0..lines)
+        .map(|i| {
+            format!(
+                r#"{{"timestamp":"2022-02-15T18:47:10.{:06}Z","level":"{}","fields":{{"message":"shaving yak number {}","yak":{},"excitement":"yay!","ratio":{}.5}},"target":"fmt_json::yak_shave","spans":[{{"yaks":{},"name":"shaving_yaks"}},{{"yak":{},"name":"shave"}}]}}"#,
+                i % 1_000_000,
+                levels[i % levels.len()],
+                i,
+                i % 7,
+                i % 13,
+                i % 10,
+                i % 100
+            )
+        })
+        .collect()
+}
+
+fn bench_add_json_message(c: &mut Criterion) {
+    let lines = corpus(50_000);
+    let bytes = lines.iter().map(|line| line.len() as u64).sum();
+
+    let mut group = c.benchmark_group("add_json_message");
+    group.throughput(Throughput::Bytes(bytes));
+    group.sample_size(20);
+    group.bench_function("synthetic", |b| {
+        b.iter(|| {
+            let logs = Logs::new();
+            for line in &lines {
+                logs.add_json_message(line);
+            }
+            logs
+        })
+    });
+    group.finish();
+}
+
+/// Loading a small file once, where interning field values may not pay for itself.
+fn bench_intern_values(c: &mut Criterion) {
+    let lines = corpus(2_000);
+    let bytes = lines.iter().map(|line| line.len() as u64).sum();
+
+    let mut group = c.benchmark_group("small_file");
+    group.throughput(Throughput::Bytes(bytes));
+    for intern_values in [true, false] {
+        let name = if intern_values {
+            "interned"
+        } else {
+            "not_interned"
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let logs = Logs::new();
+                logs.set_intern_values(intern_values);
+                for line in &lines {
+                    logs.add_json_message(line);
+                }
+                logs
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_add_json_message, bench_intern_values);
+criterion_main!(benches);
@@ -0,0 +1,340 @@
+//! Spotting field values that are really some other format (JSON, SQL, a URL)
+//! and splitting them into tokens to color them by.
+//!
+//! This is only done for the one value in the expanded value window, so it
+//! favors being simple over being fast or exactly right: a mis-detected value
+//! just gets colored a bit oddly.
+
+use std::ops::Range;
+
+/// A format a string field's value can hold.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Sql,
+    Url,
+}
+
+/// What a token is, to pick its color.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    /// A JSON object key, or a URL's query parameter name
+    Key,
+    String,
+    Number,
+    /// SQL keywords, JSON's `true`/`false`/`null`, and a URL's scheme and host
+    Keyword,
+    Punct,
+}
+
+/// The SQL keywords to highlight (matched case-insensitively).
+const SQL_KEYWORDS: &[&str] = &[
+    "ALTER",
+    "AND",
+    "AS",
+    "ASC",
+    "BEGIN",
+    "BETWEEN",
+    "BY",
+    "CASE",
+    "COMMIT",
+    "CREATE",
+    "DELETE",
+    "DESC",
+    "DISTINCT",
+    "DROP",
+    "ELSE",
+    "END",
+    "EXISTS",
+    "FROM",
+    "GROUP",
+    "HAVING",
+    "IN",
+    "INDEX",
+    "INNER",
+    "INSERT",
+    "INTO",
+    "IS",
+    "JOIN",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "NOT",
+    "NULL",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "RETURNING",
+    "RIGHT",
+    "ROLLBACK",
+    "SELECT",
+    "SET",
+    "TABLE",
+    "THEN",
+    "UNION",
+    "UPDATE",
+    "VALUES",
+    "WHEN",
+    "WHERE",
+    "WITH",
+];
+
+/// The keywords a SQL statement can start with.
+const SQL_STATEMENTS: &[&str] = &[
+    "SELECT", "INSERT", "UPDATE", "DELETE", "WITH", "CREATE", "ALTER", "DROP", "BEGIN",
+];
+
+impl Format {
+    /// Guess what format `text` is in, if it looks like one we know.
+    pub fn detect(text: &str) -> Option<Format> {
+        let text = text.trim();
+        if (text.starts_with('{') || text.starts_with('['))
+            && serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok()
+        {
+            return Some(Format::Json);
+        }
+        if !text.contains(char::is_whitespace)
+            && ["http://", "https://"]
+                .iter()
+                .any(|scheme| text.starts_with(scheme))
+        {
+            return Some(Format::Url);
+        }
+        let first_word = text.split(|c: char| !c.is_alphanumeric()).next()?;
+        if text.contains(char::is_whitespace)
+            && SQL_STATEMENTS
+                .iter()
+                .any(|keyword| keyword.eq_ignore_ascii_case(first_word))
+        {
+            return Some(Format::Sql);
+        }
+        None
+    }
+
+    /// Split `text` into tokens, which cover all of it in order.
+    pub fn tokenize(self, text: &str) -> Vec<(Range<usize>, TokenKind)> {
+        let mut tokens = Vec::new();
+        match self {
+            Format::Json => tokenize_json(text, &mut tokens),
+            Format::Sql => tokenize_sql(text, &mut tokens),
+            Format::Url => tokenize_url(text, &mut tokens),
+        }
+        tokens
+    }
+}
+
+/// Add a token, merging it into the last one if they're the same kind.
+fn push(tokens: &mut Vec<(Range<usize>, TokenKind)>, range: Range<usize>, kind: TokenKind) {
+    if range.is_empty() {
+        return;
+    }
+    match tokens.last_mut() {
+        Some((last, last_kind)) if *last_kind == kind && last.end == range.start => {
+            last.end = range.end;
+        }
+        _ => tokens.push((range, kind)),
+    }
+}
+
+/// The end of the quoted string starting at `start`, past the closing quote
+/// (or the end of `text` if it isn't closed).
+fn quoted_end(text: &str, start: usize, quote: u8) -> usize {
+    let bytes = text.as_bytes();
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b if b == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// The end of the run of bytes starting at `start` that match `f`.
+fn run_end(text: &str, start: usize, f: impl Fn(u8) -> bool) -> usize {
+    text.as_bytes()[start..]
+        .iter()
+        .position(|&b| !f(b))
+        .map_or(text.len(), |len| start + len)
+}
+
+fn tokenize_json(text: &str, tokens: &mut Vec<(Range<usize>, TokenKind)>) {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (end, kind) = match bytes[i] {
+            b'"' => {
+                let end = quoted_end(text, i, b'"');
+                let rest = &text[end..];
+                let is_key = rest.trim_start().starts_with(':');
+                (
+                    end,
+                    if is_key {
+                        TokenKind::Key
+                    } else {
+                        TokenKind::String
+                    },
+                )
+            }
+            b'-' | b'0'..=b'9' => (
+                run_end(text, i + 1, |b| {
+                    b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-')
+                }),
+                TokenKind::Number,
+            ),
+            b'a'..=b'z' => (
+                run_end(text, i, |b| b.is_ascii_alphabetic()),
+                TokenKind::Keyword,
+            ),
+            b'{' | b'}' | b'[' | b']' | b':' | b',' => (i + 1, TokenKind::Punct),
+            _ => (
+                run_end(text, i, |b| {
+                    !b"\"-0123456789{}[]:,".contains(&b) && !b.is_ascii_lowercase()
+                }),
+                TokenKind::Plain,
+            ),
+        };
+        push(tokens, i..end, kind);
+        i = end;
+    }
+}
+
+fn tokenize_sql(text: &str, tokens: &mut Vec<(Range<usize>, TokenKind)>) {
+    let bytes = text.as_bytes();
+    let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || !b.is_ascii();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (end, kind) = match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => (
+                quoted_end(text, i, quote),
+                if quote == b'\'' {
+                    TokenKind::String
+                } else {
+                    TokenKind::Plain
+                },
+            ),
+            b'0'..=b'9' => (
+                run_end(text, i, |b| b.is_ascii_digit() || b == b'.'),
+                TokenKind::Number,
+            ),
+            b if is_word(b) => {
+                let end = run_end(text, i, is_word);
+                let word = &text[i..end];
+                let is_keyword = SQL_KEYWORDS
+                    .iter()
+                    .any(|keyword| keyword.eq_ignore_ascii_case(word));
+                (
+                    end,
+                    if is_keyword {
+                        TokenKind::Keyword
+                    } else {
+                        TokenKind::Plain
+                    },
+                )
+            }
+            b if b.is_ascii_whitespace() => (
+                run_end(text, i, |b| b.is_ascii_whitespace()),
+                TokenKind::Plain,
+            ),
+            _ => (i + 1, TokenKind::Punct),
+        };
+        push(tokens, i..end, kind);
+        i = end;
+    }
+}
+
+fn tokenize_url(text: &str, tokens: &mut Vec<(Range<usize>, TokenKind)>) {
+    // scheme://host/path?key=value&key=value#fragment
+    let host_start = text.find("://").map_or(0, |i| i + 3);
+    push(tokens, 0..host_start, TokenKind::Keyword);
+    let query_start = text.find('?').unwrap_or(text.len());
+    let fragment_start = text.find('#').unwrap_or(text.len());
+    let path_end = query_start.min(fragment_start);
+    let host_end = text[host_start..path_end]
+        .find('/')
+        .map_or(path_end, |i| host_start + i);
+    push(tokens, host_start..host_end, TokenKind::Keyword);
+    push(tokens, host_end..path_end, TokenKind::Plain);
+
+    if query_start < fragment_start {
+        let mut i = query_start;
+        for param in text[query_start + 1..fragment_start].split('&') {
+            // The `?` or `&` before it
+            push(tokens, i..i + 1, TokenKind::Punct);
+            i += 1;
+            let param_end = i + param.len();
+            match param.find('=') {
+                Some(eq) => {
+                    push(tokens, i..i + eq, TokenKind::Key);
+                    push(tokens, i + eq..i + eq + 1, TokenKind::Punct);
+                    push(tokens, i + eq + 1..param_end, TokenKind::String);
+                }
+                None => push(tokens, i..param_end, TokenKind::Key),
+            }
+            i = param_end;
+        }
+    }
+    push(
+        tokens,
+        fragment_start.max(path_end)..text.len(),
+        TokenKind::Plain,
+    );
+}
+
+#[test]
+fn test_highlight() {
+    assert_eq!(Format::detect(r#"{"a": [1, true]}"#), Some(Format::Json));
+    assert_eq!(Format::detect("{not json"), None);
+    assert_eq!(Format::detect("select * from yaks"), Some(Format::Sql));
+    assert_eq!(Format::detect("selecting yaks"), None);
+    assert_eq!(
+        Format::detect("https://example.com/a?b=c"),
+        Some(Format::Url)
+    );
+    assert_eq!(Format::detect("see https://example.com"), None);
+
+    let text = r#"{"yak": "shaved", "n": -1.5e3, "ok": null}"#;
+    let tokens = Format::Json.tokenize(text);
+    let pick = |kind| {
+        tokens
+            .iter()
+            .filter(|(_, k)| *k == kind)
+            .map(|(range, _)| &text[range.clone()])
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(pick(TokenKind::Key), [r#""yak""#, r#""n""#, r#""ok""#]);
+    assert_eq!(pick(TokenKind::String), [r#""shaved""#]);
+    assert_eq!(pick(TokenKind::Number), ["-1.5e3"]);
+    assert_eq!(pick(TokenKind::Keyword), ["null"]);
+
+    for (format, text) in [
+        (Format::Json, text),
+        (
+            Format::Sql,
+            "SELECT name FROM yaks WHERE id = 'a''b' AND n > 3",
+        ),
+        (Format::Url, "https://example.com/a/b?x=1&y&z=2#top"),
+        (Format::Url, "http://example.com#frag?not=query"),
+    ] {
+        let tokens = format.tokenize(text);
+        let mut covered = 0;
+        for (range, _) in &tokens {
+            assert_eq!(range.start, covered, "{:?} has a gap", text);
+            covered = range.end;
+        }
+        assert_eq!(covered, text.len());
+    }
+
+    let text = "https://example.com/a?x=1";
+    let keys = Format::Url
+        .tokenize(text)
+        .into_iter()
+        .filter(|(_, kind)| *kind == TokenKind::Key)
+        .map(|(range, _)| &text[range])
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["x"]);
+}
@@ -1,62 +1,492 @@
 use std::{
+    collections::VecDeque,
     fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-    sync::{Arc, Condvar, Mutex},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Condvar, Mutex},
+    time::{Duration, Instant},
 };
 
 use eframe::CreationContext;
-use logs::Logs;
+use logs::{
+    DisplayOptions, ErrorSpans, Filter, LevelCounts, LineKind, Logs, MemoryEstimate, MessageId,
+    Query, SpanId,
+};
+use serde::{Deserialize, Serialize};
+use ui_filter::FilterUi;
 use ui_logs_linear::LinearLogsUi;
-use ui_logs_tree::TreeLogsUi;
+use ui_logs_rows::{LevelPalette, RowsUi};
+use ui_logs_tree::{LandOn, SpanSort, TreeLogsUi};
 use ui_settings::SettingsUi;
+use ui_workspaces::{Workspace, WorkspacesUi};
 
+mod highlight;
 pub mod logs;
+mod ui_filter;
 mod ui_logs_linear;
+mod ui_logs_rows;
 mod ui_logs_tree;
+mod ui_parse_errors;
 mod ui_settings;
+mod ui_status;
+mod ui_workspaces;
 
 pub struct App {
+    /// The traces that are open, shown side by side. There's always at least one.
+    viewers: Vec<Viewer>,
+    /// The viewer the tab bar, status bar, and settings are for
+    cur_viewer: usize,
+    /// The id to give the next viewer opened
+    next_viewer_id: usize,
+    /// For starting up the processors of new viewers
+    ctx: egui::Context,
+    /// The part of the file to read next time one is opened
+    load_range: LoadRange,
+
+    settings: Settings,
+    tab: Tab,
+    /// Hide the tab bar for a full-height log view (toggled with Ctrl+Shift+H)
+    hide_tabs: bool,
+    /// Show nothing but the current tab's logs across the whole window
+    /// (toggled with Ctrl+Shift+F, left with Escape)
+    focus_mode: bool,
+    settings_ui: SettingsUi,
+    workspaces_ui: WorkspacesUi,
+}
+
+/// One trace being looked at: the logs, the processor reading them in, and
+/// where the user is in them. The settings are shared between all of these.
+struct Viewer {
+    /// Tells viewers apart in the ids of their panels and windows
+    id: usize,
     logs: Logs,
     cur_status: ProcessorStatus,
+    cur_throughput: Throughput,
+    loaded_path: Option<PathBuf>,
+    /// The part of `loaded_path` that was actually read
+    loaded_range: LoadRange,
 
-    settings: Settings,
+    filter: Filter,
+    filter_ui: FilterUi,
+    /// A message to jump to once the logs are done loading
+    pending_goto: Option<MessageId>,
+    /// Scroll to the end once the logs are done loading
+    pending_jump_to_end: bool,
+    /// Pick a span per `Settings::land_on` once the logs are done loading
+    /// (not for reloads, which shouldn't lose your place)
+    pending_land_on: bool,
+    /// The counts from before a reload, to compare against once it's done
+    reload_baseline: Option<LevelCounts>,
+    /// What the last reload added, shown in the status bar for a little while
+    reload_changes: Option<ReloadChanges>,
+    /// How much memory the logs take, and how many messages there were and
+    /// when it was worked out
+    memory_estimate: Option<(usize, Instant, MemoryEstimate)>,
 
-    tab: Tab,
+    /// Show the panel listing the lines that failed to parse
+    show_parse_errors: bool,
     tree_logs_ui: TreeLogsUi,
     linear_logs_ui: LinearLogsUi,
-    #[allow(dead_code)]
-    settings_ui: SettingsUi,
+    rows_ui: RowsUi,
+    /// Shared by the views for `Filter::errors_only_spans`
+    error_spans: Option<ErrorSpans>,
 
     task_sender: ProcessorTaskSender,
     status_receiver: ProcessorStatusReceiver,
-    _processor_thread: std::thread::JoinHandle<()>,
+    /// Taken when the viewer is dropped, to shut the processor down
+    processor_thread: Option<std::thread::JoinHandle<()>>,
+}
+
+/// How the messages changed over a reload.
+#[derive(Debug, Clone, Copy)]
+struct ReloadChanges {
+    messages: i64,
+    errors: i64,
+    at: Instant,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum Tab {
+impl ReloadChanges {
+    const SHOW_FOR: Duration = Duration::from_secs(10);
+
+    fn new(before: LevelCounts, after: LevelCounts) -> Self {
+        Self {
+            messages: after.total() as i64 - before.total() as i64,
+            errors: after.error as i64 - before.error as i64,
+            at: Instant::now(),
+        }
+    }
+}
+
+/// Things to do as soon as the app starts up.
+#[derive(Debug, Default, Clone)]
+pub struct StartupOptions {
+    /// A log file to open
+    pub path: Option<PathBuf>,
+    /// A message to scroll to and highlight once the logs are loaded
+    pub goto: Option<MessageId>,
+    /// The tab to show, instead of what the settings say
+    pub tab: Option<Tab>,
+}
+
+/// What egui's `command` modifier is called on this platform, for shortcut hints.
+const COMMAND: &str = if cfg!(target_os = "macos") {
+    "Cmd"
+} else {
+    "Ctrl"
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tab {
     Settings,
     TreeLogs,
     LinearLogs,
 }
 
-#[derive(Debug, Clone)]
+impl Tab {
+    const ALL: [Tab; 3] = [Tab::Settings, Tab::LinearLogs, Tab::TreeLogs];
+    /// The linear view isn't finished, so it's hidden until asked for.
+    const DEFAULT_SHOWN: [Tab; 2] = [Tab::Settings, Tab::TreeLogs];
+
+    fn name(self) -> &'static str {
+        match self {
+            Tab::Settings => "settings",
+            Tab::LinearLogs => "linear logs",
+            Tab::TreeLogs => "tree logs",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            Tab::Settings => "⚙",
+            Tab::LinearLogs => "☰",
+            Tab::TreeLogs => "🌲",
+        }
+    }
+}
+
+impl std::str::FromStr for Tab {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "settings" => Ok(Tab::Settings),
+            "linear" => Ok(Tab::LinearLogs),
+            "tree" => Ok(Tab::TreeLogs),
+            _ => Err(format!(
+                "unknown tab {:?} (expected settings, linear, or tree)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 struct Settings {
     available_paths: Vec<PathBuf>,
     picked_path: Option<String>,
+    display: DisplayOptions,
+    /// Width of the span list in the tree view, as last dragged by the user.
+    span_list_width: Option<f32>,
+    span_sort: SpanSort,
+    /// Hide spans with nothing matching the filter from the span list.
+    hide_unmatched_spans: bool,
+    /// Nest the span list the way the spans are nested, instead of listing them flat.
+    span_list_tree: bool,
+    /// Say how many messages are in each span in the span list.
+    show_span_counts: bool,
+    /// Draw when each span's messages happened next to it in the span list.
+    show_span_sparklines: bool,
+    /// Show every span with the same name and fields together, wherever they are.
+    merge_identical_spans: bool,
+    /// Collapse spans that repeat under the same parent into one entry in the
+    /// span list (see `LogsInner::group_repeated_spans`).
+    group_repeated_spans: bool,
+    /// The comma-separated fields that can differ between repeats of a span.
+    varying_fields: String,
+    /// Which span to select once a file is loaded.
+    land_on: LandOn,
+    /// Color values that hold JSON, SQL, or a URL in the expanded value window.
+    highlight_values: bool,
+    /// Offer to expand string values that hold JSON, decoding them first.
+    decode_json_strings: bool,
+    /// Show a line of stats about the selected span above its messages.
+    show_span_summary: bool,
+    /// Widths of the columns of the field stats table, as last dragged by the user.
+    stat_column_widths: Vec<f32>,
+    /// Show the tabs as just icons.
+    compact_tabs: bool,
+    /// The tabs in the tab bar, in order (`Tab::DEFAULT_SHOWN` if unset).
+    tabs: Option<Vec<Tab>>,
+    /// The tab to start on (settings if unset).
+    startup_tab: Option<Tab>,
+    /// The tab to switch to when a file is opened (the tree view if unset).
+    opened_file_tab: Option<Tab>,
+    /// Don't switch tabs when a file is opened, ignoring `opened_file_tab`.
+    keep_tab_on_open: bool,
+    /// The user's own field presets, on top of `FieldPreset::builtin`.
+    field_presets: Vec<FieldPreset>,
+    /// The colors to draw each level in.
+    level_palette: LevelPalette,
+    /// Saved setups of file, filter, and view to switch between.
+    workspaces: Vec<Workspace>,
+    processor: ProcessorSettings,
+}
+
+/// A named choice of which fields to show, for flipping between kinds of logs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FieldPreset {
+    name: String,
+    /// The only fields to show besides the message, or all of them if `None`.
+    shown_keys: Option<Vec<String>>,
+}
+
+impl FieldPreset {
+    fn builtin() -> Vec<FieldPreset> {
+        let preset = |name: &str, keys: Option<&[&str]>| FieldPreset {
+            name: name.to_owned(),
+            shown_keys: keys.map(|keys| keys.iter().map(|key| key.to_string()).collect()),
+        };
+        vec![
+            preset("full", None),
+            preset("minimal", Some(&[])),
+            preset("web", Some(&["status", "method", "path", "latency"])),
+        ]
+    }
+}
+
+/// How many spans to make before putting messages in outer spans instead,
+/// unless the user says otherwise. This is far more than a sensible trace
+/// has, so it only kicks in when something's gone wrong.
+const DEFAULT_MAX_SPANS: usize = 1_000_000;
+
+/// Knobs for the background thread that reads the logs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+struct ProcessorSettings {
+    /// How often to check for cancellation and report progress while reading.
+    checkin_interval_ms: u64,
+    /// Dedupe the values of message fields (see `LogsInner::intern_values`).
+    intern_values: bool,
+    /// Gather lines into one record until a blank line, instead of one record
+    /// per line. This is for pretty-printed JSON logs.
+    blank_line_records: bool,
+    /// How records are delimited, for logs that aren't split into lines.
+    framing: Framing,
+    /// Keep lines that aren't JSON as plain text messages.
+    plain_text_fallback: bool,
+    /// The top-level key to read each message's timestamp from.
+    timestamp_key: String,
+    /// Messages' `spans` go from the innermost span out, instead of the other way.
+    spans_innermost_first: bool,
+    /// See `LogsInner::max_spans`.
+    max_spans: Option<usize>,
+    /// Throw away what was read when loading is cancelled, instead of keeping
+    /// the part that was read.
+    clear_on_cancel: bool,
+    /// Once the end of the file is reached, keep watching it for new lines.
+    follow: bool,
+    /// How often to check a followed file for new lines.
+    follow_interval_ms: u64,
+    /// Throw away lines that come in while following is paused, instead of
+    /// holding on to them until it's resumed.
+    discard_while_paused: bool,
+}
+
+impl Default for ProcessorSettings {
+    fn default() -> Self {
+        Self {
+            checkin_interval_ms: 50,
+            intern_values: true,
+            blank_line_records: false,
+            framing: Framing::default(),
+            plain_text_fallback: false,
+            timestamp_key: logs::JSON_TIMESTAMP_KEY.to_owned(),
+            spans_innermost_first: false,
+            max_spans: Some(DEFAULT_MAX_SPANS),
+            clear_on_cancel: false,
+            follow: false,
+            follow_interval_ms: 250,
+            discard_while_paused: false,
+        }
+    }
+}
+
+impl ProcessorSettings {
+    fn checkin_interval(&self) -> Duration {
+        Duration::from_millis(self.checkin_interval_ms)
+    }
+
+    fn follow_interval(&self) -> Duration {
+        Duration::from_millis(self.follow_interval_ms)
+    }
+
+    /// `timestamp_key`, or the usual one if it was left blank.
+    fn timestamp_key(&self) -> &str {
+        match self.timestamp_key.trim() {
+            "" => logs::JSON_TIMESTAMP_KEY,
+            key => key,
+        }
+    }
 }
 
 type ProcessorTaskSender = Arc<(Mutex<Option<ProcessorTask>>, Condvar)>;
 type ProcessorTaskReceiver = ProcessorTaskSender;
-type ProcessorStatusSender = Arc<Mutex<ProcessorStatus>>;
-type ProcessorStatusReceiver = ProcessorStatusSender;
+type ProcessorStatusSender = mpsc::SyncSender<(ProcessorStatus, Throughput)>;
+type ProcessorStatusReceiver = mpsc::Receiver<(ProcessorStatus, Throughput)>;
+
+/// How many status updates can be waiting for the ui before progress
+/// updates start getting dropped.
+const STATUS_CHANNEL_BOUND: usize = 64;
 
 enum ProcessorTask {
-    OpenLogs(PathBuf),
+    OpenLogs(PathBuf, LoadRange, ProcessorSettings),
+    /// Read all the (rotated) log files in a directory as one stream.
+    OpenDir(PathBuf, ProcessorSettings),
+    /// Stop whatever is happening and exit the processor thread.
+    Shutdown,
     Cancel,
+    /// Stop adding new lines to the logs while following a file.
+    Pause,
+    /// Catch up on what came in while paused, and carry on following.
+    Resume,
+}
+
+/// How the records of a log file are delimited.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Framing {
+    /// By newlines (or blank lines, see `ProcessorSettings::blank_line_records`)
+    #[default]
+    Lines,
+    /// Each record comes after its length as a LEB128 varint, like protobuf's
+    /// length-delimited streams
+    Varint,
+    /// Each record comes after its length as a 4-byte big-endian integer
+    U32Be,
+    /// Each record comes after its length as a 4-byte little-endian integer
+    U32Le,
+}
+
+impl Framing {
+    const ALL: [Framing; 4] = [
+        Framing::Lines,
+        Framing::Varint,
+        Framing::U32Be,
+        Framing::U32Le,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Framing::Lines => "lines",
+            Framing::Varint => "varint length prefix",
+            Framing::U32Be => "u32 length prefix (big-endian)",
+            Framing::U32Le => "u32 length prefix (little-endian)",
+        }
+    }
+}
+
+/// The biggest length-prefixed record we'll believe in, so that reading a file
+/// with the wrong framing doesn't try to allocate some absurd amount.
+const MAX_FRAME_LEN: u64 = 256 * 1024 * 1024;
+
+/// Read the next length-prefixed record into `record`, returning how many bytes
+/// that took (prefix included), or `None` if the input ended cleanly first.
+fn read_frame(
+    reader: &mut impl Read,
+    framing: Framing,
+    record: &mut Vec<u8>,
+) -> std::io::Result<Option<usize>> {
+    use std::io::{Error, ErrorKind};
+
+    let mut prefix_len = 0;
+    let mut next_byte = |reader: &mut _| -> std::io::Result<Option<u8>> {
+        let mut byte = [0];
+        match Read::read_exact(reader, &mut byte) {
+            Ok(()) => {
+                prefix_len += 1;
+                Ok(Some(byte[0]))
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    };
+    let truncated = || Error::new(ErrorKind::UnexpectedEof, "the last record was cut off");
+
+    let Some(first) = next_byte(reader)? else {
+        return Ok(None);
+    };
+    let len = match framing {
+        Framing::Lines => unreachable!("lines aren't length-prefixed"),
+        Framing::Varint => {
+            let mut len = 0u64;
+            let mut byte = first;
+            let mut shift = 0;
+            loop {
+                if shift >= 64 {
+                    return Err(Error::new(ErrorKind::InvalidData, "varint is too long"));
+                }
+                len |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    break len;
+                }
+                shift += 7;
+                byte = next_byte(reader)?.ok_or_else(truncated)?;
+            }
+        }
+        Framing::U32Be | Framing::U32Le => {
+            let mut bytes = [first, 0, 0, 0];
+            for byte in &mut bytes[1..] {
+                *byte = next_byte(reader)?.ok_or_else(truncated)?;
+            }
+            let len = match framing {
+                Framing::U32Be => u32::from_be_bytes(bytes),
+                _ => u32::from_le_bytes(bytes),
+            };
+            u64::from(len)
+        }
+    };
+    if len > MAX_FRAME_LEN {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("record length {} is too big, is the framing right?", len),
+        ));
+    }
+    record.resize(len as usize, 0);
+    reader.read_exact(record).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => truncated(),
+        _ => e,
+    })?;
+    Ok(Some(prefix_len + record.len()))
 }
 
+/// Which part of a log file to read, for inspecting a slice of a huge file
+/// without paying to parse all of it.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum LoadRange {
+    #[default]
+    All,
+    /// Lines `start..end`, counting from 0.
+    Lines { start: u64, end: u64 },
+    /// Every line that starts within bytes `start..end`.
+    Bytes { start: u64, end: u64 },
+    /// The last `lines` lines, like `tail -n`.
+    Tail { lines: u64 },
+}
+
+impl LoadRange {
+    /// A description of the range if it's only part of the file.
+    fn partial_description(&self) -> Option<String> {
+        match self {
+            LoadRange::All => None,
+            LoadRange::Lines { start, end } => Some(format!("lines {}..{}", start, end)),
+            LoadRange::Bytes { start, end } => Some(format!("bytes {}..{}", start, end)),
+            LoadRange::Tail { lines } => Some(format!("the last {} lines", lines)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 enum ProcessorStatus {
     #[default]
     NotStarted,
@@ -64,140 +494,1369 @@ enum ProcessorStatus {
     Cancelled,
     Reading,
     Done,
+    /// Done reading what was there, and watching for more.
+    Following,
+    /// Following, but not adding new lines to the logs. `new_lines` is how
+    /// many came in since then, which are held on to until it's resumed, or
+    /// thrown away if `discarding` (`ProcessorSettings::discard_while_paused`,
+    /// as it was when the file was opened).
+    Paused {
+        new_lines: usize,
+        discarding: bool,
+    },
+    /// Something went wrong in the processor itself, with the panic message.
+    Crashed(String),
+}
+
+impl ProcessorStatus {
+    fn is_following(&self) -> bool {
+        matches!(
+            self,
+            ProcessorStatus::Following | ProcessorStatus::Paused { .. }
+        )
+    }
+}
+
+/// How fast the processor is chewing through the current file.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Throughput {
+    lines: u64,
+    bytes: u64,
+    total_bytes: Option<u64>,
+    lines_per_sec: f64,
+    bytes_per_sec: f64,
+}
+
+/// Computes a rolling rate over the last few checkins, so that a stall shows up
+/// as the rate dropping instead of being averaged away over the whole file.
+struct ThroughputTracker {
+    cur: Throughput,
+    samples: VecDeque<(Instant, u64, u64)>,
+}
+
+impl ThroughputTracker {
+    const WINDOW: Duration = Duration::from_secs(2);
+
+    fn new(total_bytes: Option<u64>) -> Self {
+        let mut samples = VecDeque::new();
+        samples.push_back((Instant::now(), 0, 0));
+        Self {
+            cur: Throughput {
+                total_bytes,
+                ..Throughput::default()
+            },
+            samples,
+        }
+    }
+
+    fn add_line(&mut self, bytes: usize) {
+        self.cur.lines += 1;
+        self.cur.bytes += bytes as u64;
+    }
+
+    fn sample(&mut self) -> Throughput {
+        let now = Instant::now();
+        while self.samples.len() > 1 && now - self.samples[0].0 > Self::WINDOW {
+            self.samples.pop_front();
+        }
+        let (then, old_lines, old_bytes) = self.samples[0];
+        let secs = (now - then).as_secs_f64();
+        if secs > 0.0 {
+            self.cur.lines_per_sec = (self.cur.lines - old_lines) as f64 / secs;
+            self.cur.bytes_per_sec = (self.cur.bytes - old_bytes) as f64 / secs;
+        }
+        self.samples
+            .push_back((now, self.cur.lines, self.cur.bytes));
+        self.cur
+    }
+
+    fn finish(&mut self) -> Throughput {
+        self.cur.lines_per_sec = 0.0;
+        self.cur.bytes_per_sec = 0.0;
+        self.cur
+    }
 }
 
-fn run_processor(
+/// The background thread that reads log files into `Logs`.
+struct Processor {
     task_receiver: ProcessorTaskReceiver,
     status_sender: ProcessorStatusSender,
     logs: Logs,
-) {
-    'main: loop {
-        let (lock, condvar) = &*task_receiver;
-        let task = {
-            let mut task = lock.lock().unwrap();
-            if task.is_none() {
-                task = condvar.wait(task).unwrap();
+    ctx: egui::Context,
+}
+
+/// Where to stop reading, for `LoadRange`s.
+#[derive(Debug, Default, Copy, Clone)]
+struct ReadLimits {
+    max_lines: Option<u64>,
+    max_bytes: Option<u64>,
+}
+
+/// Where the lines being read came from, for saying where parse errors are.
+#[derive(Debug, Clone)]
+struct ReadSource {
+    file: Arc<Path>,
+    /// The line number of the first line read, counting from 1, if we know it
+    first_line: Option<u64>,
+}
+
+/// How far `read_lines` got, so following can carry on from there.
+#[derive(Debug, Default)]
+struct ReadProgress {
+    /// The line number of the next line (see `ReadSource::first_line`)
+    next_line: Option<u64>,
+    /// A record that hasn't ended yet, and the line it started on
+    record: String,
+    record_line: Option<u64>,
+    /// The start of a line whose end hasn't been written yet
+    partial: Vec<u8>,
+}
+
+impl Processor {
+    /// How often to wake the ui up to show progress while reading.
+    const REPAINT_INTERVAL: Duration = Duration::from_millis(100);
+
+    fn run(&self) {
+        loop {
+            // Whatever we were doing is over, so make sure the ui sees the final status.
+            // Otherwise the ui only repaints when something happens, so it sleeps while idle.
+            self.ctx.request_repaint();
+
+            let (lock, condvar) = &*self.task_receiver;
+            let task = {
+                let mut task = lock.lock().unwrap();
+                if task.is_none() {
+                    task = condvar.wait(task).unwrap();
+                }
+                task.take().unwrap()
+            };
+
+            if let ProcessorTask::Shutdown = task {
+                return;
             }
-            task.take().unwrap()
-        };
+            // A bug tripped by some odd input shouldn't take the whole processor
+            // down with it, so report it and carry on with the next task.
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| self.run_task(task)));
+            if let Err(payload) = result {
+                let message = match payload.downcast::<String>() {
+                    Ok(message) => *message,
+                    Err(payload) => match payload.downcast::<&str>() {
+                        Ok(message) => message.to_string(),
+                        Err(_) => "unknown panic".to_owned(),
+                    },
+                };
+                // Whatever was read before the panic is still worth looking at
+                self.logs.inner.clear_poison();
+                self.logs.invalidate_render();
+                self.set_status(ProcessorStatus::Crashed(message), Throughput::default());
+            }
+        }
+    }
 
+    fn run_task(&self, task: ProcessorTask) {
         match task {
-            ProcessorTask::Cancel => {
+            ProcessorTask::Cancel | ProcessorTask::Pause | ProcessorTask::Resume => {
                 // Do nothing, this is only relevant within the other tasks, now we're just clearing it out
             }
-            ProcessorTask::OpenLogs(path) => {
-                logs.clear();
-                *status_sender.lock().unwrap() = ProcessorStatus::Reading;
-                let file = match File::open(&path) {
-                    Ok(file) => file,
-                    Err(_) => {
-                        *status_sender.lock().unwrap() = ProcessorStatus::IoFailed;
-                        continue 'main;
+            ProcessorTask::OpenLogs(path, range, options) => {
+                self.open_logs(&path, range, &options);
+            }
+            ProcessorTask::OpenDir(path, options) => {
+                self.open_dir(&path, &options);
+            }
+            ProcessorTask::Shutdown => {}
+        }
+    }
+
+    /// Throw away the old logs and get ready to read new ones with `options`.
+    fn start_reading(&self, options: &ProcessorSettings) {
+        self.logs.clear();
+        self.logs.set_intern_values(options.intern_values);
+        self.logs
+            .set_plain_text_fallback(options.plain_text_fallback);
+        self.logs.set_timestamp_key(options.timestamp_key());
+        self.logs
+            .set_spans_innermost_first(options.spans_innermost_first);
+        self.logs.set_max_spans(options.max_spans);
+        self.set_status(ProcessorStatus::Reading, Throughput::default());
+        self.ctx.request_repaint();
+    }
+
+    fn open_logs(&self, path: &Path, range: LoadRange, options: &ProcessorSettings) {
+        self.start_reading(options);
+        let checkin_interval = options.checkin_interval();
+
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => {
+                self.set_status(ProcessorStatus::IoFailed, Throughput::default());
+                return;
+            }
+        };
+        let file_bytes = file.metadata().ok().map(|meta| meta.len());
+        let mut buf_read = BufReader::new(file);
+        // Skipping ahead means looking for newlines, which framed records don't have
+        let range = if options.framing != Framing::Lines && range != LoadRange::All {
+            eprintln!(
+                "WARN: length-prefixed records can only be read from the start, reading everything"
+            );
+            LoadRange::All
+        } else {
+            range
+        };
+
+        // Get to the start of the requested range, and work out when to stop
+        let (total_bytes, limits) = match range {
+            LoadRange::All => (file_bytes, ReadLimits::default()),
+            LoadRange::Lines { start, end } => {
+                let mut last_checkin = Instant::now();
+                let mut skipped = Vec::new();
+                for _ in 0..start {
+                    skipped.clear();
+                    match buf_read.read_until(b'\n', &mut skipped) {
+                        Ok(0) => break,
+                        Ok(_) => {}
+                        Err(_) => {
+                            self.set_status(ProcessorStatus::IoFailed, Throughput::default());
+                            return;
+                        }
+                    }
+                    if last_checkin.elapsed() >= checkin_interval {
+                        last_checkin = Instant::now();
+                        if self.cancel_requested() {
+                            self.finish_reading(
+                                ProcessorStatus::Cancelled,
+                                Throughput::default(),
+                                options,
+                            );
+                            return;
+                        }
                     }
+                }
+                let limits = ReadLimits {
+                    max_lines: Some(end.saturating_sub(start)),
+                    max_bytes: None,
                 };
-                let mut buf_read = BufReader::new(file);
-
-                const LINE_COUNT_CHECKIN: usize = 1000;
-                let mut lines_since_checkin = 0;
-                let mut cur_line = String::new();
-
-                // TODO: do this in more bulk to avoid lots of locking?
-                while let Ok(_line_length) = buf_read.read_line(&mut cur_line) {
-                    // First check if we've been ordered to do something else
-                    lines_since_checkin += 1;
-                    if lines_since_checkin > LINE_COUNT_CHECKIN
-                        && task_receiver.0.lock().unwrap().is_some()
-                    {
-                        *status_sender.lock().unwrap() = ProcessorStatus::Cancelled;
-                        continue 'main;
+                (None, limits)
+            }
+            LoadRange::Bytes { start, end } => {
+                if buf_read.seek(SeekFrom::Start(start)).is_err() {
+                    self.set_status(ProcessorStatus::IoFailed, Throughput::default());
+                    return;
+                }
+                if start > 0 {
+                    // We probably landed in the middle of a line, so skip the rest of it
+                    let _ = buf_read.read_until(b'\n', &mut Vec::new());
+                }
+                let len = end.saturating_sub(start);
+                let limits = ReadLimits {
+                    max_lines: None,
+                    max_bytes: Some(len),
+                };
+                (Some(len), limits)
+            }
+            LoadRange::Tail { lines } => {
+                let start = match tail_start(&mut buf_read, lines, TAIL_CHUNK_SIZE) {
+                    Ok(start) => start,
+                    Err(e) => {
+                        eprintln!("WARN: failed to find the end of {}: {}", path.display(), e);
+                        self.set_status(ProcessorStatus::IoFailed, Throughput::default());
+                        return;
+                    }
+                };
+                if buf_read.seek(SeekFrom::Start(start)).is_err() {
+                    self.set_status(ProcessorStatus::IoFailed, Throughput::default());
+                    return;
+                }
+                let total_bytes = file_bytes.map(|len| len.saturating_sub(start));
+                (total_bytes, ReadLimits::default())
+            }
+        };
+
+        let source = ReadSource {
+            file: Arc::from(path),
+            // Seeking to a byte offset leaves us not knowing how many lines came before
+            first_line: match range {
+                LoadRange::All => Some(1),
+                LoadRange::Lines { start, .. } => Some(start + 1),
+                LoadRange::Bytes { start, .. } => (start == 0).then_some(1),
+                LoadRange::Tail { .. } => None,
+            },
+        };
+        // Following only makes sense when we read up to the end
+        let to_the_end = matches!(range, LoadRange::All | LoadRange::Tail { .. });
+        // (and for lines, since a half-written record can't be told apart from
+        // a corrupt one otherwise)
+        let followable = options.framing == Framing::Lines;
+        let follow = options.follow && to_the_end && followable;
+        let mut progress = ReadProgress::default();
+        let mut throughput = ThroughputTracker::new(total_bytes);
+        let status = self.read_records(
+            &mut buf_read,
+            &source,
+            limits,
+            options,
+            &mut throughput,
+            follow.then_some(&mut progress),
+        );
+        if status == ProcessorStatus::Done && follow {
+            self.follow(&mut buf_read, &source, progress, options, &mut throughput);
+        } else {
+            self.finish_reading(status, throughput.finish(), options);
+        }
+    }
+
+    /// Keep reading lines as they're appended to the file, until told to do
+    /// something else.
+    fn follow(
+        &self,
+        reader: &mut impl BufRead,
+        source: &ReadSource,
+        progress: ReadProgress,
+        options: &ProcessorSettings,
+        throughput: &mut ThroughputTracker,
+    ) {
+        let mut paused = false;
+        // Records that came in while paused, with their line numbers
+        let mut held: Vec<(Option<u64>, String)> = Vec::new();
+        let mut discarded = 0;
+        let ReadProgress {
+            mut next_line,
+            mut record,
+            mut record_line,
+            mut partial,
+        } = progress;
+        self.set_status(ProcessorStatus::Following, throughput.finish());
+
+        loop {
+            // Sleep until it's time to look again, unless a task comes in first
+            let pause = {
+                let (lock, condvar) = &*self.task_receiver;
+                let mut task = lock.lock().unwrap();
+                if task.is_none() {
+                    task = condvar
+                        .wait_timeout(task, options.follow_interval())
+                        .unwrap()
+                        .0;
+                }
+                match *task {
+                    None => None,
+                    Some(ProcessorTask::Pause) => {
+                        *task = None;
+                        Some(true)
                     }
-                    let trim_line = cur_line.trim();
-                    if trim_line.is_empty() {
-                        continue;
+                    Some(ProcessorTask::Resume) => {
+                        *task = None;
+                        Some(false)
+                    }
+                    Some(ProcessorTask::Cancel) => {
+                        // Stop following, but keep what we've got
+                        *task = None;
+                        drop(task);
+                        self.set_status(ProcessorStatus::Done, throughput.finish());
+                        return;
+                    }
+                    // Leave anything else for `run` to pick up
+                    Some(_) => return,
+                }
+            };
+            let mut changed = false;
+            if let Some(pause) = pause {
+                paused = pause;
+                if !paused {
+                    for (line, text) in held.drain(..) {
+                        self.logs.add_json_line(&source.file, line, &text);
                     }
-                    logs.add_json_message(trim_line);
-                    cur_line.clear();
+                    discarded = 0;
                 }
-                *status_sender.lock().unwrap() = ProcessorStatus::Done;
+                changed = true;
             }
+
+            loop {
+                match reader.read_until(b'\n', &mut partial) {
+                    Ok(0) => break,
+                    Ok(_) if !partial.ends_with(b"\n") => {
+                        // The rest of the line isn't there yet, try again next time
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("WARN: stopped following: {}", e);
+                        self.set_status(ProcessorStatus::IoFailed, throughput.finish());
+                        return;
+                    }
+                }
+                throughput.add_line(partial.len());
+                if record.is_empty() {
+                    record_line = next_line;
+                }
+                next_line = next_line.map(|line| line + 1);
+                let line = String::from_utf8_lossy(&partial);
+                let blank = trim_record(&line).is_empty();
+                record.push_str(&line);
+                partial.clear();
+                if options.blank_line_records && !blank {
+                    // Keep going until the blank line that ends the record
+                    continue;
+                }
+                let text = trim_record(&record);
+                if !text.is_empty() {
+                    if !paused {
+                        self.logs.add_json_line(&source.file, record_line, text);
+                    } else if options.discard_while_paused {
+                        discarded += 1;
+                    } else {
+                        held.push((record_line, text.to_owned()));
+                    }
+                    changed = true;
+                }
+                record.clear();
+            }
+
+            if changed {
+                let status = if paused {
+                    ProcessorStatus::Paused {
+                        new_lines: held.len() + discarded,
+                        discarding: options.discard_while_paused,
+                    }
+                } else {
+                    ProcessorStatus::Following
+                };
+                self.set_status(status, throughput.finish());
+                self.ctx.request_repaint();
+            }
+        }
+    }
+
+    fn open_dir(&self, path: &Path, options: &ProcessorSettings) {
+        self.start_reading(options);
+
+        let files = match rotated_log_files(path) {
+            Ok(files) if !files.is_empty() => files,
+            Ok(_) => {
+                eprintln!("WARN: no log files in {}", path.display());
+                self.set_status(ProcessorStatus::IoFailed, Throughput::default());
+                return;
+            }
+            Err(e) => {
+                eprintln!("WARN: failed to read {}: {}", path.display(), e);
+                self.set_status(ProcessorStatus::IoFailed, Throughput::default());
+                return;
+            }
+        };
+        let total_bytes = files
+            .iter()
+            .filter_map(|file| file.metadata().ok())
+            .map(|meta| meta.len())
+            .sum();
+        let mut throughput = ThroughputTracker::new(Some(total_bytes));
+        for file_path in &files {
+            let file = match File::open(file_path) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("WARN: failed to open {}: {}", file_path.display(), e);
+                    continue;
+                }
+            };
+            let source = ReadSource {
+                file: Arc::from(file_path.as_path()),
+                first_line: Some(1),
+            };
+            let status = self.read_records(
+                &mut BufReader::new(file),
+                &source,
+                ReadLimits::default(),
+                options,
+                &mut throughput,
+                None,
+            );
+            if status != ProcessorStatus::Done {
+                self.finish_reading(status, throughput.finish(), options);
+                return;
+            }
+        }
+        self.set_status(ProcessorStatus::Done, throughput.finish());
+    }
+
+    /// Feed records to the logs however they're framed.
+    /// (Only lines can be followed, so `progress` is just for them.)
+    fn read_records(
+        &self,
+        reader: &mut impl BufRead,
+        source: &ReadSource,
+        limits: ReadLimits,
+        options: &ProcessorSettings,
+        throughput: &mut ThroughputTracker,
+        progress: Option<&mut ReadProgress>,
+    ) -> ProcessorStatus {
+        match options.framing {
+            Framing::Lines => {
+                self.read_lines(reader, source, limits, options, throughput, progress)
+            }
+            _ => self.read_frames(reader, source, limits, options, throughput),
+        }
+    }
+
+    /// `read_lines`, but for length-prefixed records. Each record counts as a
+    /// "line" for line numbers and line limits.
+    fn read_frames(
+        &self,
+        reader: &mut impl BufRead,
+        source: &ReadSource,
+        limits: ReadLimits,
+        options: &ProcessorSettings,
+        throughput: &mut ThroughputTracker,
+    ) -> ProcessorStatus {
+        let checkin_interval = options.checkin_interval();
+        let mut last_checkin = Instant::now();
+        let mut last_repaint = Instant::now();
+        let mut record = Vec::new();
+        let mut records_read = 0;
+        let mut status = ProcessorStatus::Done;
+        loop {
+            let past_end = limits
+                .max_lines
+                .is_some_and(|max| throughput.cur.lines >= max)
+                || limits
+                    .max_bytes
+                    .is_some_and(|max| throughput.cur.bytes >= max);
+            if past_end {
+                break;
+            }
+            let record_length = match read_frame(reader, options.framing, &mut record) {
+                Ok(None) => break,
+                Ok(Some(record_length)) => record_length,
+                // Like a last line with no newline, that's just where the file ends
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    eprintln!("WARN: {}", e);
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("WARN: stopped reading early: {}", e);
+                    status = ProcessorStatus::IoFailed;
+                    break;
+                }
+            };
+            throughput.add_line(record_length);
+            let record_line = source.first_line.map(|first| first + records_read);
+            records_read += 1;
+
+            if last_checkin.elapsed() >= checkin_interval {
+                last_checkin = Instant::now();
+                if self.cancel_requested() {
+                    return ProcessorStatus::Cancelled;
+                }
+                self.report_progress(throughput.sample());
+                if last_repaint.elapsed() >= Self::REPAINT_INTERVAL {
+                    last_repaint = Instant::now();
+                    self.ctx.request_repaint();
+                }
+            }
+            let text = String::from_utf8_lossy(&record);
+            let text = text.trim();
+            if !text.is_empty() {
+                self.logs.add_json_line(&source.file, record_line, text);
+            }
+        }
+        status
+    }
+
+    /// Feed lines to the logs until we run out, hit the limits, or get cancelled.
+    /// If reading fails partway, whatever was read is kept but it's `IoFailed`.
+    /// With `progress`, the file might still be being written, so a last
+    /// record or line that hasn't ended is left there for `follow` to finish.
+    ///
+    /// Every `checkin_interval` we check for a new task and report progress, so
+    /// cancelling stays snappy even if the lines are huge.
+    fn read_lines(
+        &self,
+        reader: &mut impl BufRead,
+        source: &ReadSource,
+        limits: ReadLimits,
+        options: &ProcessorSettings,
+        throughput: &mut ThroughputTracker,
+        mut progress: Option<&mut ReadProgress>,
+    ) -> ProcessorStatus {
+        let checkin_interval = options.checkin_interval();
+        let mut last_checkin = Instant::now();
+        let mut last_repaint = Instant::now();
+        // The record being read, which is several lines in `blank_line_records` mode
+        let mut cur_line = String::new();
+        // The raw line being read, which might not be valid UTF-8
+        let mut line_bytes = Vec::new();
+        let mut lines_read = 0;
+        // The line number the record started on (see `ReadSource::first_line`)
+        let mut record_line = None;
+        let mut status = ProcessorStatus::Done;
+
+        // TODO: do this in more bulk to avoid lots of locking?
+        loop {
+            line_bytes.clear();
+            let line_length = match reader.read_until(b'\n', &mut line_bytes) {
+                // EOF
+                Ok(0) => break,
+                Ok(line_length) => line_length,
+                Err(e) => {
+                    eprintln!("WARN: stopped reading early: {}", e);
+                    status = ProcessorStatus::IoFailed;
+                    break;
+                }
+            };
+            let past_end = limits
+                .max_lines
+                .is_some_and(|max| throughput.cur.lines >= max)
+                || limits
+                    .max_bytes
+                    .is_some_and(|max| throughput.cur.bytes >= max);
+            if past_end {
+                break;
+            }
+            if !line_bytes.ends_with(b"\n") {
+                if let Some(progress) = progress.as_deref_mut() {
+                    // The rest of the line might still be on its way
+                    progress.partial = std::mem::take(&mut line_bytes);
+                    break;
+                }
+            }
+            throughput.add_line(line_length);
+            // A stray bad byte shouldn't cost us the line, let alone the rest of the file
+            let line_start = cur_line.len();
+            cur_line.push_str(&String::from_utf8_lossy(&line_bytes));
+            if line_start == 0 {
+                record_line = source.first_line.map(|first| first + lines_read);
+            }
+            lines_read += 1;
+
+            // First check if we've been ordered to do something else
+            if last_checkin.elapsed() >= checkin_interval {
+                last_checkin = Instant::now();
+                if self.cancel_requested() {
+                    return ProcessorStatus::Cancelled;
+                }
+                self.report_progress(throughput.sample());
+                if last_repaint.elapsed() >= Self::REPAINT_INTERVAL {
+                    last_repaint = Instant::now();
+                    self.ctx.request_repaint();
+                }
+            }
+            if options.blank_line_records && !trim_record(&cur_line[line_start..]).is_empty() {
+                // Keep going until the blank line that ends the record
+                continue;
+            }
+            let trim_line = trim_record(&cur_line);
+            if !trim_line.is_empty() {
+                self.logs
+                    .add_json_line(&source.file, record_line, trim_line);
+            }
+            cur_line.clear();
+        }
+        if let Some(progress) = progress {
+            progress.next_line = source.first_line.map(|first| first + lines_read);
+            progress.record = cur_line;
+            progress.record_line = record_line;
+            return status;
+        }
+        // The last record might not have a blank line after it
+        let trim_line = trim_record(&cur_line);
+        if !trim_line.is_empty() {
+            self.logs
+                .add_json_line(&source.file, record_line, trim_line);
+        }
+        status
+    }
+
+    /// Whether a new task has come in that we should drop everything for.
+    fn cancel_requested(&self) -> bool {
+        self.task_receiver.0.lock().unwrap().is_some()
+    }
+
+    /// Report how reading ended, first throwing away what was read if it was
+    /// cancelled and that's what the user wants.
+    fn finish_reading(
+        &self,
+        status: ProcessorStatus,
+        throughput: Throughput,
+        options: &ProcessorSettings,
+    ) {
+        if status == ProcessorStatus::Cancelled && options.clear_on_cancel {
+            self.logs.clear();
         }
+        self.set_status(status, throughput);
+    }
+
+    /// Tell the ui about a change in status. These always get delivered.
+    fn set_status(&self, status: ProcessorStatus, throughput: Throughput) {
+        // If the ui is gone then we're about to be shut down anyway
+        let _ = self.status_sender.send((status, throughput));
+    }
+
+    /// Tell the ui how reading is going, unless it's fallen behind on updates.
+    fn report_progress(&self, throughput: Throughput) {
+        let _ = self
+            .status_sender
+            .try_send((ProcessorStatus::Reading, throughput));
     }
 }
 
+/// A record without the whitespace around it, or a byte order mark at its
+/// start. Some editors save files with one, which isn't whitespace to `trim`,
+/// and appending such files together leaves one at the start of a line.
+fn trim_record(record: &str) -> &str {
+    record.trim().trim_start_matches('\u{FEFF}').trim_start()
+}
+
+/// How much to read at a time when looking backwards for the start of a tail.
+const TAIL_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Where the last `lines` lines of a file start, found by reading backwards
+/// from the end a chunk at a time.
+fn tail_start(file: &mut (impl Read + Seek), lines: u64, chunk_size: u64) -> std::io::Result<u64> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if lines == 0 {
+        return Ok(len);
+    }
+    let mut chunk = vec![0; chunk_size as usize];
+    let mut newlines = 0;
+    let mut end = len;
+    while end > 0 {
+        let start = end.saturating_sub(chunk_size);
+        let chunk = &mut chunk[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        for (i, &byte) in chunk.iter().enumerate().rev() {
+            let pos = start + i as u64;
+            // A newline at the very end finishes the last line instead of starting one
+            if byte == b'\n' && pos + 1 != len {
+                newlines += 1;
+                if newlines == lines {
+                    return Ok(pos + 1);
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// The files in a directory, in the order they were written.
+///
+/// Files are grouped by name with any rotation suffix removed, so several
+/// rotated logs in one directory are each read in order rather than interleaved.
+fn rotated_log_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || !entry.file_type()?.is_file() {
+            continue;
+        }
+        let (base, rotation) = rotation_sort_key(&name);
+        files.push(((base.to_owned(), rotation), entry.path()));
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// How a log file was rotated, according to its name.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Rotation {
+    /// `app.log.3`, as logrotate does it: bigger numbers are older
+    Numbered(std::cmp::Reverse<u64>),
+    /// `app.log.2024-01-01` (or with `-HH` or `-HH-MM`), as tracing-appender does it
+    Dated(String),
+    /// `app.log`, the one currently being written to
+    Current,
+}
+
+/// A key to sort rotated log files chronologically: the base name, and the rotation.
+fn rotation_sort_key(name: &str) -> (&str, Rotation) {
+    if let Some((base, suffix)) = name.rsplit_once('.') {
+        if let Ok(number) = suffix.parse() {
+            return (base, Rotation::Numbered(std::cmp::Reverse(number)));
+        }
+        let is_date = suffix.len() >= "YYYY-MM-DD".len()
+            && suffix.starts_with(|c: char| c.is_ascii_digit())
+            && suffix.chars().all(|c| c.is_ascii_digit() || c == '-');
+        if is_date {
+            return (base, Rotation::Dated(suffix.to_owned()));
+        }
+    }
+    (name, Rotation::Current)
+}
+
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.poll_processor_state();
+        for viewer in &mut self.viewers {
+            viewer.poll_processor_state(&self.settings);
+            if matches!(
+                viewer.cur_status,
+                ProcessorStatus::Done | ProcessorStatus::Following
+            ) {
+                if let Some(message_id) = viewer.pending_goto.take() {
+                    viewer.goto_message(message_id);
+                    self.tab = Tab::TreeLogs;
+                }
+                if std::mem::take(&mut viewer.pending_jump_to_end) {
+                    viewer.rows_ui.jump_to_end();
+                }
+            }
+        }
         self.update_ui(ctx);
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+    }
 }
 
 // Core State Updating
 impl App {
-    pub fn new(_cc: &CreationContext<'_>) -> Self {
+    pub fn new(cc: &CreationContext<'_>, startup: StartupOptions) -> Self {
+        let settings: Settings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+
+        #[cfg(target_arch = "wasm32")]
+        let startup = StartupOptions {
+            goto: startup.goto.or_else(|| {
+                cc.integration_info
+                    .web_info
+                    .as_ref()
+                    .and_then(|web_info| parse_goto_fragment(&web_info.location.hash))
+            }),
+            ..startup
+        };
+
+        let mut viewer = Viewer::new(&cc.egui_ctx, 0);
+        viewer.pending_goto = startup.goto;
+        let startup_tab = settings.startup_tab.unwrap_or(Tab::Settings);
+        let mut app = Self {
+            viewers: vec![viewer],
+            cur_viewer: 0,
+            next_viewer_id: 1,
+            ctx: cc.egui_ctx.clone(),
+            load_range: LoadRange::All,
+            settings,
+            tab: startup_tab,
+            hide_tabs: false,
+            focus_mode: false,
+            settings_ui: SettingsUi::default(),
+            workspaces_ui: WorkspacesUi::default(),
+        };
+
+        if let Some(path) = startup.path {
+            let idx = match app.settings.available_paths.iter().position(|p| p == &path) {
+                Some(idx) => idx,
+                None => {
+                    app.settings.available_paths.push(path);
+                    app.settings.available_paths.len() - 1
+                }
+            };
+            app.set_path(idx);
+        }
+        if let Some(tab) = startup.tab {
+            app.tab = tab;
+        }
+        app
+    }
+
+    /// The viewer the tab bar, status bar, and settings are for.
+    fn viewer(&self) -> &Viewer {
+        &self.viewers[self.cur_viewer]
+    }
+
+    fn viewer_mut(&mut self) -> &mut Viewer {
+        &mut self.viewers[self.cur_viewer]
+    }
+
+    /// Ask for a log file and open it.
+    fn pick_file(&mut self) {
+        // FIXME(WASM): this has to be made async in wasm
+        if let Some(path) = rfd::FileDialog::new().pick_file() {
+            self.settings.available_paths.push(path);
+            self.set_path(self.settings.available_paths.len() - 1);
+        }
+    }
+
+    /// Ask for a log file and open it in a new viewer, next to the others.
+    fn pick_file_beside(&mut self) {
+        // FIXME(WASM): this has to be made async in wasm
+        if let Some(path) = rfd::FileDialog::new().pick_file() {
+            self.viewers
+                .push(Viewer::new(&self.ctx, self.next_viewer_id));
+            self.next_viewer_id += 1;
+            self.cur_viewer = self.viewers.len() - 1;
+            self.settings.available_paths.push(path);
+            self.set_path(self.settings.available_paths.len() - 1);
+        }
+    }
+
+    /// Close a viewer, unless it's the last one.
+    fn close_viewer(&mut self, idx: usize) {
+        if self.viewers.len() <= 1 {
+            return;
+        }
+        // Dropping it shuts its processor down
+        self.viewers.remove(idx);
+        if self.cur_viewer >= idx && self.cur_viewer > 0 {
+            self.cur_viewer -= 1;
+        }
+    }
+
+    fn set_path(&mut self, idx: usize) {
+        let path = self.settings.available_paths[idx].clone();
+        self.settings.picked_path = Some(path.display().to_string());
+        self.open_logs(path);
+        if !self.settings.keep_tab_on_open {
+            self.tab = self.settings.opened_file_tab.unwrap_or(Tab::TreeLogs);
+        }
+    }
+
+    fn open_logs(&mut self, path: PathBuf) {
+        self.viewers[self.cur_viewer].open_logs(path, self.load_range, &self.settings.processor);
+    }
+
+    /// The tabs for the tab bar: the ones picked in the settings, plus the
+    /// current one if it isn't among them (so it doesn't seem to vanish).
+    fn shown_tabs(&self) -> Vec<Tab> {
+        let mut tabs = match &self.settings.tabs {
+            Some(tabs) => tabs.clone(),
+            None => Tab::DEFAULT_SHOWN.to_vec(),
+        };
+        if !tabs.contains(&self.tab) {
+            tabs.push(self.tab);
+        }
+        tabs
+    }
+}
+
+impl Settings {
+    /// The built-in field presets followed by the user's.
+    fn field_presets(&self) -> Vec<FieldPreset> {
+        let mut presets = FieldPreset::builtin();
+        presets.extend(self.field_presets.iter().cloned());
+        presets
+    }
+}
+
+/// Pick which fields are shown with a preset, or "custom" if none match.
+fn ui_field_preset_picker(ui: &mut egui::Ui, settings: &mut Settings) {
+    let presets = settings.field_presets();
+    let shown_keys = &mut settings.display.shown_keys;
+    let current = presets
+        .iter()
+        .find(|preset| preset.shown_keys == *shown_keys)
+        .map_or("custom", |preset| &preset.name);
+    egui::ComboBox::from_id_source("field_preset")
+        .selected_text(current)
+        .show_ui(ui, |ui| {
+            for preset in &presets {
+                let selected = preset.shown_keys == *shown_keys;
+                if ui.selectable_label(selected, &preset.name).clicked() {
+                    *shown_keys = preset.shown_keys.clone();
+                }
+            }
+        });
+}
+
+impl Viewer {
+    fn new(ctx: &egui::Context, id: usize) -> Self {
         let logs = Logs::new();
         let task_sender = ProcessorTaskSender::default();
         let task_receiver = task_sender.clone();
-        let status_sender = ProcessorStatusSender::default();
-        let status_receiver = status_sender.clone();
+        let (status_sender, status_receiver) = mpsc::sync_channel(STATUS_CHANNEL_BOUND);
         let logs_handle = logs.clone();
+        let ctx = ctx.clone();
 
         // FIXME(WASM): this doesn't work in wasm, move to async?
-        let _processor_thread = std::thread::spawn(move || {
-            run_processor(task_receiver, status_sender, logs_handle);
+        let processor_thread = std::thread::spawn(move || {
+            let processor = Processor {
+                task_receiver,
+                status_sender,
+                logs: logs_handle,
+                ctx,
+            };
+            processor.run();
         });
 
         Self {
-            _processor_thread,
+            id,
+            processor_thread: Some(processor_thread),
             logs,
             cur_status: ProcessorStatus::NotStarted,
-            settings: Settings {
-                available_paths: Vec::new(),
-                picked_path: None,
-            },
-            tab: Tab::Settings,
+            cur_throughput: Throughput::default(),
+            loaded_path: None,
+            loaded_range: LoadRange::All,
+            filter: Filter::default(),
+            filter_ui: FilterUi::default(),
+            pending_goto: None,
+            pending_jump_to_end: false,
+            pending_land_on: false,
+            reload_baseline: None,
+            reload_changes: None,
+            memory_estimate: None,
+            show_parse_errors: false,
             linear_logs_ui: LinearLogsUi::default(),
+            rows_ui: RowsUi::default(),
+            error_spans: None,
             tree_logs_ui: TreeLogsUi::default(),
-            settings_ui: SettingsUi::default(),
             task_sender,
             status_receiver,
         }
     }
-    fn poll_processor_state(&mut self) {
+
+    fn poll_processor_state(&mut self, settings: &Settings) {
         // Fetch updates from processing thread
-        self.cur_status = *self.status_receiver.lock().unwrap();
+        while let Ok((status, throughput)) = self.status_receiver.try_recv() {
+            self.cur_throughput = throughput;
+            match &status {
+                ProcessorStatus::Done | ProcessorStatus::Following => {
+                    if let Some(before) = self.reload_baseline.take() {
+                        let after = self.logs.level_counts();
+                        self.reload_changes = Some(ReloadChanges::new(before, after));
+                    }
+                    // A --goto says where to go well enough
+                    if std::mem::take(&mut self.pending_land_on) && self.pending_goto.is_none() {
+                        self.land_on_span(settings);
+                    }
+                }
+                // These were meant for the file that didn't load, not whatever comes next
+                ProcessorStatus::IoFailed
+                | ProcessorStatus::Cancelled
+                | ProcessorStatus::Crashed(_) => {
+                    self.reload_baseline = None;
+                    self.pending_goto = None;
+                    self.pending_land_on = false;
+                    self.forget_missing_spans();
+                }
+                ProcessorStatus::NotStarted
+                | ProcessorStatus::Reading
+                | ProcessorStatus::Paused { .. } => {}
+            }
+            self.cur_status = status;
+        }
     }
 
-    fn set_path(&mut self, idx: usize) {
-        let path = self.settings.available_paths[idx].clone();
-        self.settings.picked_path = Some(path.display().to_string());
-        let (lock, condvar) = &*self.task_sender;
-        let mut new_task = lock.lock().unwrap();
-        *new_task = Some(ProcessorTask::OpenLogs(path));
-        self.tab = Tab::TreeLogs;
-        condvar.notify_one();
+    /// Stop pointing at spans that aren't there, like after loading was
+    /// cancelled and what was read got thrown away.
+    fn forget_missing_spans(&mut self) {
+        let logs = self.logs.inner.lock().unwrap();
+        let exists = |span: &SpanId| logs.spans.contains_key(span);
+        if !self.tree_logs_ui.cur_span.as_ref().is_none_or(exists) {
+            self.tree_logs_ui.cur_span = None;
+        }
+        if !self.linear_logs_ui.cur_span.as_ref().is_none_or(exists) {
+            self.linear_logs_ui.cur_span = None;
+        }
+        self.tree_logs_ui.focus.retain(exists);
+    }
+
+    /// What to call the viewer: the name of the file it has open.
+    fn title(&self) -> String {
+        match &self.loaded_path {
+            Some(path) => path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+            None => "no logs".to_owned(),
+        }
+    }
+
+    fn reload(&mut self, options: &ProcessorSettings) {
+        if let Some(path) = self.loaded_path.clone() {
+            // Counted before the processor gets a chance to clear the logs
+            let before = self.logs.level_counts();
+            self.open_logs_range(path, self.loaded_range, options);
+            self.reload_baseline = Some(before);
+            self.pending_land_on = false;
+        }
+    }
+
+    /// Reload, and scroll to the newest messages once that's done.
+    fn reload_to_end(&mut self, options: &ProcessorSettings) {
+        if self.loaded_path.is_some() {
+            self.reload(options);
+            self.pending_jump_to_end = true;
+        }
+    }
+
+    fn open_logs(&mut self, path: PathBuf, range: LoadRange, options: &ProcessorSettings) {
+        // A reload should keep your place, but a new file starts from scratch
+        self.tree_logs_ui.cur_span = None;
+        self.tree_logs_ui.focus.clear();
+        self.open_logs_range(path, range, options);
+    }
+
+    fn open_logs_range(&mut self, path: PathBuf, range: LoadRange, options: &ProcessorSettings) {
+        self.loaded_path = Some(path.clone());
+        self.reload_baseline = None;
+        self.reload_changes = None;
+        self.pending_land_on = true;
+        self.pending_jump_to_end = false;
+        self.rows_ui.collapse_all();
+        let options = options.clone();
+        let task = if path.is_dir() {
+            // Ranges don't mean much across a bunch of files
+            self.loaded_range = LoadRange::All;
+            ProcessorTask::OpenDir(path, options)
+        } else {
+            self.loaded_range = range;
+            ProcessorTask::OpenLogs(path, range, options)
+        };
+        self.send_task(task);
+    }
+
+    /// What a tab is showing.
+    fn current_query(&self, tab: Tab, settings: &Settings) -> Query {
+        match tab {
+            Tab::Settings => Query::All,
+            Tab::LinearLogs => self.linear_logs_ui.cur_span.map_or(Query::All, Query::Span),
+            Tab::TreeLogs => {
+                let ui_state = &self.tree_logs_ui;
+                match ui_state.cur_span {
+                    Some(span) if settings.merge_identical_spans => Query::Merged(span),
+                    Some(span) if ui_state.flat => Query::Flat(span),
+                    Some(span) => Query::Span(span),
+                    None => {
+                        let root = ui_state.focus_root();
+                        match (root, ui_state.flat) {
+                            (Some(span), true) => Query::Flat(span),
+                            (Some(span), false) => Query::Span(span),
+                            (None, true) => Query::Flat(self.logs.inner.lock().unwrap().root_span),
+                            (None, false) => Query::All,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Scroll to and highlight a message in the tree view.
+    fn goto_message(&mut self, message_id: MessageId) {
+        self.tree_logs_ui.cur_span = None;
+        self.tree_logs_ui.focus.clear();
+        self.rows_ui.jump_to(LineKind::Message(message_id));
+    }
+
+    /// Destructively prune the logs down to what the current filter matches.
+    fn retain_matching(&mut self, display: &DisplayOptions) {
+        self.logs.retain_matching(&self.filter, display);
+        self.filter = Filter::default();
+        self.filter_ui = FilterUi::default();
+        self.tree_logs_ui.cur_span = None;
+        self.tree_logs_ui.focus.clear();
+        self.linear_logs_ui.cur_span = None;
     }
 
     fn cancel_processing(&mut self) {
+        self.send_task(ProcessorTask::Cancel);
+    }
+
+    /// Pause or resume adding new lines while following a file.
+    fn toggle_follow_paused(&mut self) {
+        let task = match self.cur_status {
+            ProcessorStatus::Paused { .. } => ProcessorTask::Resume,
+            _ => ProcessorTask::Pause,
+        };
+        self.send_task(task);
+    }
+
+    fn send_task(&mut self, task: ProcessorTask) {
         let (lock, condvar) = &*self.task_sender;
         let mut new_task = lock.lock().unwrap();
-        *new_task = Some(ProcessorTask::Cancel);
+        *new_task = Some(task);
         condvar.notify_one();
     }
 }
 
+impl Drop for Viewer {
+    fn drop(&mut self) {
+        self.send_task(ProcessorTask::Shutdown);
+        // Hang up so the processor can't get stuck waiting for us to read its status
+        let (_, hung_up) = mpsc::sync_channel(0);
+        drop(std::mem::replace(&mut self.status_receiver, hung_up));
+        if let Some(processor_thread) = self.processor_thread.take() {
+            // Any in-progress read will notice the new task at its next checkin
+            if processor_thread.join().is_err() {
+                eprintln!("WARN: log processor thread panicked");
+            }
+        }
+    }
+}
+
 impl App {
     fn update_ui(&mut self, ctx: &egui::Context) {
-        egui::TopBottomPanel::top("tabs").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.selectable_value(&mut self.tab, Tab::Settings, "settings");
-                ui.selectable_value(&mut self.tab, Tab::LinearLogs, "linear logs");
-                ui.selectable_value(&mut self.tab, Tab::TreeLogs, "tree logs");
+        // Plain R, as long as it isn't being typed into something
+        let reload_to_end = ctx.memory().focus().is_none()
+            && ctx.input().key_pressed(egui::Key::R)
+            && !ctx.input().modifiers.any();
+        if reload_to_end {
+            self.viewers[self.cur_viewer].reload_to_end(&self.settings.processor);
+        }
+        let (toggle_tabs, toggle_focus, leave_focus, picked_tab) = {
+            let input = ctx.input();
+            // `command` is Cmd on macOS and Ctrl everywhere else
+            let command = input.modifiers.command;
+            let shown_tabs = self.shown_tabs();
+            let toggle_tabs = command && input.modifiers.shift && input.key_pressed(egui::Key::H);
+            let toggle_focus = command && input.modifiers.shift && input.key_pressed(egui::Key::F);
+            let leave_focus = input.key_pressed(egui::Key::Escape);
+            // Ctrl+1/2/3 picks the tab in that position
+            let picked_tab = [egui::Key::Num1, egui::Key::Num2, egui::Key::Num3]
+                .into_iter()
+                .zip(shown_tabs)
+                .find(|(key, _)| command && input.key_pressed(*key))
+                .map(|(_, tab)| tab);
+            (toggle_tabs, toggle_focus, leave_focus, picked_tab)
+        };
+        if toggle_tabs {
+            self.hide_tabs = !self.hide_tabs;
+        }
+        if toggle_focus {
+            self.focus_mode = !self.focus_mode;
+        } else if leave_focus {
+            self.focus_mode = false;
+        }
+        if let Some(tab) = picked_tab {
+            self.tab = tab;
+        }
+        let tab = self.tab;
+        if self.focus_mode && tab != Tab::Settings {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.weak(format!(
+                    "focus mode (Escape or {}+Shift+F to leave)",
+                    COMMAND
+                ));
+                self.ui_viewers(ui, |viewer, ui, settings, _| {
+                    let query = viewer.current_query(tab, settings);
+                    viewer.ui_logs_rows(ui, query, settings);
+                });
             });
-        });
-        egui::CentralPanel::default().show(ctx, |ui| match self.tab {
+            return;
+        }
+        if !self.hide_tabs {
+            egui::TopBottomPanel::top("tabs").show(ctx, |ui| self.ui_tabs(ui));
+        }
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| self.viewer_mut().ui_status_bar(ui));
+        if self.viewer().show_parse_errors {
+            egui::TopBottomPanel::bottom("parse_errors")
+                .resizable(true)
+                .default_height(150.0)
+                .show(ctx, |ui| self.viewer_mut().ui_parse_errors(ui));
+        }
+        egui::CentralPanel::default().show(ctx, |ui| match tab {
             Tab::Settings => self.ui_settings(ui, ctx),
-            Tab::LinearLogs => self.ui_logs_linear(ui, ctx),
-            Tab::TreeLogs => self.ui_logs_tree(ui, ctx),
+            Tab::LinearLogs => self.ui_viewers(ui, |viewer, ui, settings, focus_mode| {
+                viewer.ui_logs_linear(ui, ctx, settings, focus_mode)
+            }),
+            Tab::TreeLogs => self.ui_viewers(ui, |viewer, ui, settings, focus_mode| {
+                viewer.ui_logs_tree(ui, ctx, settings, focus_mode)
+            }),
+        });
+    }
+
+    /// Show each viewer with `show`, side by side with a header to pick or
+    /// close each one (or just the one, if that's all there is).
+    fn ui_viewers(
+        &mut self,
+        ui: &mut egui::Ui,
+        mut show: impl FnMut(&mut Viewer, &mut egui::Ui, &mut Settings, &mut bool),
+    ) {
+        let Self {
+            viewers,
+            cur_viewer,
+            settings,
+            focus_mode,
+            ..
+        } = self;
+        if let [viewer] = &mut viewers[..] {
+            show(viewer, ui, settings, focus_mode);
+            return;
+        }
+        let mut closed = None;
+        ui.columns(viewers.len(), |columns| {
+            for (i, (ui, viewer)) in columns.iter_mut().zip(viewers.iter_mut()).enumerate() {
+                ui.push_id(viewer.id, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .selectable_label(i == *cur_viewer, viewer.title())
+                            .on_hover_text(
+                                "make this the one the tab bar, status bar, and settings are for",
+                            )
+                            .clicked()
+                        {
+                            *cur_viewer = i;
+                        }
+                        if ui.small_button("❌").on_hover_text("close").clicked() {
+                            closed = Some(i);
+                        }
+                    });
+                    ui.separator();
+                    show(viewer, ui, settings, focus_mode);
+                });
+            }
+        });
+        if let Some(i) = closed {
+            self.close_viewer(i);
+        }
+    }
+
+    fn ui_tabs(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let compact = self.settings.compact_tabs;
+            for (i, tab) in self.shown_tabs().into_iter().enumerate() {
+                let shortcut = format!("{}+{}", COMMAND, i + 1);
+                if compact {
+                    ui.selectable_value(&mut self.tab, tab, tab.icon())
+                        .on_hover_text(format!("{} ({})", tab.name(), shortcut));
+                } else {
+                    ui.selectable_value(&mut self.tab, tab, tab.name())
+                        .on_hover_text(shortcut);
+                }
+            }
+            ui.separator();
+            let open_label = if compact { "📂" } else { "📂 open" };
+            if ui
+                .button(open_label)
+                .on_hover_text("open another log file")
+                .clicked()
+            {
+                self.pick_file();
+            }
+            let beside_label = if compact { "◫" } else { "◫ open beside" };
+            if ui
+                .button(beside_label)
+                .on_hover_text("open another log file next to this one, to compare them")
+                .clicked()
+            {
+                self.pick_file_beside();
+            }
+            self.ui_workspaces(ui);
+            let viewer = &mut self.viewers[self.cur_viewer];
+            let parse_errors = viewer.logs.inner.lock().unwrap().parse_errors;
+            if parse_errors > 0 {
+                ui.separator();
+                ui.toggle_value(
+                    &mut viewer.show_parse_errors,
+                    format!("⚠ {} parse errors", parse_errors),
+                )
+                .on_hover_text("show the lines that couldn't be read");
+            }
+            ui.with_layout(egui::Layout::right_to_left(), |ui| {
+                let (icon, hover) = if compact {
+                    ("⏵", "show tab names")
+                } else {
+                    ("⏴", "only show tab icons")
+                };
+                if ui
+                    .small_button(icon)
+                    .on_hover_text(format!("{} ({}+Shift+H hides the tab bar)", hover, COMMAND))
+                    .clicked()
+                {
+                    self.settings.compact_tabs = !compact;
+                }
+            });
         });
     }
 }
@@ -219,5 +1878,584 @@ pub fn start(canvas_id: &str) -> Result<(), wasm_bindgen::JsValue> {
     tracing_wasm::set_as_global_default();
 
     // let web_options = eframe::WebOptions::default();
-    eframe::start_web(canvas_id, Box::new(|cc| Box::new(App::new(cc))))
+    eframe::start_web(
+        canvas_id,
+        Box::new(|cc| Box::new(App::new(cc, StartupOptions::default()))),
+    )
+}
+
+/// Permalinks on the web look like `index.html#message-123` (or just `#123`).
+#[cfg(target_arch = "wasm32")]
+fn parse_goto_fragment(hash: &str) -> Option<MessageId> {
+    let hash = hash.trim_start_matches('#');
+    hash.strip_prefix("message-").unwrap_or(hash).parse().ok()
+}
+
+#[test]
+fn test_byte_order_mark() {
+    // Saved with a BOM, and then another file saved with one appended to it
+    let line = r#"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"hi"},"target":"fmt_json"}"#;
+    let input = format!("\u{FEFF}{line}\n{line}\n\u{FEFF}  {line}\n");
+
+    let processor = Processor {
+        task_receiver: ProcessorTaskReceiver::default(),
+        status_sender: mpsc::sync_channel(STATUS_CHANNEL_BOUND).0,
+        logs: Logs::new(),
+        ctx: egui::Context::default(),
+    };
+    let source = ReadSource {
+        file: Arc::from(Path::new("test.log")),
+        first_line: Some(1),
+    };
+    let status = processor.read_lines(
+        &mut std::io::Cursor::new(input),
+        &source,
+        ReadLimits::default(),
+        &ProcessorSettings::default(),
+        &mut ThroughputTracker::new(None),
+        None,
+    );
+    assert_eq!(status, ProcessorStatus::Done);
+    let logs = processor.logs.inner.lock().unwrap();
+    assert_eq!(logs.parse_errors, 0);
+    assert_eq!(logs.messages.len(), 3);
+}
+
+#[test]
+fn test_rotation_sort_key() {
+    let mut names = vec![
+        "app.log",
+        "app.log.2024-01-10",
+        "app.log.2024-01-02",
+        "other.log.1",
+        "app.log.2023-12-31-23",
+        "other.log",
+        "other.log.10",
+        "other.log.2",
+    ];
+    names.sort_by_key(|name| rotation_sort_key(name));
+    assert_eq!(
+        names,
+        [
+            "app.log.2023-12-31-23",
+            "app.log.2024-01-02",
+            "app.log.2024-01-10",
+            "app.log",
+            "other.log.10",
+            "other.log.2",
+            "other.log.1",
+            "other.log",
+        ]
+    );
+}
+
+#[test]
+fn test_status_updates() {
+    let task_sender = ProcessorTaskSender::default();
+    let (status_sender, status_receiver) = mpsc::sync_channel(STATUS_CHANNEL_BOUND);
+    let processor = Processor {
+        task_receiver: task_sender.clone(),
+        status_sender,
+        logs: Logs::new(),
+        ctx: egui::Context::default(),
+    };
+    let recv = || {
+        status_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no status update")
+    };
+
+    // Progress gets reported at every checkin
+    let input = r#"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"one"},"target":"fmt_json"}
+{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"two"},"target":"fmt_json"}
+"#;
+    let mut throughput = ThroughputTracker::new(None);
+    let options = ProcessorSettings {
+        checkin_interval_ms: 0,
+        ..ProcessorSettings::default()
+    };
+    let source = ReadSource {
+        file: Arc::from(Path::new("test.log")),
+        first_line: Some(1),
+    };
+    let status = processor.read_lines(
+        &mut std::io::Cursor::new(input),
+        &source,
+        ReadLimits::default(),
+        &options,
+        &mut throughput,
+        None,
+    );
+    assert_eq!(status, ProcessorStatus::Done);
+    let (status, throughput) = recv();
+    assert_eq!(status, ProcessorStatus::Reading);
+    assert_eq!(throughput.lines, 1);
+    assert_eq!(recv().1.lines, 2);
+    assert_eq!(processor.logs.num_messages(), 2);
+
+    // Tasks report their final status, even when they fail
+    let processor_thread = std::thread::spawn(move || processor.run());
+    let send_task = |task| {
+        let (lock, condvar) = &*task_sender;
+        *lock.lock().unwrap() = Some(task);
+        condvar.notify_one();
+    };
+    send_task(ProcessorTask::OpenLogs(
+        PathBuf::from("this/file/does/not/exist.json"),
+        LoadRange::All,
+        ProcessorSettings::default(),
+    ));
+    assert_eq!(recv().0, ProcessorStatus::Reading);
+    assert_eq!(recv().0, ProcessorStatus::IoFailed);
+
+    send_task(ProcessorTask::Shutdown);
+    processor_thread.join().unwrap();
+}
+
+#[test]
+fn test_blank_line_records() {
+    let input = r#"{
+  "timestamp": "2022-02-15T18:47:10.821315Z",
+  "level": "INFO",
+  "fields": {
+    "message": "one"
+  },
+  "target": "fmt_json"
+}
+
+{
+  "timestamp": "2022-02-15T18:47:10.821315Z",
+  "level": "WARN",
+  "fields": {
+    "message": "two"
+  },
+  "target": "fmt_json"
+}
+
+not json"#;
+    let processor = Processor {
+        task_receiver: ProcessorTaskReceiver::default(),
+        status_sender: mpsc::sync_channel(STATUS_CHANNEL_BOUND).0,
+        logs: Logs::new(),
+        ctx: egui::Context::default(),
+    };
+    let options = ProcessorSettings {
+        blank_line_records: true,
+        ..ProcessorSettings::default()
+    };
+    let source = ReadSource {
+        file: Arc::from(Path::new("test.log")),
+        first_line: Some(101),
+    };
+    let status = processor.read_lines(
+        &mut std::io::Cursor::new(input),
+        &source,
+        ReadLimits::default(),
+        &options,
+        &mut ThroughputTracker::new(None),
+        None,
+    );
+    assert_eq!(status, ProcessorStatus::Done);
+    let logs = processor.logs.inner.lock().unwrap();
+    // Parse errors say where the record started, counting from where reading did
+    assert_eq!(logs.bad_lines.len(), 1);
+    assert_eq!(
+        logs.bad_lines[0].file.as_deref(),
+        Some(Path::new("test.log"))
+    );
+    assert_eq!(logs.bad_lines[0].line, Some(119));
+    let levels = logs
+        .messages
+        .values()
+        .map(|message| message.level)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        levels,
+        [Some(tracing::Level::INFO), Some(tracing::Level::WARN)]
+    );
+}
+
+#[test]
+fn test_tail_start() {
+    let text = "one\ntwo\nthree\n";
+    for chunk_size in [1, 3, 64] {
+        let mut file = std::io::Cursor::new(text);
+        let mut tail = |lines| {
+            let start = tail_start(&mut file, lines, chunk_size).unwrap();
+            &text[start as usize..]
+        };
+        assert_eq!(tail(0), "");
+        assert_eq!(tail(1), "three\n");
+        assert_eq!(tail(2), "two\nthree\n");
+        assert_eq!(tail(3), text);
+        assert_eq!(tail(10), text);
+    }
+    // Without a newline at the end
+    let mut file = std::io::Cursor::new("one\ntwo");
+    assert_eq!(tail_start(&mut file, 1, 64).unwrap(), 4);
+}
+
+#[test]
+fn test_cancel_during_huge_lines() {
+    /// The same enormous log line, forever.
+    struct EndlessLines(Vec<u8>, usize);
+    impl Read for EndlessLines {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let rest = &self.0[self.1..];
+            let len = rest.len().min(buf.len());
+            buf[..len].copy_from_slice(&rest[..len]);
+            self.1 = (self.1 + len) % self.0.len();
+            Ok(len)
+        }
+    }
+    let line = format!(
+        r#"{{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{{"message":"{}"}},"target":"fmt_json"}}"#,
+        "yak ".repeat(250_000)
+    ) + "\n";
+
+    let processor = Arc::new(Processor {
+        task_receiver: ProcessorTaskReceiver::default(),
+        status_sender: mpsc::sync_channel(STATUS_CHANNEL_BOUND).0,
+        logs: Logs::new(),
+        ctx: egui::Context::default(),
+    });
+    let (result_sender, result_receiver) = std::sync::mpsc::channel();
+    let reader_processor = processor.clone();
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(EndlessLines(line.into_bytes(), 0));
+        let mut throughput = ThroughputTracker::new(None);
+        let options = ProcessorSettings {
+            checkin_interval_ms: 10,
+            ..ProcessorSettings::default()
+        };
+        let source = ReadSource {
+            file: Arc::from(Path::new("endless.log")),
+            first_line: Some(1),
+        };
+        let status = reader_processor.read_lines(
+            &mut reader,
+            &source,
+            ReadLimits::default(),
+            &options,
+            &mut throughput,
+            None,
+        );
+        result_sender.send((status, throughput.cur.lines)).unwrap();
+    });
+
+    std::thread::sleep(Duration::from_millis(100));
+    *processor.task_receiver.0.lock().unwrap() = Some(ProcessorTask::Cancel);
+    let (status, lines) = result_receiver
+        .recv_timeout(Duration::from_secs(5))
+        .expect("reading wasn't cancelled");
+    assert_eq!(status, ProcessorStatus::Cancelled);
+    // Far fewer lines than the old every-1000-lines checkin would have needed
+    assert!(lines < 1000, "{lines}");
+}
+
+#[test]
+fn test_invalid_utf8() {
+    let mut input = Vec::new();
+    input.extend_from_slice(br#"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"one"},"target":"fmt_json"}"#);
+    input.extend_from_slice(b"\n");
+    input.extend_from_slice(
+        br#"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"bad "#,
+    );
+    input.extend_from_slice(b"\xff\xfe");
+    input.extend_from_slice(br#" bytes"},"target":"fmt_json"}"#);
+    input.extend_from_slice(b"\n");
+    input.extend_from_slice(br#"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"WARN","fields":{"message":"three"},"target":"fmt_json"}"#);
+    input.extend_from_slice(b"\n");
+
+    let processor = Processor {
+        task_receiver: ProcessorTaskReceiver::default(),
+        status_sender: mpsc::sync_channel(STATUS_CHANNEL_BOUND).0,
+        logs: Logs::new(),
+        ctx: egui::Context::default(),
+    };
+    let source = ReadSource {
+        file: Arc::from(Path::new("test.log")),
+        first_line: Some(1),
+    };
+    let mut throughput = ThroughputTracker::new(None);
+    let status = processor.read_lines(
+        &mut std::io::Cursor::new(input),
+        &source,
+        ReadLimits::default(),
+        &ProcessorSettings::default(),
+        &mut throughput,
+        None,
+    );
+    assert_eq!(status, ProcessorStatus::Done);
+    assert_eq!(throughput.cur.lines, 3);
+    let logs = processor.logs.inner.lock().unwrap();
+    let messages = logs
+        .messages
+        .values()
+        .map(|message| match &message.fields.vals[0].1 {
+            logs::IValue::S(s) => s.to_string(),
+            v => panic!("unexpected message {:?}", v),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(messages, ["one", "bad \u{FFFD}\u{FFFD} bytes", "three"]);
+}
+
+#[test]
+fn test_read_error() {
+    /// A file that breaks after the first line.
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk fell off"))
+        }
+    }
+    let input = r#"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"one"},"target":"fmt_json"}
+"#;
+
+    let processor = Processor {
+        task_receiver: ProcessorTaskReceiver::default(),
+        status_sender: mpsc::sync_channel(STATUS_CHANNEL_BOUND).0,
+        logs: Logs::new(),
+        ctx: egui::Context::default(),
+    };
+    let source = ReadSource {
+        file: Arc::from(Path::new("test.log")),
+        first_line: Some(1),
+    };
+    let status = processor.read_lines(
+        &mut BufReader::new(std::io::Cursor::new(input).chain(Broken)),
+        &source,
+        ReadLimits::default(),
+        &ProcessorSettings::default(),
+        &mut ThroughputTracker::new(None),
+        None,
+    );
+    // What was read before it broke stays, but it isn't reported as done
+    assert_eq!(status, ProcessorStatus::IoFailed);
+    assert_eq!(processor.logs.num_messages(), 1);
+}
+
+#[test]
+fn test_follow_pause() {
+    use std::io::Write;
+
+    let line = |message: &str| {
+        format!(
+            r#"{{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{{"message":"{}"}},"target":"fmt_json"}}"#,
+            message
+        ) + "\n"
+    };
+    let path = std::env::temp_dir().join(format!("tracing-gui-follow-{}.json", std::process::id()));
+    let mut file = File::create(&path).unwrap();
+    file.write_all(line("one").as_bytes()).unwrap();
+    // A line that's still being written when the file is opened
+    file.write_all(&line("two").as_bytes()[..20]).unwrap();
+
+    let task_sender = ProcessorTaskSender::default();
+    let (status_sender, status_receiver) = mpsc::sync_channel(STATUS_CHANNEL_BOUND);
+    let logs = Logs::new();
+    let processor = Processor {
+        task_receiver: task_sender.clone(),
+        status_sender,
+        logs: logs.clone(),
+        ctx: egui::Context::default(),
+    };
+    let processor_thread = std::thread::spawn(move || processor.run());
+    let send_task = |task| {
+        let (lock, condvar) = &*task_sender;
+        *lock.lock().unwrap() = Some(task);
+        condvar.notify_one();
+    };
+    // The next status that isn't just progress
+    let recv = || loop {
+        let (status, _) = status_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no status update");
+        if status != ProcessorStatus::Reading {
+            return status;
+        }
+    };
+
+    let options = ProcessorSettings {
+        follow: true,
+        ..ProcessorSettings::default()
+    };
+    send_task(ProcessorTask::OpenLogs(
+        path.clone(),
+        LoadRange::All,
+        options,
+    ));
+    assert_eq!(recv(), ProcessorStatus::Following);
+    assert_eq!(logs.num_messages(), 1);
+
+    // New lines show up as they're written, but not half-written ones
+    file.write_all(&line("two").as_bytes()[20..]).unwrap();
+    file.write_all(&line("three").as_bytes()[..20]).unwrap();
+    assert_eq!(recv(), ProcessorStatus::Following);
+    assert_eq!(logs.num_messages(), 2);
+    assert_eq!(logs.inner.lock().unwrap().parse_errors, 0);
+
+    // Pausing holds on to new lines until resumed
+    send_task(ProcessorTask::Pause);
+    let paused = |new_lines| ProcessorStatus::Paused {
+        new_lines,
+        discarding: false,
+    };
+    assert_eq!(recv(), paused(0));
+    file.write_all(&line("three").as_bytes()[20..]).unwrap();
+    assert_eq!(recv(), paused(1));
+    assert_eq!(logs.num_messages(), 2);
+    send_task(ProcessorTask::Resume);
+    assert_eq!(recv(), ProcessorStatus::Following);
+    assert_eq!(logs.num_messages(), 3);
+
+    // Cancelling stops following but keeps everything
+    send_task(ProcessorTask::Cancel);
+    assert_eq!(recv(), ProcessorStatus::Done);
+    assert_eq!(logs.num_messages(), 3);
+
+    send_task(ProcessorTask::Shutdown);
+    processor_thread.join().unwrap();
+    drop(file);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_length_prefixed_records() {
+    let records = [
+        r#"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"one"},"target":"fmt_json"}"#,
+        // Newlines inside a record don't split it
+        "{\"timestamp\":\"2022-02-15T18:47:10.821315Z\",\"level\":\"WARN\",\n\"fields\":{\"message\":\"two\"},\"target\":\"fmt_json\",\"spans\":[{\"yak\":1,\"name\":\"shave\"}]}",
+    ];
+    // Enough to need a second byte of varint
+    assert!(records[1].len() >= 128);
+    let frame = |framing: Framing, record: &str| {
+        let len = record.len() as u32;
+        let mut framed = match framing {
+            Framing::Varint => {
+                let mut varint = Vec::new();
+                let mut rest = len;
+                while rest >= 0x80 {
+                    varint.push((rest & 0x7f) as u8 | 0x80);
+                    rest >>= 7;
+                }
+                varint.push(rest as u8);
+                varint
+            }
+            Framing::U32Be => len.to_be_bytes().to_vec(),
+            Framing::U32Le => len.to_le_bytes().to_vec(),
+            Framing::Lines => unreachable!(),
+        };
+        framed.extend_from_slice(record.as_bytes());
+        framed
+    };
+
+    for framing in [Framing::Varint, Framing::U32Be, Framing::U32Le] {
+        let processor = Processor {
+            task_receiver: ProcessorTaskReceiver::default(),
+            status_sender: mpsc::sync_channel(STATUS_CHANNEL_BOUND).0,
+            logs: Logs::new(),
+            ctx: egui::Context::default(),
+        };
+        let options = ProcessorSettings {
+            framing,
+            ..ProcessorSettings::default()
+        };
+        let mut input = records
+            .iter()
+            .flat_map(|record| frame(framing, record))
+            .collect::<Vec<u8>>();
+        // A record that got cut off is dropped, but not the ones before it
+        input.extend_from_slice(&frame(framing, records[0])[..20]);
+        let source = ReadSource {
+            file: Arc::from(Path::new("test.bin")),
+            first_line: Some(1),
+        };
+        let mut throughput = ThroughputTracker::new(None);
+        let status = processor.read_records(
+            &mut std::io::Cursor::new(input),
+            &source,
+            ReadLimits::default(),
+            &options,
+            &mut throughput,
+            None,
+        );
+        assert_eq!(status, ProcessorStatus::Done);
+        assert_eq!(throughput.cur.lines, 2);
+        let logs = processor.logs.inner.lock().unwrap();
+        assert_eq!(logs.parse_errors, 0);
+        let levels = logs
+            .messages
+            .values()
+            .map(|message| message.level)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            levels,
+            [Some(tracing::Level::INFO), Some(tracing::Level::WARN)]
+        );
+    }
+}
+
+#[test]
+fn test_cancel_mid_load() {
+    use std::io::{BufWriter, Write};
+
+    const LINES: usize = 100_000;
+    let path = std::env::temp_dir().join(format!("tracing-gui-cancel-{}.json", std::process::id()));
+    let mut file = BufWriter::new(File::create(&path).unwrap());
+    for i in 0..LINES {
+        writeln!(
+            file,
+            r#"{{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{{"message":"{}"}},"target":"fmt_json","spans":[{{"name":"shave"}}]}}"#,
+            i
+        )
+        .unwrap();
+    }
+    drop(file);
+
+    for clear_on_cancel in [false, true] {
+        let (status_sender, status_receiver) = mpsc::sync_channel(STATUS_CHANNEL_BOUND);
+        let processor = Processor {
+            task_receiver: ProcessorTaskReceiver::default(),
+            status_sender,
+            logs: Logs::new(),
+            ctx: egui::Context::default(),
+        };
+        // Keep up with the updates so the processor never blocks on sending one
+        let last_status = std::thread::spawn(move || status_receiver.into_iter().last().unwrap().0);
+        // Cancel as soon as anything has been read
+        let logs = processor.logs.clone();
+        let task_receiver = processor.task_receiver.clone();
+        let canceller = std::thread::spawn(move || {
+            while logs.num_messages() == 0 {
+                std::thread::yield_now();
+            }
+            *task_receiver.0.lock().unwrap() = Some(ProcessorTask::Cancel);
+        });
+        let options = ProcessorSettings {
+            checkin_interval_ms: 0,
+            clear_on_cancel,
+            ..ProcessorSettings::default()
+        };
+        processor.open_logs(&path, LoadRange::All, &options);
+        canceller.join().unwrap();
+
+        let logs = processor.logs.clone();
+        drop(processor);
+        assert_eq!(last_status.join().unwrap(), ProcessorStatus::Cancelled);
+        let log = logs.inner.lock().unwrap();
+        if clear_on_cancel {
+            assert!(log.messages.is_empty());
+            assert_eq!(log.spans.len(), 1);
+        } else {
+            assert!(!log.messages.is_empty() && log.messages.len() < LINES);
+            // Every span that's there is whole, with its parent linked up
+            for span in log.spans.values() {
+                if let Some(parent) = span.parent {
+                    assert!(log.spans.contains_key(&parent));
+                }
+            }
+        }
+    }
+    let _ = std::fs::remove_file(&path);
 }
@@ -1,15 +1,20 @@
 use std::collections::HashSet;
 use std::fmt::Write;
 use std::{
-    collections::{BTreeMap, HashMap},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
     ops::Range,
+    path::Path,
     sync::{Arc, Mutex},
 };
 
-use chrono::{DateTime, Local, SecondsFormat};
-use serde::Deserialize;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
 use tracing::Level;
 
+use crate::highlight::Format;
+
 #[derive(Debug, Clone)]
 pub struct Logs {
     pub inner: Arc<Mutex<LogsInner>>,
@@ -25,11 +30,44 @@ pub struct LogsInner {
     pub messages: BTreeMap<MessageId, MessageEntry>,
 
     pub last_query: Option<Query>,
-    pub cur_string: Option<Arc<String>>,
+    pub last_options: DisplayOptions,
+    pub last_filter: Filter,
+    pub last_expanded: BTreeSet<MessageId>,
+    pub last_max_messages: Option<usize>,
+    pub cur_rendered: Option<Arc<Rendered>>,
 
     pub next_span_id: SpanId,
     pub next_message_id: MessageId,
 
+    /// Every key that's been seen in a message's fields
+    pub field_keys: HashSet<IString>,
+    /// The spans with each name and set of fields, wherever they are in the
+    /// tree, in the order they were created (see `Query::Merged`).
+    pub spans_by_key: HashMap<(IString, PseudoMap<IString, IValue>), Vec<SpanId>>,
+    /// Whether to intern the values of message fields. Mostly they're unique
+    /// (like the message itself), so for a file that's only going to be looked
+    /// at once it can be faster to just store them.
+    pub intern_values: bool,
+    /// How many lines failed to parse since the last `clear`.
+    pub parse_errors: usize,
+    /// The first `MAX_BAD_LINES` of those.
+    pub bad_lines: Vec<ParseError>,
+    /// Keep lines that aren't JSON as plain messages, instead of dropping them.
+    pub plain_text_fallback: bool,
+    /// The top-level key messages keep their timestamp under.
+    pub timestamp_key: String,
+    /// Whether a message's `spans` list the innermost span first, instead of
+    /// the outermost like `tracing-subscriber` does.
+    pub spans_innermost_first: bool,
+    /// The earliest and latest message timestamps.
+    pub time_range: Option<(DateTime<Local>, DateTime<Local>)>,
+    /// Stop making new spans once there are this many, so a span field with
+    /// a different value every time can't use up all the memory.
+    pub max_spans: Option<usize>,
+    /// How many messages went in an outer span than they should have since
+    /// the last `clear`, because of `max_spans`.
+    pub overflowed_messages: usize,
+
     // An interner and some interned strings
     pub interner: Interner,
     /// "message"
@@ -40,12 +78,67 @@ pub struct LogsInner {
     pub i_empty: IString,
 }
 
+/// How many `ParseError`s to keep around, since a file in the wrong format
+/// would otherwise have one for every line.
+pub const MAX_BAD_LINES: usize = 1000;
+
+/// The key `tracing-subscriber`'s JSON formatter puts timestamps under.
+pub const JSON_TIMESTAMP_KEY: &str = "timestamp";
+
+/// A line that couldn't be parsed.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    /// The file it was in, if it came from one
+    pub file: Option<Arc<Path>>,
+    /// The line number within `file`, counting from 1, if known
+    pub line: Option<u64>,
+    /// The start of the line
+    pub snippet: String,
+    pub error: String,
+}
+
+impl ParseError {
+    const SNIPPET_LEN: usize = 200;
+
+    fn new(
+        file: Option<&Arc<Path>>,
+        line: Option<u64>,
+        input: &str,
+        error: &serde_json::Error,
+    ) -> Self {
+        let mut snippet = input.chars().take(Self::SNIPPET_LEN).collect::<String>();
+        if snippet.len() < input.len() {
+            snippet.push('…');
+        }
+        Self {
+            file: file.cloned(),
+            line,
+            snippet,
+            error: error.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SpanEntry {
+    /// The span this is inside of (`None` for the root)
+    pub parent: Option<SpanId>,
     pub name: IString,
     pub fields: PseudoMap<IString, IValue>,
     pub events: Vec<EventEntry>,
     pub json_subspan_keys: HashMap<PseudoMap<IString, IValue>, SpanId>,
+    /// The earliest timestamp of any message in this span or its subspans.
+    pub start: Option<DateTime<Local>>,
+}
+
+impl SpanEntry {
+    /// How many messages are directly in this span (not counting subspans).
+    pub fn message_count(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|event| matches!(event, EventEntry::Message(_)))
+            .count()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -59,301 +152,3209 @@ pub enum EventEntry {
 pub struct MessageEntry {
     pub timestamp: Option<DateTime<Local>>,
     pub level: Option<Level>,
+    /// The level as it was written in the logs, if we didn't recognize it
+    pub raw_level: Option<IString>,
     pub fields: PseudoMap<IString, IValue>,
-    pub _target: IString,
+    pub target: IString,
+    /// Set if this is one of the events `FmtSpan` emits for a span, and not a real message
+    pub lifecycle: Option<SpanLifecycle>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum Query {
-    All,
-    Span(SpanId),
+/// A field of several messages, see `Logs::compare_messages`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldComparison {
+    pub key: String,
+    /// The value in each message, if it has this field.
+    pub values: Vec<Option<String>>,
+    /// Whether the messages don't all have the same value.
+    pub differs: bool,
 }
 
-pub fn print_indent(output: &mut String, depth: usize) {
-    write!(output, "{:indent$}", "", indent = depth * 4).unwrap();
+/// Messages that happened in the same stretch of time, see `LogsInner::time_buckets`.
+#[derive(Debug, Clone)]
+pub struct TimeBucket {
+    pub start: DateTime<Local>,
+    pub levels: LevelCounts,
+    pub messages: Vec<MessageId>,
 }
-pub fn print_val(output: &mut String, _depth: usize, val: &IValue) {
-    match val {
-        IValue::S(v) => write!(output, "{}", v).unwrap(),
-        IValue::B(v) => write!(output, "{}", v).unwrap(),
-        IValue::I(v) => write!(output, "{}", v).unwrap(),
-        IValue::F(v) => write!(output, "{}", v).unwrap(),
-    }
+
+/// How many messages there are of each level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelCounts {
+    pub error: usize,
+    pub warn: usize,
+    pub info: usize,
+    pub debug: usize,
+    pub trace: usize,
+    pub other: usize,
 }
 
-pub fn print_span_header(output: &mut String, depth: usize, span: &SpanEntry, line_break: bool) {
-    if !span.name.is_empty() {
-        print_indent(output, depth);
-        write!(output, "[{}", span.name).unwrap();
-        for (k, v) in &span.fields.vals {
-            write!(output, ", {k} = ").unwrap();
-            print_val(output, depth, v);
-        }
-        write!(output, "]").unwrap();
-        if line_break {
-            writeln!(output).unwrap();
-        }
+impl LevelCounts {
+    pub fn add(&mut self, level: Option<Level>) {
+        let count = match level {
+            Some(Level::ERROR) => &mut self.error,
+            Some(Level::WARN) => &mut self.warn,
+            Some(Level::INFO) => &mut self.info,
+            Some(Level::DEBUG) => &mut self.debug,
+            Some(Level::TRACE) => &mut self.trace,
+            None => &mut self.other,
+        };
+        *count += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.error + self.warn + self.info + self.debug + self.trace + self.other
     }
 }
 
-pub fn print_span_recursive(
-    this: &LogsInner,
-    output: &mut String,
-    depth: usize,
-    span: &SpanEntry,
-    range: Option<Range<usize>>,
-) {
-    print_span_header(output, depth, span, true);
+/// How long a `TimeBucket` is.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BucketSize {
+    Second,
+    TenSeconds,
+    #[default]
+    Minute,
+    TenMinutes,
+    Hour,
+}
 
-    let event_range = if let Some(range) = range {
-        &span.events[range]
-    } else {
-        &span.events[..]
-    };
-    for event in event_range {
-        match event {
-            EventEntry::Message(message_id) => {
-                let entry = &this.messages[message_id];
-                let message = entry
-                    .fields
-                    .vals
-                    .iter()
-                    .find(|(k, _v)| k == &this.i_message);
-                print_indent(output, depth + 1);
-                if let Some(level) = entry.level {
-                    write!(output, "[{:5}] ", level).unwrap();
-                } else {
-                    write!(output, "      ").unwrap();
-                }
-                if let Some(timestamp) = &entry.timestamp {
-                    write!(
-                        output,
-                        "[{}] ",
-                        timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
-                    )
-                    .unwrap();
-                }
-                for (k, v) in &entry.fields.vals {
-                    if k != &this.i_message {
-                        write!(output, "[{} = ", k).unwrap();
-                        print_val(output, depth, v);
-                        write!(output, "] ").unwrap();
-                    }
-                }
-                if let Some(message) = message {
-                    print_val(output, depth + 1, &message.1);
-                }
-                writeln!(output).unwrap();
-            }
-            EventEntry::Span(sub_span) => {
-                print_span_recursive(this, output, depth + 1, &this.spans[sub_span], None);
-            }
+impl BucketSize {
+    pub const ALL: [BucketSize; 5] = [
+        BucketSize::Second,
+        BucketSize::TenSeconds,
+        BucketSize::Minute,
+        BucketSize::TenMinutes,
+        BucketSize::Hour,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BucketSize::Second => "second",
+            BucketSize::TenSeconds => "10 seconds",
+            BucketSize::Minute => "minute",
+            BucketSize::TenMinutes => "10 minutes",
+            BucketSize::Hour => "hour",
         }
     }
-}
 
-impl Logs {
-    pub fn new() -> Self {
-        Self {
-            inner: Arc::new(Mutex::new(LogsInner::new())),
+    pub fn secs(self) -> i64 {
+        match self {
+            BucketSize::Second => 1,
+            BucketSize::TenSeconds => 10,
+            BucketSize::Minute => 60,
+            BucketSize::TenMinutes => 600,
+            BucketSize::Hour => 3600,
         }
     }
+}
 
-    pub fn clear(&self) {
-        let mut log = self.inner.lock().unwrap();
-        let root_span = log.root_span;
-        let mut root = log.spans.remove(&root_span).unwrap();
-        root.events.clear();
+/// How many messages are in a span, see `LogsInner::matching_counts`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+    /// Right in the span
+    pub direct: usize,
+    /// Anywhere under it, subspans included
+    pub total: usize,
+}
 
-        log.spans.clear();
-        log.messages.clear();
-        log.cur_string = None;
-        log.next_message_id = 0;
-        log.next_span_id = 1;
+/// Aggregate stats about everything under a span, see `LogsInner::span_summary`.
+#[derive(Debug, Clone, Default)]
+pub struct SpanSummary {
+    pub messages: usize,
+    /// How many of `messages` are in the span itself, rather than its subspans.
+    pub direct_messages: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub first: Option<DateTime<Local>>,
+    pub last: Option<DateTime<Local>>,
+    pub field_keys: Vec<IString>,
+    /// Stats for the fields that had numbers in them, sorted by key.
+    pub numeric_fields: Vec<FieldStats>,
+}
 
-        // TODO: probably should clear the interner but then also have to
-        // carefully reinsert the builtin strings.
+/// The range and average of a field's numeric values, see `SpanSummary`.
+#[derive(Debug, Clone)]
+pub struct FieldStats {
+    pub key: IString,
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+}
 
-        log.spans.insert(root_span, root);
+impl FieldStats {
+    fn new(key: IString) -> Self {
+        Self {
+            key,
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+        }
     }
 
-    pub fn add_json_message(&self, input: &str) {
-        self.inner.lock().unwrap().add_json_message(input);
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
     }
 
-    pub fn string_query(&self, query: Query) -> Arc<String> {
-        let mut log = self.inner.lock().unwrap();
-        if Some(query) == log.last_query {
-            if let Some(string) = &log.cur_string {
-                return string.clone();
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+impl fmt::Display for SpanSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages ({} directly), {} errors, {} warnings",
+            self.messages, self.direct_messages, self.errors, self.warnings
+        )?;
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            let elapsed = (last - first).to_std().unwrap_or_default();
+            write!(
+                f,
+                ", {} to {} ({:.3}s)",
+                first.to_rfc3339_opts(SecondsFormat::Millis, true),
+                last.to_rfc3339_opts(SecondsFormat::Millis, true),
+                elapsed.as_secs_f64()
+            )?;
+        }
+        if !self.field_keys.is_empty() {
+            write!(f, ", fields: ")?;
+            for (i, key) in self.field_keys.iter().enumerate() {
+                if i != 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", key)?;
             }
         }
-        log.last_query = Some(query);
+        Ok(())
+    }
+}
 
-        let mut output = String::new();
+/// Roughly how many bytes the loaded logs take up, see `LogsInner::memory_estimate`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// The interner's strings
+    pub strings: usize,
+    /// Messages, including their field values that weren't interned
+    pub messages: usize,
+    pub spans: usize,
+    /// The current rendering of the logs
+    pub rendered: usize,
+}
 
-        let (span_to_print, range) = match query {
-            Query::All => (&log.spans[&log.root_span], None),
-            Query::Span(span) => (&log.spans[&span], None),
-        };
+impl MemoryEstimate {
+    pub fn total(&self) -> usize {
+        self.strings + self.messages + self.spans + self.rendered
+    }
+}
 
-        print_span_recursive(&log, &mut output, 0, span_to_print, range);
+/// The span events tracing-subscriber can be configured to log (see `FmtSpan`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpanLifecycle {
+    New,
+    Enter,
+    Exit,
+    Close,
+}
 
-        let result = Arc::new(output);
-        log.cur_string = Some(result.clone());
-        result
+impl SpanLifecycle {
+    /// Recognize a span event from a message's fields.
+    ///
+    /// These are just messages like "enter", so to avoid catching real messages
+    /// that happen to say that, the only other fields allowed are the
+    /// `time.busy`/`time.idle` that "close" comes with.
+    fn from_fields(fields: &PseudoMap<&str, Value>) -> Option<Self> {
+        let mut lifecycle = None;
+        for (k, v) in &fields.vals {
+            match (*k, v) {
+                ("message", Value::S(message)) => {
+                    lifecycle = match &**message {
+                        "new" => Some(SpanLifecycle::New),
+                        "enter" => Some(SpanLifecycle::Enter),
+                        "exit" => Some(SpanLifecycle::Exit),
+                        "close" => Some(SpanLifecycle::Close),
+                        _ => return None,
+                    }
+                }
+                (k, _) if k.starts_with("time.") => {}
+                _ => return None,
+            }
+        }
+        lifecycle
     }
-}
 
-impl Default for Logs {
-    fn default() -> Self {
-        Self::new()
+    pub fn icon(self) -> &'static str {
+        match self {
+            SpanLifecycle::New => "✚",
+            SpanLifecycle::Enter => "⏵",
+            SpanLifecycle::Exit => "⏴",
+            SpanLifecycle::Close => "✖",
+        }
     }
 }
 
-impl LogsInner {
-    pub fn new() -> Self {
-        const ROOT_SPAN: SpanId = 0;
-        const JSON_MESSAGE_KEY: &str = "message";
-        const JSON_SPAN_NAME_KEY: &str = "name";
-        const ROOT_SPAN_NAME: &str = "<all spans>";
-
-        let empty = IString(Arc::from(""));
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Query {
+    All,
+    Span(SpanId),
+    /// Every span with the same name and fields as this one, one after another.
+    Merged(SpanId),
+    /// Just the messages under a span, in order, without any span headers or
+    /// indentation.
+    Flat(SpanId),
+}
 
-        let mut this = Self {
-            root_span: ROOT_SPAN,
-            spans: BTreeMap::new(),
-            messages: BTreeMap::new(),
-            last_query: None,
-            cur_string: None,
-            next_span_id: 1,
-            next_message_id: 0,
-            i_message: empty.clone(),
-            i_name: empty.clone(),
-            i_empty: empty,
-            interner: Interner::default(),
-        };
+/// The printed output of a query, along with what each line of it is.
+#[derive(Debug, Clone, Default)]
+pub struct Rendered {
+    pub text: String,
+    pub lines: Vec<RenderedLine>,
+    /// How many messages had been loaded when this was rendered.
+    pub num_messages: usize,
+    /// How many messages were printed.
+    pub printed_messages: usize,
+    /// Whether printing stopped at `PrintOptions::max_messages` with more to go.
+    pub truncated: bool,
+    /// The timestamp of the last message printed that had one, to print the
+    /// next one's `PrintOptions::time_deltas` from.
+    pub last_timestamp: Option<DateTime<Local>>,
+}
 
-        let root_span = SpanEntry {
-            name: this.interner.intern_str(ROOT_SPAN_NAME),
-            fields: PseudoMap::default(),
-            events: Vec::new(),
-            json_subspan_keys: HashMap::new(),
-        };
-        this.spans.insert(ROOT_SPAN, root_span);
+#[derive(Debug, Clone)]
+pub struct RenderedLine {
+    /// Where this line is in `Rendered::text` (without the newline).
+    pub range: Range<usize>,
+    pub kind: LineKind,
+    pub level: Option<Level>,
+    /// Where the `[LEVEL]` token is in `Rendered::text`, if this line has one.
+    pub level_token: Option<Range<usize>>,
+    /// Where the time since the last message is in `Rendered::text`, if it's
+    /// past `PrintOptions::slow_delta`.
+    pub slow_delta_token: Option<Range<usize>>,
+    /// Bookkeeping that should be drawn less prominently than real messages.
+    pub muted: bool,
+    /// The color of the first `ColorRule` the message matched.
+    pub color: Option<[u8; 3]>,
+}
 
-        this.i_message = this.interner.intern_str(JSON_MESSAGE_KEY);
-        this.i_name = this.interner.intern_str(JSON_SPAN_NAME_KEY);
-        this.i_empty = this.interner.intern_str("");
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineKind {
+    SpanHeader(SpanId),
+    Message(MessageId),
+}
 
-        this
+impl RenderedLine {
+    fn new(kind: LineKind) -> Self {
+        Self {
+            range: 0..0,
+            kind,
+            level: None,
+            level_token: None,
+            slow_delta_token: None,
+            muted: false,
+            color: None,
+        }
     }
+}
 
-    pub fn add_json_message(&mut self, input: &str) {
-        let json_message = match serde_json::from_str::<JsonMessage>(input) {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("WARN: failed to parse log line: {}\n{}", input, e);
-                return;
+impl Rendered {
+    /// Record everything printed since `start` as lines like `template`.
+    ///
+    /// Values with newlines in them can make one entry span several lines.
+    fn push_lines(&mut self, start: usize, template: RenderedLine) {
+        let mut line_start = start;
+        for line in self.text[start..].split_inclusive('\n') {
+            let line_end = line_start + line.trim_end_matches('\n').len();
+            let mut rendered = template.clone();
+            rendered.range = line_start..line_end;
+            if line_start != start {
+                rendered.level_token = None;
+                rendered.slow_delta_token = None;
             }
-        };
-        let mut cur_span_id = self.root_span;
-        for json_span in json_message.spans {
-            let cur_span = self.spans.get_mut(&cur_span_id).unwrap();
-            let i_json_span = self.interner.intern_pseudo(json_span);
-            cur_span_id = match cur_span.json_subspan_keys.entry(i_json_span) {
-                std::collections::hash_map::Entry::Occupied(e) => *e.get(),
-                std::collections::hash_map::Entry::Vacant(e) => {
-                    // Make a new span
-                    let new_span_id = self.next_span_id;
-                    self.next_span_id += 1;
+            self.lines.push(rendered);
+            line_start += line.len();
+        }
+    }
 
-                    // This is done in a weird way because if you have a span with "name" key
-                    // then tracing will emit two keys with the string "name". We assume the
-                    // one we want is first.
-                    let name = if let Some((k, IValue::S(name))) = e.key().vals.last() {
-                        if k == &self.i_name {
-                            name.clone()
-                        } else {
-                            self.i_empty.clone()
-                        }
-                    } else {
-                        self.i_empty.clone()
-                    };
-                    let mut fields = e.key().clone();
-                    if !name.is_empty() {
-                        fields.vals.pop();
-                    }
-                    let new_span = SpanEntry {
-                        name,
-                        fields,
-                        events: Vec::new(),
-                        json_subspan_keys: HashMap::new(),
-                    };
+    pub fn line_text(&self, line: &RenderedLine) -> &str {
+        &self.text[line.range.clone()]
+    }
+}
 
-                    e.insert(new_span_id);
-                    cur_span.events.push(EventEntry::Span(new_span_id));
-                    self.spans.insert(new_span_id, new_span);
+/// User-configurable knobs for how logs get printed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayOptions {
+    /// Field keys that shouldn't be printed (they're still kept in the model).
+    pub hidden_keys: Vec<String>,
+    /// If set, the only field keys that get printed (besides the message).
+    pub shown_keys: Option<Vec<String>>,
+    /// Give ERROR/WARN rows a tinted background.
+    pub shade_rows: bool,
+    pub number_format: NumberFormat,
+    /// Squash newlines and other runs of whitespace in values down to single
+    /// spaces, for strictly one line per message.
+    pub collapse_whitespace: bool,
+    pub unknown_level: UnknownLevel,
+    pub timestamp_precision: TimestampPrecision,
+    /// A `chrono` format string like `%H:%M:%S%.3f` to print timestamps with
+    /// instead. Ignored if it's empty or invalid (see `check_time_format`).
+    pub time_format: String,
+    /// Show the new/enter/exit/close events of spans alongside messages.
+    pub show_span_lifecycle: bool,
+    /// Highlight messages based on their fields, first match wins.
+    pub color_rules: Vec<ColorRule>,
+    /// Start each message with its `#MessageId`, for referring to it (see `--goto`).
+    pub show_message_ids: bool,
+    /// Fold away spans nested deeper than this below the span being shown.
+    pub max_depth: Option<usize>,
+    /// Start each message with how long it's been since the one printed before it.
+    pub show_time_deltas: bool,
+    /// Pick out time deltas longer than this many milliseconds.
+    pub slow_delta_ms: Option<u64>,
+    pub key_style: KeyStyle,
+    /// Give spans without a name a label made from their fields, instead of
+    /// leaving them out of the output (their messages are still shown).
+    pub label_nameless_spans: bool,
+    /// When showing a single span, print times relative to its first message.
+    pub span_relative_times: bool,
+    /// Print when each span started (its first message's time) in its header.
+    pub span_start_times: bool,
+    /// Print each message's target (the module it was logged from).
+    pub show_targets: bool,
+    /// Comma-separated prefixes to cut off of targets when they're shown,
+    /// like `my_long_crate_name::`.
+    pub target_prefixes: String,
+    /// Comma-separated field keys to show as the message of messages that
+    /// don't have a `message` field, first one found wins. `*` stands for
+    /// whichever field comes first.
+    pub message_fallback_keys: String,
+}
 
-                    new_span_id
-                }
-            };
+/// Color messages where a field compares a certain way against a value,
+/// e.g. `status >= 500`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorRule {
+    pub key: String,
+    pub comparison: Comparison,
+    pub value: String,
+    pub color: [u8; 3],
+}
+
+impl Default for ColorRule {
+    fn default() -> Self {
+        Self {
+            key: String::new(),
+            comparison: Comparison::Eq,
+            value: String::new(),
+            color: [255, 0, 0],
         }
+    }
+}
 
-        let span = self.spans.get_mut(&cur_span_id).unwrap();
-        let new_message_id = self.next_message_id;
-        self.next_message_id += 1;
-        let new_message = MessageEntry {
-            timestamp: json_message.timestamp.parse().ok(),
-            level: match json_message.level {
-                "ERROR" => Some(Level::ERROR),
-                "WARN" => Some(Level::WARN),
-                "INFO" => Some(Level::INFO),
-                "DEBUG" => Some(Level::DEBUG),
-                "TRACE" => Some(Level::TRACE),
-                _ => None,
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    #[default]
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+impl Comparison {
+    pub const ALL: [Comparison; 7] = [
+        Comparison::Eq,
+        Comparison::Ne,
+        Comparison::Lt,
+        Comparison::Le,
+        Comparison::Gt,
+        Comparison::Ge,
+        Comparison::Contains,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Contains => "contains",
+        }
+    }
+
+    /// Compare a field's value against a rule's value. Numbers are compared as
+    /// numbers if both sides are numbers, and everything else as text.
+    fn compare(self, val: &IValue, rule_value: &str) -> bool {
+        let rule_value = rule_value.trim();
+        let mut printed = String::new();
+        print_val(&mut printed, 0, val, NumberFormat::Plain, false);
+        if self == Comparison::Contains {
+            return printed.contains(rule_value);
+        }
+        let ordering = match (printed.parse::<f64>(), rule_value.parse::<f64>()) {
+            (Ok(lhs), Ok(rhs)) => match lhs.partial_cmp(&rhs) {
+                Some(ordering) => ordering,
+                None => return false,
             },
-            _target: self.interner.intern_str(json_message.target),
-            fields: self.interner.intern_pseudo(json_message.fields),
+            _ => (*printed).cmp(rule_value),
         };
-        self.messages.insert(new_message_id, new_message);
-        span.events.push(EventEntry::Message(new_message_id));
+        match self {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+            Comparison::Contains => unreachable!(),
+        }
     }
 }
 
-impl Default for LogsInner {
+impl Default for DisplayOptions {
     fn default() -> Self {
-        Self::new()
+        Self {
+            hidden_keys: Vec::new(),
+            shown_keys: None,
+            shade_rows: true,
+            number_format: NumberFormat::default(),
+            collapse_whitespace: false,
+            unknown_level: UnknownLevel::default(),
+            timestamp_precision: TimestampPrecision::default(),
+            time_format: String::new(),
+            show_span_lifecycle: false,
+            color_rules: Vec::new(),
+            max_depth: None,
+            show_time_deltas: false,
+            slow_delta_ms: None,
+            key_style: KeyStyle::default(),
+            label_nameless_spans: false,
+            span_relative_times: false,
+            span_start_times: false,
+            show_targets: false,
+            target_prefixes: String::new(),
+            message_fallback_keys: "msg, body, event".to_owned(),
+            show_message_ids: false,
+        }
     }
 }
 
-// A string interner that makes all strings share the same Arc,
-// so they can be compared by address and deduplicated.
-#[derive(Debug, Clone, Default)]
-pub struct Interner {
-    facts: HashMap<IString, StringInfo>,
-    strings: HashSet<Arc<str>>,
+/// What to do with messages whose level we don't recognize.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnknownLevel {
+    /// Leave the level blank
+    #[default]
+    Blank,
+    /// Show the level exactly as it was written in the logs
+    Raw,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
 }
 
-impl Interner {
-    pub fn intern_str(&mut self, val: &str) -> IString {
-        if let Some(k) = self.strings.get(val) {
-            IString(k.clone())
-        } else {
-            let num_lines = val.lines().count();
-            let k = Arc::from(val);
-            self.strings.insert(Arc::clone(&k));
-            self.facts
-                .insert(IString(Arc::clone(&k)), StringInfo { num_lines });
-            IString(k)
+impl UnknownLevel {
+    pub const ALL: [UnknownLevel; 7] = [
+        UnknownLevel::Blank,
+        UnknownLevel::Raw,
+        UnknownLevel::Error,
+        UnknownLevel::Warn,
+        UnknownLevel::Info,
+        UnknownLevel::Debug,
+        UnknownLevel::Trace,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            UnknownLevel::Blank => "leave blank",
+            UnknownLevel::Raw => "show as written",
+            UnknownLevel::Error => "treat as ERROR",
+            UnknownLevel::Warn => "treat as WARN",
+            UnknownLevel::Info => "treat as INFO",
+            UnknownLevel::Debug => "treat as DEBUG",
+            UnknownLevel::Trace => "treat as TRACE",
         }
     }
-    pub fn intern_val(&mut self, val: Value) -> IValue {
+
+    /// The level to treat unknown levels as, if any.
+    pub fn as_level(self) -> Option<Level> {
+        match self {
+            UnknownLevel::Blank | UnknownLevel::Raw => None,
+            UnknownLevel::Error => Some(Level::ERROR),
+            UnknownLevel::Warn => Some(Level::WARN),
+            UnknownLevel::Info => Some(Level::INFO),
+            UnknownLevel::Debug => Some(Level::DEBUG),
+            UnknownLevel::Trace => Some(Level::TRACE),
+        }
+    }
+}
+
+/// How many digits of the seconds to show in timestamps.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimestampPrecision {
+    Secs,
+    #[default]
+    Millis,
+    Micros,
+    Nanos,
+    /// As many digits as it takes to not lose anything
+    Auto,
+}
+
+impl TimestampPrecision {
+    pub const ALL: [TimestampPrecision; 5] = [
+        TimestampPrecision::Secs,
+        TimestampPrecision::Millis,
+        TimestampPrecision::Micros,
+        TimestampPrecision::Nanos,
+        TimestampPrecision::Auto,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TimestampPrecision::Secs => "seconds",
+            TimestampPrecision::Millis => "milliseconds",
+            TimestampPrecision::Micros => "microseconds",
+            TimestampPrecision::Nanos => "nanoseconds",
+            TimestampPrecision::Auto => "auto",
+        }
+    }
+
+    pub fn seconds_format(self) -> SecondsFormat {
+        match self {
+            TimestampPrecision::Secs => SecondsFormat::Secs,
+            TimestampPrecision::Millis => SecondsFormat::Millis,
+            TimestampPrecision::Micros => SecondsFormat::Micros,
+            TimestampPrecision::Nanos => SecondsFormat::Nanos,
+            TimestampPrecision::Auto => SecondsFormat::AutoSi,
+        }
+    }
+
+    /// How many digits after the decimal point, for printing durations.
+    pub fn digits(self) -> usize {
+        match self {
+            TimestampPrecision::Secs => 0,
+            TimestampPrecision::Millis => 3,
+            TimestampPrecision::Micros | TimestampPrecision::Auto => 6,
+            TimestampPrecision::Nanos => 9,
+        }
+    }
+}
+
+/// How to print field keys, which can get long with namespaced keys like
+/// `otel.scope.name`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyStyle {
+    #[default]
+    Full,
+    /// `name`
+    LastSegment,
+    /// `o.s.name`
+    Abbreviated,
+}
+
+impl KeyStyle {
+    pub const ALL: [KeyStyle; 3] = [KeyStyle::Full, KeyStyle::LastSegment, KeyStyle::Abbreviated];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            KeyStyle::Full => "otel.scope.name",
+            KeyStyle::LastSegment => "name",
+            KeyStyle::Abbreviated => "o.s.name",
+        }
+    }
+
+    pub fn apply(self, key: &str) -> Cow<'_, str> {
+        let (prefix, last) = match key.rsplit_once('.') {
+            Some(split) if self != KeyStyle::Full => split,
+            _ => return Cow::Borrowed(key),
+        };
+        match self {
+            KeyStyle::Full => unreachable!(),
+            KeyStyle::LastSegment => Cow::Borrowed(last),
+            KeyStyle::Abbreviated => {
+                let mut short = String::new();
+                for segment in prefix.split('.') {
+                    short.extend(segment.chars().next());
+                    short.push('.');
+                }
+                short.push_str(last);
+                Cow::Owned(short)
+            }
+        }
+    }
+}
+
+/// Which messages to show.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    /// Only show messages with a field value containing this (case-insensitive).
+    pub search: String,
+    /// What parts of a message `search` looks at.
+    pub search_scope: SearchScope,
+    pub levels: LevelFilter,
+    /// Only show messages that have this field...
+    pub field_key: String,
+    /// ...with a value containing this (case-insensitive).
+    pub field_value: String,
+    /// Only show messages that have this field, whatever its value.
+    pub has_field: String,
+    /// Only show spans with an ERROR somewhere under them (but everything in those).
+    pub errors_only_spans: bool,
+    /// Only show messages whose target matches this. Compiled by the UI whenever
+    /// the pattern changes, so not saved with the rest.
+    #[serde(skip)]
+    pub target: Option<TargetGlob>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.search.is_empty()
+            && self.levels == LevelFilter::default()
+            && self.field_key.is_empty()
+            && self.has_field.is_empty()
+            && !self.errors_only_spans
+            && self.target.is_none()
+    }
+
+    pub fn matcher(&self, log: &LogsInner, options: &DisplayOptions) -> FilterMatcher {
+        FilterMatcher {
+            search: self.search.to_lowercase(),
+            search_scope: self.search_scope,
+            message_keys: MessageKeys::new(log, options),
+            levels: self.levels,
+            unknown_level: options.unknown_level.as_level(),
+            show_span_lifecycle: options.show_span_lifecycle,
+            field_key: self.field_key.trim().to_owned(),
+            field_value: self.field_value.to_lowercase(),
+            has_field: self.has_field.trim().to_owned(),
+            target: self.target.clone(),
+        }
+    }
+}
+
+/// A glob pattern for message targets, like `myapp::db::*`.
+///
+/// `*` matches any run of characters (including `::`), `?` matches any one
+/// character, and `[...]` matches one of a set of characters (`[!...]` for
+/// anything but them), with ranges like `[a-z]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetGlob {
+    pattern: String,
+    tokens: Vec<GlobToken>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GlobToken {
+    Literal(char),
+    AnyChar,
+    AnyRun,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl GlobToken {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            GlobToken::Literal(lit) => *lit == c,
+            GlobToken::AnyChar => true,
+            GlobToken::AnyRun => unreachable!("runs are handled by TargetGlob::matches"),
+            GlobToken::Class { negated, ranges } => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }
+        }
+    }
+}
+
+impl TargetGlob {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            let token = match c {
+                '*' => {
+                    // `**` means the same thing as `*`
+                    if tokens.last() == Some(&GlobToken::AnyRun) {
+                        continue;
+                    }
+                    GlobToken::AnyRun
+                }
+                '?' => GlobToken::AnyChar,
+                '[' => {
+                    let negated = chars.next_if(|&(_, c)| c == '!').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let Some((_, lo)) = chars.next() else {
+                            return Err(format!("unclosed '[' at {}", start));
+                        };
+                        // A `]` right at the start is part of the set rather than closing it
+                        if lo == ']' && !ranges.is_empty() {
+                            break;
+                        }
+                        let hi = if chars.next_if(|&(_, c)| c == '-').is_some() {
+                            match chars.next() {
+                                Some((_, ']')) | None => {
+                                    return Err(format!("unfinished range in '[' at {}", start))
+                                }
+                                Some((_, hi)) => hi,
+                            }
+                        } else {
+                            lo
+                        };
+                        if hi < lo {
+                            return Err(format!(
+                                "backwards range {}-{} in '[' at {}",
+                                lo, hi, start
+                            ));
+                        }
+                        ranges.push((lo, hi));
+                    }
+                    GlobToken::Class { negated, ranges }
+                }
+                c => GlobToken::Literal(c),
+            };
+            tokens.push(token);
+        }
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            tokens,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn matches(&self, target: &str) -> bool {
+        let text = target.chars().collect::<Vec<_>>();
+        let (mut t, mut p) = (0, 0);
+        // Where to pick up from if what came after the last `*` stops matching:
+        // the token after it, and the next character for it to try swallowing
+        let mut backtrack = None;
+        while t < text.len() {
+            match self.tokens.get(p) {
+                Some(GlobToken::AnyRun) => {
+                    backtrack = Some((p + 1, t));
+                    p += 1;
+                }
+                Some(token) if token.matches(text[t]) => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match backtrack {
+                    Some((star_p, star_t)) => {
+                        backtrack = Some((star_p, star_t + 1));
+                        p = star_p;
+                        t = star_t + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        self.tokens[p..]
+            .iter()
+            .all(|token| *token == GlobToken::AnyRun)
+    }
+}
+
+/// What parts of a message the search looks at.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchScope {
+    /// Just the message's text (see `MessageKeys`)
+    Message,
+    /// The values of every field
+    #[default]
+    Values,
+    /// The keys and values of every field
+    KeysAndValues,
+}
+
+impl SearchScope {
+    pub const ALL: [SearchScope; 3] = [
+        SearchScope::Message,
+        SearchScope::Values,
+        SearchScope::KeysAndValues,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SearchScope::Message => "message",
+            SearchScope::Values => "all values",
+            SearchScope::KeysAndValues => "keys + values",
+        }
+    }
+}
+
+/// Which levels of message to show.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelFilter {
+    pub error: bool,
+    pub warn: bool,
+    pub info: bool,
+    pub debug: bool,
+    pub trace: bool,
+    /// Messages with no (recognized) level
+    pub other: bool,
+}
+
+impl LevelFilter {
+    pub fn errors_only() -> Self {
+        Self {
+            error: true,
+            warn: false,
+            info: false,
+            debug: false,
+            trace: false,
+            other: false,
+        }
+    }
+
+    pub fn allows(&self, level: Option<Level>) -> bool {
+        match level {
+            Some(Level::ERROR) => self.error,
+            Some(Level::WARN) => self.warn,
+            Some(Level::INFO) => self.info,
+            Some(Level::DEBUG) => self.debug,
+            Some(Level::TRACE) => self.trace,
+            None => self.other,
+        }
+    }
+}
+
+impl Default for LevelFilter {
+    fn default() -> Self {
+        Self {
+            error: true,
+            warn: true,
+            info: true,
+            debug: true,
+            trace: true,
+            other: true,
+        }
+    }
+}
+
+/// A `Filter` preprocessed for checking lots of messages.
+#[derive(Debug, Clone)]
+pub struct FilterMatcher {
+    search: String,
+    search_scope: SearchScope,
+    message_keys: MessageKeys,
+    levels: LevelFilter,
+    unknown_level: Option<Level>,
+    show_span_lifecycle: bool,
+    field_key: String,
+    field_value: String,
+    has_field: String,
+    target: Option<TargetGlob>,
+}
+
+impl FilterMatcher {
+    pub fn matches(&self, entry: &MessageEntry) -> bool {
+        if entry.lifecycle.is_some() && !self.show_span_lifecycle {
+            return false;
+        }
+        if let Some(target) = &self.target {
+            if !target.matches(&entry.target) {
+                return false;
+            }
+        }
+        if !self.levels.allows(entry.level.or(self.unknown_level)) {
+            return false;
+        }
+        if !self.field_key.is_empty()
+            && !entry
+                .fields
+                .vals
+                .iter()
+                .any(|(k, v)| **k == *self.field_key && val_contains(v, &self.field_value))
+        {
+            return false;
+        }
+        if !self.has_field.is_empty()
+            && !entry
+                .fields
+                .vals
+                .iter()
+                .any(|(k, _)| **k == *self.has_field)
+        {
+            return false;
+        }
+        if self.search.is_empty() {
+            return true;
+        }
+        let fields = &entry.fields.vals;
+        match self.search_scope {
+            SearchScope::Message => self
+                .message_keys
+                .index(fields)
+                .is_some_and(|i| val_contains(&fields[i].1, &self.search)),
+            SearchScope::Values => fields.iter().any(|(_k, v)| val_contains(v, &self.search)),
+            SearchScope::KeysAndValues => fields.iter().any(|(k, v)| {
+                k.to_lowercase().contains(&self.search) || val_contains(v, &self.search)
+            }),
+        }
+    }
+}
+
+/// Which of a message's fields is its text, resolved against the strings of a
+/// particular `LogsInner`. The rows and the search both go through this, so
+/// they agree on what "the message" is.
+#[derive(Debug, Clone)]
+pub struct MessageKeys {
+    message: IString,
+    /// See `DisplayOptions::message_fallback_keys`, without the `*`.
+    fallback_keys: Vec<IString>,
+    /// Whether `DisplayOptions::message_fallback_keys` had a `*`.
+    fallback_first: bool,
+    /// Hidden fields can't stand in for the message (see `PrintOptions::is_hidden`).
+    hidden_keys: HashSet<IString>,
+    shown_keys: Option<HashSet<IString>>,
+}
+
+impl MessageKeys {
+    pub fn new(log: &LogsInner, options: &DisplayOptions) -> Self {
+        Self {
+            message: log.i_message.clone(),
+            fallback_keys: options
+                .message_fallback_keys
+                .split(',')
+                .map(str::trim)
+                .filter_map(|key| log.interner.get(key))
+                .collect(),
+            fallback_first: options
+                .message_fallback_keys
+                .split(',')
+                .any(|key| key.trim() == "*"),
+            hidden_keys: options
+                .hidden_keys
+                .iter()
+                .filter_map(|key| log.interner.get(key))
+                .collect(),
+            shown_keys: options.shown_keys.as_ref().map(|keys| {
+                keys.iter()
+                    .filter_map(|key| log.interner.get(key))
+                    .collect()
+            }),
+        }
+    }
+
+    /// Where the message's text is in its fields, if it has any.
+    pub fn index(&self, fields: &[(IString, IValue)]) -> Option<usize> {
+        let is_shown = |k: &IString| {
+            !self.hidden_keys.contains(k)
+                && self
+                    .shown_keys
+                    .as_ref()
+                    .is_none_or(|shown| shown.contains(k))
+        };
+        fields
+            .iter()
+            .position(|(k, _v)| k == &self.message)
+            .or_else(|| {
+                self.fallback_keys
+                    .iter()
+                    .find_map(|key| fields.iter().position(|(k, _v)| k == key && is_shown(k)))
+            })
+            .or_else(|| {
+                fields
+                    .iter()
+                    .position(|(k, _v)| is_shown(k))
+                    .filter(|_| self.fallback_first)
+            })
+    }
+}
+
+/// Whether the printed value contains `needle`, which must already be lowercase.
+fn val_contains(val: &IValue, needle: &str) -> bool {
+    if needle.is_empty() {
+        return true;
+    }
+    let mut printed = String::new();
+    print_val(&mut printed, 0, val, NumberFormat::Plain, false);
+    printed.to_lowercase().contains(needle)
+}
+
+/// How many lines of a multi-line value to show before it needs expanding.
+const MAX_COLLAPSED_LINES: usize = 10;
+
+/// `DisplayOptions` resolved against the strings of a particular `LogsInner`,
+/// so that printing can compare interned strings instead of text.
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    pub hidden_keys: HashSet<IString>,
+    pub shown_keys: Option<HashSet<IString>>,
+    pub filter: FilterMatcher,
+    pub message_keys: MessageKeys,
+    pub numbers: NumberFormat,
+    pub collapse_whitespace: bool,
+    pub keys: KeyStyle,
+    pub unknown_level: UnknownLevel,
+    pub timestamps: TimestampPrecision,
+    /// A valid `DisplayOptions::time_format`, if there is one.
+    pub time_format: Option<String>,
+    /// Print times relative to this instead of as dates.
+    pub time_origin: Option<DateTime<Local>>,
+    pub color_rules: Vec<(IString, ColorRule)>,
+    /// Cut off multi-line values that are longer than this (unless expanded).
+    pub max_block_lines: Option<usize>,
+    pub expanded_messages: BTreeSet<MessageId>,
+    pub max_depth: Option<usize>,
+    /// See `DisplayOptions::show_time_deltas`.
+    pub time_deltas: bool,
+    pub slow_delta: Option<chrono::Duration>,
+    pub show_message_ids: bool,
+    pub label_nameless_spans: bool,
+    pub span_start_times: bool,
+    /// If set, only these spans are shown (see `Filter::errors_only_spans`).
+    pub error_spans: Option<Arc<HashSet<SpanId>>>,
+    /// Stop after printing this many messages, since a huge span would
+    /// otherwise take forever to print and a lot of memory to hold.
+    pub max_messages: Option<usize>,
+    /// Print only the messages, not the spans they're in (see `Query::Flat`).
+    pub flat: bool,
+    pub show_targets: bool,
+    /// See `DisplayOptions::target_prefixes`.
+    pub target_prefixes: Vec<String>,
+}
+
+/// The spans with errors in them, along with what they were found for so we
+/// know when they're out of date. Nothing else in the filter changes them.
+#[derive(Debug, Clone)]
+pub struct ErrorSpans {
+    unknown_level: UnknownLevel,
+    num_messages: usize,
+    spans: Arc<HashSet<SpanId>>,
+}
+
+impl PrintOptions {
+    pub fn new(log: &LogsInner, options: &DisplayOptions, filter: &Filter) -> Self {
+        let error_spans = filter
+            .errors_only_spans
+            .then(|| Arc::new(log.error_spans(options.unknown_level.as_level())));
+        Self::with_error_spans(log, options, filter, error_spans)
+    }
+
+    /// Like `new`, but for every frame: finding the error spans means going
+    /// through every message, so they're only found again when `cache` is stale.
+    pub fn cached(
+        log: &LogsInner,
+        options: &DisplayOptions,
+        filter: &Filter,
+        cache: &mut Option<ErrorSpans>,
+    ) -> Self {
+        let error_spans = filter.errors_only_spans.then(|| {
+            let up_to_date = cache.as_ref().is_some_and(|cached| {
+                cached.unknown_level == options.unknown_level
+                    && cached.num_messages == log.messages.len()
+            });
+            if !up_to_date {
+                *cache = Some(ErrorSpans {
+                    unknown_level: options.unknown_level,
+                    num_messages: log.messages.len(),
+                    spans: Arc::new(log.error_spans(options.unknown_level.as_level())),
+                });
+            }
+            cache.as_ref().unwrap().spans.clone()
+        });
+        Self::with_error_spans(log, options, filter, error_spans)
+    }
+
+    fn with_error_spans(
+        log: &LogsInner,
+        options: &DisplayOptions,
+        filter: &Filter,
+        error_spans: Option<Arc<HashSet<SpanId>>>,
+    ) -> Self {
+        Self {
+            filter: filter.matcher(log, options),
+            message_keys: MessageKeys::new(log, options),
+            numbers: options.number_format,
+            collapse_whitespace: options.collapse_whitespace,
+            keys: options.key_style,
+            unknown_level: options.unknown_level,
+            timestamps: options.timestamp_precision,
+            time_format: Some(options.time_format.trim())
+                .filter(|format| !format.is_empty() && check_time_format(format).is_ok())
+                .map(str::to_owned),
+            time_origin: None,
+            max_block_lines: Some(MAX_COLLAPSED_LINES),
+            expanded_messages: BTreeSet::new(),
+            max_depth: options.max_depth,
+            time_deltas: options.show_time_deltas,
+            slow_delta: options
+                .slow_delta_ms
+                .map(|ms| chrono::Duration::milliseconds(ms.min(i64::MAX as u64) as i64)),
+            show_message_ids: options.show_message_ids,
+            label_nameless_spans: options.label_nameless_spans,
+            span_start_times: options.span_start_times,
+            max_messages: None,
+            flat: false,
+            show_targets: options.show_targets,
+            target_prefixes: options
+                .target_prefixes
+                .split(',')
+                .map(str::trim)
+                .filter(|prefix| !prefix.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            error_spans,
+            color_rules: options
+                .color_rules
+                .iter()
+                .filter_map(|rule| Some((log.interner.get(rule.key.trim())?, rule.clone())))
+                .collect(),
+            shown_keys: options.shown_keys.as_ref().map(|keys| {
+                keys.iter()
+                    .filter_map(|key| log.interner.get(key))
+                    .collect()
+            }),
+            // If a key was never interned then nothing can have it, so just skip it
+            hidden_keys: options
+                .hidden_keys
+                .iter()
+                .filter_map(|key| log.interner.get(key))
+                .collect(),
+        }
+    }
+
+    pub fn is_hidden(&self, key: &IString) -> bool {
+        self.hidden_keys.contains(key)
+            || self
+                .shown_keys
+                .as_ref()
+                .is_some_and(|shown| !shown.contains(key))
+    }
+
+    /// The color of the first rule the message matches.
+    pub fn color_of(&self, entry: &MessageEntry) -> Option<[u8; 3]> {
+        self.color_rules.iter().find_map(|(key, rule)| {
+            entry
+                .fields
+                .vals
+                .iter()
+                .any(|(k, v)| k == key && rule.comparison.compare(v, &rule.value))
+                .then_some(rule.color)
+        })
+    }
+
+    /// A target with the longest of `target_prefixes` it starts with cut off
+    /// (unless that would leave nothing).
+    pub fn trim_target<'a>(&self, target: &'a str) -> &'a str {
+        self.target_prefixes
+            .iter()
+            .filter_map(|prefix| target.strip_prefix(prefix.as_str()))
+            .filter(|trimmed| !trimmed.is_empty())
+            .min_by_key(|trimmed| trimmed.len())
+            .unwrap_or(target)
+    }
+
+    /// The level to show a message as, after dealing with unknown levels.
+    pub fn level_of(&self, entry: &MessageEntry) -> Option<Level> {
+        entry.level.or_else(|| self.unknown_level.as_level())
+    }
+}
+
+pub fn print_indent(output: &mut String, depth: usize) {
+    // Not a `{:indent$}` format, since those panic past u16::MAX
+    output.extend(std::iter::repeat_n(' ', depth * 4));
+}
+fn write_json_str(output: &mut String, s: &str) {
+    output.push_str(&serde_json::to_string(s).unwrap());
+}
+
+/// A message's timestamp the way tracing-subscriber writes it, or nothing.
+fn format_export_timestamp(entry: &MessageEntry) -> String {
+    entry
+        .timestamp
+        .map(|timestamp| {
+            timestamp
+                .with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        })
+        .unwrap_or_default()
+}
+
+/// A message's level as it was written, if it had one.
+fn export_level(entry: &MessageEntry) -> String {
+    match (entry.level, &entry.raw_level) {
+        (Some(level), _) => level.to_string(),
+        (None, Some(raw_level)) => raw_level.to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+/// Write a CSV cell, quoted if it has to be.
+fn write_csv_field(output: &mut String, s: &str) {
+    if s.contains([',', '"', '\n', '\r']) {
+        write!(output, "\"{}\"", s.replace('"', "\"\"")).unwrap();
+    } else {
+        output.push_str(s);
+    }
+}
+
+/// Write a Markdown table cell, escaped so it stays in its cell.
+fn write_markdown_cell(output: &mut String, s: &str) {
+    let s = s.replace('\\', "\\\\").replace('|', "\\|");
+    let s = s.replace("\r\n", "<br>").replace(['\n', '\r'], "<br>");
+    write!(output, " {} |", s).unwrap();
+}
+
+/// Write fields as a JSON object, keeping duplicate keys.
+fn write_json_object<'a>(
+    output: &mut String,
+    fields: impl IntoIterator<Item = (&'a IString, &'a IValue)>,
+) {
+    write!(output, "{{").unwrap();
+    for (i, (k, v)) in fields.into_iter().enumerate() {
+        if i != 0 {
+            write!(output, ",").unwrap();
+        }
+        write_json_str(output, k);
+        write!(output, ":").unwrap();
+        match v {
+            IValue::S(s) => write_json_str(output, s),
+            IValue::U(s) => write_json_str(output, s),
+            IValue::B(b) => write!(output, "{}", b).unwrap(),
+            IValue::I(i) => write!(output, "{}", i).unwrap(),
+            // NaN and the infinities become null, since JSON can't say them
+            IValue::F(f) => output.push_str(&serde_json::to_string(&f.0).unwrap()),
+            IValue::J(json) => output.push_str(json),
+        }
+    }
+    write!(output, "}}").unwrap();
+}
+
+/// Print a value inline. With `collapse_whitespace`, runs of whitespace in
+/// strings (newlines included) are squashed down to single spaces.
+pub fn print_val(
+    output: &mut String,
+    _depth: usize,
+    val: &IValue,
+    numbers: NumberFormat,
+    collapse_whitespace: bool,
+) {
+    match val {
+        IValue::S(v) if collapse_whitespace => print_collapsed(output, v),
+        IValue::U(v) if collapse_whitespace => print_collapsed(output, v),
+        IValue::S(v) => write!(output, "{}", v).unwrap(),
+        IValue::U(v) => write!(output, "{}", v).unwrap(),
+        IValue::B(v) => write!(output, "{}", v).unwrap(),
+        IValue::I(v) => write!(output, "{}", numbers.format_int(*v)).unwrap(),
+        IValue::F(v) => write!(output, "{}", numbers.format_float(v.0)).unwrap(),
+        IValue::J(v) => write!(output, "{}", v).unwrap(),
+    }
+}
+
+fn print_collapsed(output: &mut String, val: &str) {
+    for (i, word) in val.split_whitespace().enumerate() {
+        if i != 0 {
+            output.push(' ');
+        }
+        output.push_str(word);
+    }
+}
+
+/// How to write out numbers.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumberFormat {
+    /// `1234567.89`, just like Rust does it
+    #[default]
+    Plain,
+    /// `1,234,567.89`
+    English,
+    /// `1.234.567,89`
+    German,
+    /// `1 234 567,89`
+    French,
+    /// `1'234'567.89`
+    Swiss,
+}
+
+impl NumberFormat {
+    pub const ALL: [NumberFormat; 5] = [
+        NumberFormat::Plain,
+        NumberFormat::English,
+        NumberFormat::German,
+        NumberFormat::French,
+        NumberFormat::Swiss,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NumberFormat::Plain => "1234567.89",
+            NumberFormat::English => "1,234,567.89",
+            NumberFormat::German => "1.234.567,89",
+            NumberFormat::French => "1 234 567,89",
+            NumberFormat::Swiss => "1'234'567.89",
+        }
+    }
+
+    /// The (thousands, decimal) separators, or None for plain formatting.
+    fn separators(self) -> Option<(char, char)> {
+        match self {
+            NumberFormat::Plain => None,
+            NumberFormat::English => Some((',', '.')),
+            NumberFormat::German => Some(('.', ',')),
+            NumberFormat::French => Some((' ', ',')),
+            NumberFormat::Swiss => Some(('\'', '.')),
+        }
+    }
+
+    pub fn format_int(self, val: i64) -> String {
+        self.localize(&val.to_string())
+    }
+
+    pub fn format_float(self, val: f64) -> String {
+        if !val.is_finite() {
+            return val.to_string();
+        }
+        self.localize(&val.to_string())
+    }
+
+    /// Take a number as Rust prints it and swap in this format's separators.
+    fn localize(self, plain: &str) -> String {
+        let (thousands, decimal) = match self.separators() {
+            Some(separators) => separators,
+            None => return plain.to_owned(),
+        };
+        let (sign, unsigned) = match plain.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", plain),
+        };
+        let (int_part, frac_part) = match unsigned.split_once('.') {
+            Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+            None => (unsigned, None),
+        };
+
+        let mut output = String::from(sign);
+        for (i, digit) in int_part.chars().enumerate() {
+            if i != 0 && (int_part.len() - i) % 3 == 0 {
+                output.push(thousands);
+            }
+            output.push(digit);
+        }
+        if let Some(frac_part) = frac_part {
+            output.push(decimal);
+            output.push_str(frac_part);
+        }
+        output
+    }
+}
+
+/// Print a time as a date, or relative to `PrintOptions::time_origin` if there is one.
+fn print_time(output: &mut String, timestamp: DateTime<Local>, options: &PrintOptions) {
+    match options.time_origin {
+        Some(origin) => {
+            let since = (timestamp - origin).num_nanoseconds().unwrap_or(i64::MAX);
+            let secs = since as f64 / 1_000_000_000.0;
+            write!(output, "{:+.*}s", options.timestamps.digits(), secs).unwrap();
+        }
+        None => match &options.time_format {
+            Some(format) => write!(output, "{}", timestamp.format(format)).unwrap(),
+            None => write!(
+                output,
+                "{}",
+                timestamp.to_rfc3339_opts(options.timestamps.seconds_format(), true)
+            )
+            .unwrap(),
+        },
+    }
+}
+
+/// Make sure a `chrono` format string only has specifiers chrono understands,
+/// since formatting with a bad one panics.
+pub fn check_time_format(format: &str) -> Result<(), String> {
+    for item in StrftimeItems::new(format) {
+        if item == Item::Error {
+            return Err(format!("{:?} isn't a valid time format", format));
+        }
+    }
+    Ok(())
+}
+
+/// The ` @ time` a span started at, if that's wanted and known.
+fn print_span_start_time(output: &mut String, span: &SpanEntry, options: &PrintOptions) {
+    if let (true, Some(start)) = (options.span_start_times, span.start) {
+        write!(output, " @ ").unwrap();
+        print_time(output, start, options);
+    }
+}
+
+pub fn print_span_header(
+    output: &mut String,
+    depth: usize,
+    span: &SpanEntry,
+    line_break: bool,
+    options: &PrintOptions,
+) {
+    if !span.name.is_empty() {
+        print_indent(output, depth);
+        write!(output, "[{}", span.name).unwrap();
+        for (k, v) in &span.fields.vals {
+            if options.is_hidden(k) {
+                continue;
+            }
+            write!(output, ", {} = ", options.keys.apply(k)).unwrap();
+            print_val(
+                output,
+                depth,
+                v,
+                options.numbers,
+                options.collapse_whitespace,
+            );
+        }
+        write!(output, "]").unwrap();
+        print_span_start_time(output, span, options);
+        if line_break {
+            writeln!(output).unwrap();
+        }
+    } else if options.label_nameless_spans {
+        // Make up a label like `{yak=1}` out of the fields
+        print_indent(output, depth);
+        write!(output, "{{").unwrap();
+        let shown = span
+            .fields
+            .vals
+            .iter()
+            .filter(|(k, _v)| !options.is_hidden(k));
+        for (i, (k, v)) in shown.enumerate() {
+            if i != 0 {
+                write!(output, ", ").unwrap();
+            }
+            write!(output, "{}=", options.keys.apply(k)).unwrap();
+            print_val(
+                output,
+                depth,
+                v,
+                options.numbers,
+                options.collapse_whitespace,
+            );
+        }
+        write!(output, "}}").unwrap();
+        print_span_start_time(output, span, options);
+        if line_break {
+            writeln!(output).unwrap();
+        }
+    }
+}
+
+const SPAN_PATH_SEPARATOR: &str = " > ";
+
+/// One span's part of a `Logs::span_path`, like `shave[yak=1]`.
+///
+/// Any `>` (or `\`) in it gets a `\` in front, so it can't be mistaken for
+/// the separator.
+fn print_span_path_segment(output: &mut String, span: &SpanEntry) {
+    let mut segment = span.name.to_string();
+    if !span.fields.vals.is_empty() {
+        write!(segment, "[").unwrap();
+        for (i, (k, v)) in span.fields.vals.iter().enumerate() {
+            if i != 0 {
+                write!(segment, ", ").unwrap();
+            }
+            write!(segment, "{}=", k).unwrap();
+            print_val(&mut segment, 0, v, NumberFormat::Plain, false);
+        }
+        write!(segment, "]").unwrap();
+    }
+    for c in segment.chars() {
+        if matches!(c, '>' | '\\') {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+}
+
+/// Split a `Logs::span_path` into its (still escaped) segments.
+fn split_span_path(path: &str) -> Vec<&str> {
+    let separator = SPAN_PATH_SEPARATOR.trim();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut chars = path.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if path[i..].starts_with(separator) {
+            segments.push(&path[start..i]);
+            start = i + separator.len();
+        }
+    }
+    segments.push(&path[start..]);
+    segments
+}
+
+/// Print a message (without indentation or a trailing newline).
+///
+/// Multi-line values are printed as indented blocks after the rest of the message.
+///
+/// Returns where the `[LEVEL]` token ended up, if there is one.
+pub fn print_message(
+    this: &LogsInner,
+    output: &mut String,
+    depth: usize,
+    message_id: MessageId,
+    entry: &MessageEntry,
+    options: &PrintOptions,
+) -> Option<Range<usize>> {
+    let mut level_token = None;
+    let message = options.message_keys.index(&entry.fields.vals);
+    if options.show_message_ids {
+        write!(output, "#{:<6} ", message_id).unwrap();
+    }
+    if let Some(lifecycle) = entry.lifecycle {
+        write!(output, "{} ", lifecycle.icon()).unwrap();
+    }
+    if let Some(level) = options.level_of(entry) {
+        let level_start = output.len();
+        write!(output, "[{:5}]", level).unwrap();
+        level_token = Some(level_start..output.len());
+        write!(output, " ").unwrap();
+    } else if let (UnknownLevel::Raw, Some(raw_level)) = (options.unknown_level, &entry.raw_level) {
+        write!(output, "[{:5}] ", raw_level).unwrap();
+    } else {
+        write!(output, "      ").unwrap();
+    }
+    if let Some(timestamp) = entry.timestamp {
+        write!(output, "[").unwrap();
+        print_time(output, timestamp, options);
+        write!(output, "] ").unwrap();
+    }
+    if options.show_targets && !entry.target.is_empty() {
+        write!(output, "{}: ", options.trim_target(&entry.target)).unwrap();
+    }
+    fn multiline<'a>(this: &LogsInner, options: &PrintOptions, v: &'a IValue) -> Option<&'a str> {
+        match v {
+            // Collapsing whitespace means strictly one line per message
+            IValue::S(s) if !options.collapse_whitespace && this.interner.num_lines(s) > 1 => {
+                Some(s)
+            }
+            IValue::U(s) if !options.collapse_whitespace && s.lines().nth(1).is_some() => Some(s),
+            _ => None,
+        }
+    }
+    let mut blocks = Vec::new();
+    for (i, (k, v)) in entry.fields.vals.iter().enumerate() {
+        if k != &this.i_message && Some(i) != message && !options.is_hidden(k) {
+            if let Some(s) = multiline(this, options, v) {
+                write!(output, "[{} = ↓] ", options.keys.apply(k)).unwrap();
+                blocks.push((Some(k), s.lines().collect::<Vec<_>>()));
+                continue;
+            }
+            write!(output, "[{} = ", options.keys.apply(k)).unwrap();
+            print_val(
+                output,
+                depth,
+                v,
+                options.numbers,
+                options.collapse_whitespace,
+            );
+            write!(output, "] ").unwrap();
+        }
+    }
+    if let Some(i) = message {
+        let message = &entry.fields.vals[i].1;
+        if let Some(s) = multiline(this, options, message) {
+            // The first line of a message is usually a fine summary of the rest
+            let mut lines = s.lines();
+            write!(output, "{}", lines.next().unwrap_or_default()).unwrap();
+            blocks.insert(0, (None, lines.collect()));
+        } else {
+            print_val(
+                output,
+                depth,
+                message,
+                options.numbers,
+                options.collapse_whitespace,
+            );
+        }
+    }
+
+    let expanded = options.expanded_messages.contains(&message_id);
+    for (key, lines) in blocks {
+        let mut block_depth = depth + 1;
+        if let Some(key) = key {
+            writeln!(output).unwrap();
+            print_indent(output, block_depth);
+            write!(output, "{}:", options.keys.apply(key)).unwrap();
+            block_depth += 1;
+        }
+        let max_lines = options.max_block_lines.filter(|_| !expanded);
+        for (i, line) in lines.iter().enumerate() {
+            writeln!(output).unwrap();
+            print_indent(output, block_depth);
+            if max_lines.is_some_and(|max| i >= max) {
+                write!(
+                    output,
+                    "… {} more lines (double-click to expand)",
+                    lines.len() - i
+                )
+                .unwrap();
+                break;
+            }
+            write!(output, "{}", line).unwrap();
+        }
+    }
+    level_token
+}
+
+pub fn print_span_tree(
+    this: &LogsInner,
+    output: &mut Rendered,
+    depth: usize,
+    span_id: SpanId,
+    range: Option<Range<usize>>,
+    options: &PrintOptions,
+) {
+    if !options.flat {
+        print_span_start(this, output, depth, span_id, options);
+    }
+    let range = range.unwrap_or(0..this.spans[&span_id].events.len());
+    // The spans being printed, innermost last, with how deep they are and which
+    // of their events are left. This is a loop instead of recursion so that
+    // absurdly deep logs can't blow the stack.
+    let mut stack = vec![(span_id, depth, range)];
+    while let Some((span_id, depth, events)) = stack.last_mut() {
+        let (span_id, depth) = (*span_id, *depth);
+        let Some(idx) = events.next() else {
+            stack.pop();
+            continue;
+        };
+        match &this.spans[&span_id].events[idx] {
+            EventEntry::Message(message_id) => {
+                let entry = &this.messages[message_id];
+                if !options.filter.matches(entry) {
+                    continue;
+                }
+                if options.max_messages == Some(output.printed_messages) {
+                    output.truncated = true;
+                    return;
+                }
+                output.printed_messages += 1;
+                let start = output.text.len();
+                let mut line = RenderedLine::new(LineKind::Message(*message_id));
+                let depth = if options.flat { 0 } else { depth + 1 };
+                if options.time_deltas {
+                    line.slow_delta_token = print_time_delta(output, entry, options);
+                }
+                print_indent(&mut output.text, depth);
+                line.level = options.level_of(entry);
+                line.muted = entry.lifecycle.is_some();
+                line.color = options.color_of(entry);
+                line.level_token =
+                    print_message(this, &mut output.text, depth, *message_id, entry, options);
+                writeln!(output.text).unwrap();
+                output.push_lines(start, line);
+            }
+            EventEntry::Span(sub_span) => {
+                let has_error = options
+                    .error_spans
+                    .as_ref()
+                    .map(|spans| spans.contains(sub_span));
+                if has_error == Some(false) {
+                    continue;
+                }
+                if options.flat {
+                    let num_events = this.spans[sub_span].events.len();
+                    stack.push((*sub_span, depth, 0..num_events));
+                    continue;
+                }
+                // Spans leading to an error are never folded, so the error is in view
+                if has_error.is_none() && options.max_depth.is_some_and(|max| depth + 1 > max) {
+                    print_folded_span(this, output, depth + 1, *sub_span, options);
+                } else {
+                    print_span_start(this, output, depth + 1, *sub_span, options);
+                    let num_events = this.spans[sub_span].events.len();
+                    stack.push((*sub_span, depth + 1, 0..num_events));
+                }
+            }
+        }
+    }
+}
+
+/// The header line a span's events are printed under.
+fn print_span_start(
+    this: &LogsInner,
+    output: &mut Rendered,
+    depth: usize,
+    span_id: SpanId,
+    options: &PrintOptions,
+) {
+    let start = output.text.len();
+    print_span_header(
+        &mut output.text,
+        depth,
+        &this.spans[&span_id],
+        true,
+        options,
+    );
+    // (nameless spans might not have printed anything)
+    if options.time_deltas && output.text.len() > start {
+        output
+            .text
+            .insert_str(start, &" ".repeat(DELTA_GUTTER_WIDTH));
+    }
+    output.push_lines(start, RenderedLine::new(LineKind::SpanHeader(span_id)));
+}
+
+/// How wide the `PrintOptions::time_deltas` gutter is, including the space after it.
+const DELTA_GUTTER_WIDTH: usize = 9;
+
+/// Print the time since the last message with a timestamp in a gutter (left
+/// blank if there's nothing to measure), and say where it is if it's longer
+/// than `PrintOptions::slow_delta`.
+fn print_time_delta(
+    output: &mut Rendered,
+    entry: &MessageEntry,
+    options: &PrintOptions,
+) -> Option<Range<usize>> {
+    let start = output.text.len();
+    let delta = entry
+        .timestamp
+        .zip(output.last_timestamp)
+        .map(|(timestamp, last)| timestamp - last);
+    let printed = delta.map(format_time_delta).unwrap_or_default();
+    write!(output.text, "{:>1$} ", printed, DELTA_GUTTER_WIDTH - 1).unwrap();
+    if entry.timestamp.is_some() {
+        output.last_timestamp = entry.timestamp;
+    }
+    let slow = delta
+        .zip(options.slow_delta)
+        .is_some_and(|(delta, slow)| delta > slow);
+    slow.then(|| start..output.text.len() - 1)
+}
+
+/// A short `+12ms`-like description of the time between two messages.
+fn format_time_delta(delta: chrono::Duration) -> String {
+    let sign = if delta < chrono::Duration::zero() {
+        "-"
+    } else {
+        "+"
+    };
+    let delta = delta.abs();
+    let ms = delta.num_milliseconds();
+    if ms == 0 {
+        let us = delta.num_microseconds().unwrap_or(0);
+        format!("{}{}µs", sign, us)
+    } else if ms < 1000 {
+        format!("{}{}ms", sign, ms)
+    } else if ms < 60_000 {
+        format!("{}{:.2}s", sign, ms as f64 / 1000.0)
+    } else if ms < 3_600_000 {
+        format!("{}{}m{:02}s", sign, ms / 60_000, ms / 1000 % 60)
+    } else {
+        format!("{}{}h{:02}m", sign, ms / 3_600_000, ms / 60_000 % 60)
+    }
+}
+
+/// Print a one line stand-in for a span that's past `PrintOptions::max_depth`.
+fn print_folded_span(
+    this: &LogsInner,
+    output: &mut Rendered,
+    depth: usize,
+    span_id: SpanId,
+    options: &PrintOptions,
+) {
+    let mut deeper_messages = 0;
+    let mut to_visit = vec![span_id];
+    while let Some(span_id) = to_visit.pop() {
+        for event in &this.spans[&span_id].events {
+            match event {
+                EventEntry::Message(id) => {
+                    deeper_messages += options.filter.matches(&this.messages[id]) as usize;
+                }
+                EventEntry::Span(id) => to_visit.push(*id),
+            }
+        }
+    }
+    if deeper_messages == 0 {
+        return;
+    }
+
+    let start = output.text.len();
+    let span = &this.spans[&span_id];
+    if options.time_deltas {
+        write!(output.text, "{}", " ".repeat(DELTA_GUTTER_WIDTH)).unwrap();
+    }
+    if span.name.is_empty() && !options.label_nameless_spans {
+        print_indent(&mut output.text, depth);
+    } else {
+        print_span_header(&mut output.text, depth, span, false, options);
+        write!(output.text, " ").unwrap();
+    }
+    writeln!(output.text, "[... {} deeper messages]", deeper_messages).unwrap();
+    let mut line = RenderedLine::new(LineKind::SpanHeader(span_id));
+    line.muted = true;
+    output.push_lines(start, line);
+}
+
+impl Logs {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(LogsInner::new())),
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut log = self.inner.lock().unwrap();
+        let root_span = log.root_span;
+        let mut root = log.spans.remove(&root_span).unwrap();
+        root.events.clear();
+
+        root.json_subspan_keys.clear();
+        root.start = None;
+
+        log.spans.clear();
+        log.messages.clear();
+        log.cur_rendered = None;
+        log.next_message_id = 0;
+        log.next_span_id = 1;
+        log.field_keys.clear();
+        log.spans_by_key.clear();
+        log.parse_errors = 0;
+        log.bad_lines.clear();
+        log.time_range = None;
+        log.overflowed_messages = 0;
+
+        // TODO: probably should clear the interner but then also have to
+        // carefully reinsert the builtin strings.
+
+        log.spans.insert(root_span, root);
+    }
+
+    pub fn add_json_message(&self, input: &str) {
+        self.inner.lock().unwrap().add_json_message(input);
+    }
+
+    /// `add_json_message`, but with where it came from for reporting parse errors.
+    pub fn add_json_line(&self, file: &Arc<Path>, line: Option<u64>, input: &str) {
+        self.inner
+            .lock()
+            .unwrap()
+            .add_json_line(Some(file), line, input);
+    }
+
+    /// The earliest and latest message timestamps, if any messages had one.
+    pub fn time_range(&self) -> Option<(DateTime<Local>, DateTime<Local>)> {
+        self.inner.lock().unwrap().time_range
+    }
+
+    /// How many messages are currently loaded.
+    pub fn num_messages(&self) -> usize {
+        self.inner.lock().unwrap().messages.len()
+    }
+
+    /// How many messages of each level are loaded.
+    pub fn level_counts(&self) -> LevelCounts {
+        let log = self.inner.lock().unwrap();
+        let mut counts = LevelCounts::default();
+        for entry in log.messages.values() {
+            counts.add(entry.level);
+        }
+        counts
+    }
+
+    /// Throw away the cached rendering so the next `render_query` starts fresh.
+    pub fn invalidate_render(&self) {
+        self.inner.lock().unwrap().cur_rendered = None;
+    }
+
+    /// See `LogsInner::intern_values`.
+    pub fn set_intern_values(&self, intern_values: bool) {
+        self.inner.lock().unwrap().intern_values = intern_values;
+    }
+
+    /// See `LogsInner::plain_text_fallback`.
+    pub fn set_plain_text_fallback(&self, plain_text_fallback: bool) {
+        self.inner.lock().unwrap().plain_text_fallback = plain_text_fallback;
+    }
+
+    /// See `LogsInner::timestamp_key`.
+    pub fn set_timestamp_key(&self, timestamp_key: &str) {
+        self.inner.lock().unwrap().timestamp_key = timestamp_key.to_owned();
+    }
+
+    /// See `LogsInner::max_spans`.
+    pub fn set_max_spans(&self, max_spans: Option<usize>) {
+        self.inner.lock().unwrap().max_spans = max_spans;
+    }
+
+    /// See `LogsInner::overflowed_messages`.
+    pub fn overflowed_messages(&self) -> usize {
+        self.inner.lock().unwrap().overflowed_messages
+    }
+
+    /// See `LogsInner::spans_innermost_first`.
+    pub fn set_spans_innermost_first(&self, spans_innermost_first: bool) {
+        self.inner.lock().unwrap().spans_innermost_first = spans_innermost_first;
+    }
+
+    /// Whether most of what was read wasn't tracing JSON at all, so the user
+    /// probably opened the wrong kind of file.
+    pub fn mostly_not_json(&self) -> bool {
+        let log = self.inner.lock().unwrap();
+        let lines = log.parse_errors + log.messages.len();
+        !log.plain_text_fallback && log.parse_errors > 0 && log.parse_errors * 10 > lines * 9
+    }
+
+    /// All the field keys messages have had, in alphabetical order.
+    pub fn field_keys(&self) -> Vec<IString> {
+        let log = self.inner.lock().unwrap();
+        let mut keys = log.field_keys.iter().cloned().collect::<Vec<_>>();
+        keys.sort_by_key(|key| key.0.clone());
+        keys
+    }
+
+    /// Rebuild clean plain text for the given lines of a rendered query,
+    /// one line per span/message with no indentation.
+    pub fn plain_text(
+        &self,
+        lines: &[RenderedLine],
+        options: &DisplayOptions,
+        filter: &Filter,
+    ) -> String {
+        let log = self.inner.lock().unwrap();
+        let mut print_options = PrintOptions::new(&log, options, filter);
+        // Copies should have everything, even if it's collapsed on screen
+        print_options.max_block_lines = None;
+        let mut output = String::new();
+        let mut prev_kind = None;
+        for line in lines {
+            // Multi-line values show up as several lines of the same kind
+            if prev_kind == Some(line.kind) {
+                continue;
+            }
+            prev_kind = Some(line.kind);
+            match line.kind {
+                LineKind::SpanHeader(span_id) => {
+                    print_span_header(&mut output, 0, &log.spans[&span_id], true, &print_options);
+                }
+                LineKind::Message(message_id) => {
+                    let entry = &log.messages[&message_id];
+                    print_message(&log, &mut output, 0, message_id, entry, &print_options);
+                    writeln!(output).unwrap();
+                }
+            }
+        }
+        output
+    }
+
+    /// The nested JSON fields of a message, pretty-printed, and any strings
+    /// with newlines or tabs in them as they originally were.
+    ///
+    /// With `json_strings`, strings that hold JSON (even if it was encoded as
+    /// a string more than once) are decoded and pretty-printed. With
+    /// `embedded`, also any strings that hold JSON, SQL, or a URL.
+    pub fn nested_fields(
+        &self,
+        message_id: MessageId,
+        json_strings: bool,
+        embedded: bool,
+    ) -> Vec<(String, String)> {
+        let log = self.inner.lock().unwrap();
+        let entry = match log.messages.get(&message_id) {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+        entry
+            .fields
+            .vals
+            .iter()
+            .filter_map(|(k, v)| {
+                let text = match v {
+                    IValue::J(json) => pretty_json(json),
+                    IValue::S(IString(s)) | IValue::U(s) => {
+                        match decode_json_string(s).filter(|_| json_strings || embedded) {
+                            Some(pretty) => pretty,
+                            None if s.contains(['\n', '\t']) => s.to_string(),
+                            None if embedded && Format::detect(s).is_some() => s.to_string(),
+                            None => return None,
+                        }
+                    }
+                    _ => return None,
+                };
+                Some((k.to_string(), text))
+            })
+            .collect()
+    }
+
+    /// The fields of a row as plain text, for copying them one at a time.
+    pub fn field_values(&self, kind: LineKind) -> Vec<(String, String)> {
+        let log = self.inner.lock().unwrap();
+        let fields = match kind {
+            LineKind::Message(id) => log.messages.get(&id).map(|entry| &entry.fields),
+            LineKind::SpanHeader(id) => log.spans.get(&id).map(|span| &span.fields),
+        };
+        fields
+            .into_iter()
+            .flat_map(|fields| &fields.vals)
+            .map(|(k, v)| {
+                let mut printed = String::new();
+                print_val(&mut printed, 0, v, NumberFormat::Plain, false);
+                (k.to_string(), printed)
+            })
+            .collect()
+    }
+
+    /// The fields of several messages lined up by key, in the order the keys
+    /// first appear. Messages that aren't loaded are skipped.
+    pub fn compare_messages(&self, message_ids: &[MessageId]) -> Vec<FieldComparison> {
+        let log = self.inner.lock().unwrap();
+        let entries = message_ids
+            .iter()
+            .filter_map(|id| log.messages.get(id))
+            .collect::<Vec<_>>();
+        let mut keys = Vec::<&IString>::new();
+        for entry in &entries {
+            for (key, _) in &entry.fields.vals {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys.into_iter()
+            .map(|key| {
+                let values = entries
+                    .iter()
+                    .map(|entry| {
+                        let field = entry.fields.vals.iter().find(|(k, _)| k == key);
+                        field.map(|(_, val)| {
+                            let mut printed = String::new();
+                            print_val(&mut printed, 0, val, NumberFormat::Plain, false);
+                            printed
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                // Compare what's printed rather than the `IValue`s, since
+                // uninterned strings with the same text aren't `==`
+                FieldComparison {
+                    key: key.to_string(),
+                    differs: values.iter().any(|val| *val != values[0]),
+                    values,
+                }
+            })
+            .collect()
+    }
+
+    /// The keys of a row's fields that `style` shortens, as (shortened, full).
+    pub fn shortened_keys(&self, kind: LineKind, style: KeyStyle) -> Vec<(String, String)> {
+        let log = self.inner.lock().unwrap();
+        let fields = match kind {
+            LineKind::Message(id) => log.messages.get(&id).map(|entry| &entry.fields),
+            LineKind::SpanHeader(id) => log.spans.get(&id).map(|span| &span.fields),
+        };
+        fields
+            .into_iter()
+            .flat_map(|fields| &fields.vals)
+            .filter_map(|(k, _v)| {
+                let short = style.apply(k);
+                (*short != **k).then(|| (short.into_owned(), k.to_string()))
+            })
+            .collect()
+    }
+
+    /// See `LogsInner::span_path`.
+    pub fn span_path(&self, span_id: SpanId) -> String {
+        self.inner.lock().unwrap().span_path(span_id)
+    }
+
+    /// See `LogsInner::resolve_span_path`.
+    pub fn resolve_span_path(&self, path: &str) -> Option<SpanId> {
+        self.inner.lock().unwrap().resolve_span_path(path)
+    }
+
+    pub fn retain_matching(&self, filter: &Filter, options: &DisplayOptions) {
+        self.inner.lock().unwrap().retain_matching(filter, options);
+    }
+
+    /// The messages a query shows (that the filter matches) as CSV, with a column
+    /// for each of the shown keys, or for every key if they're all shown.
+    pub fn export_csv(&self, query: Query, options: &DisplayOptions, filter: &Filter) -> String {
+        let log = self.inner.lock().unwrap();
+        let span_ids = match query {
+            Query::All => vec![log.root_span],
+            Query::Span(span) | Query::Flat(span) => vec![span],
+            Query::Merged(span) => log.identical_spans(span),
+        };
+        let columns = match &options.shown_keys {
+            Some(keys) => keys.clone(),
+            None => {
+                let mut keys = log
+                    .field_keys
+                    .iter()
+                    .filter(|key| **key != log.i_message)
+                    .map(|key| key.to_string())
+                    .collect::<Vec<_>>();
+                keys.sort();
+                keys
+            }
+        };
+        log.export_csv(&span_ids, &columns, &filter.matcher(&log, options))
+    }
+
+    /// Some messages as a Markdown table, for pasting into an issue. There's
+    /// a column for each of the shown keys, or for every key the messages have
+    /// if they're all shown.
+    pub fn markdown_table(&self, message_ids: &[MessageId], options: &DisplayOptions) -> String {
+        let log = self.inner.lock().unwrap();
+        let entries = message_ids
+            .iter()
+            .filter_map(|id| log.messages.get(id))
+            .collect::<Vec<_>>();
+        let message_keys = MessageKeys::new(&log, options);
+        let columns = match &options.shown_keys {
+            Some(keys) => keys
+                .iter()
+                .filter_map(|key| log.interner.get(key))
+                .filter(|key| *key != log.i_message)
+                .collect(),
+            None => {
+                let mut keys = Vec::<IString>::new();
+                for entry in &entries {
+                    for (key, _) in &entry.fields.vals {
+                        if *key != log.i_message && !keys.contains(key) {
+                            keys.push(key.clone());
+                        }
+                    }
+                }
+                keys
+            }
+        };
+
+        let mut output = String::from("|");
+        let headers = ["time", "level", "message"]
+            .into_iter()
+            .chain(columns.iter().map(|key| &**key));
+        for header in headers.clone() {
+            write_markdown_cell(&mut output, header);
+        }
+        output.push_str("\n|");
+        for _ in headers {
+            output.push_str(" --- |");
+        }
+        output.push('\n');
+
+        let print_field = |(_, val): &(IString, IValue)| {
+            let mut printed = String::new();
+            print_val(&mut printed, 0, val, NumberFormat::Plain, false);
+            printed
+        };
+        let find_value = |entry: &MessageEntry, key: &IString| {
+            entry
+                .fields
+                .vals
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(print_field)
+        };
+        for entry in entries {
+            output.push('|');
+            let message = message_keys
+                .index(&entry.fields.vals)
+                .map(|idx| print_field(&entry.fields.vals[idx]))
+                .unwrap_or_default();
+            for cell in [format_export_timestamp(entry), export_level(entry), message] {
+                write_markdown_cell(&mut output, &cell);
+            }
+            for key in &columns {
+                let value = find_value(entry, key);
+                write_markdown_cell(&mut output, value.as_deref().unwrap_or_default());
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Print the results of a query, or at least the first `max_messages` of
+    /// them, with the multi-line values of `expanded_messages` printed in full.
+    pub fn render_query(
+        &self,
+        query: Query,
+        options: &DisplayOptions,
+        filter: &Filter,
+        expanded_messages: &BTreeSet<MessageId>,
+        max_messages: Option<usize>,
+    ) -> Arc<Rendered> {
+        let mut log = self.inner.lock().unwrap();
+        if Some(query) == log.last_query
+            && options == &log.last_options
+            && filter == &log.last_filter
+            && expanded_messages == &log.last_expanded
+            && max_messages == log.last_max_messages
+        {
+            if let Some(rendered) = &log.cur_rendered {
+                return rendered.clone();
+            }
+        }
+        log.last_query = Some(query);
+        log.last_options = options.clone();
+        log.last_filter = filter.clone();
+        log.last_expanded = expanded_messages.clone();
+        log.last_max_messages = max_messages;
+        let mut print_options = PrintOptions::new(&log, options, filter);
+        print_options.expanded_messages = expanded_messages.clone();
+        print_options.max_messages = max_messages;
+        if let (Query::Span(span_id) | Query::Flat(span_id), true) =
+            (query, options.span_relative_times)
+        {
+            print_options.time_origin = log.first_timestamp(span_id);
+        }
+
+        let mut output = Rendered {
+            num_messages: log.messages.len(),
+            ..Rendered::default()
+        };
+
+        let (span_to_print, range) = match query {
+            Query::All => (log.root_span, None),
+            Query::Span(span) => (span, None),
+            Query::Flat(span) => {
+                print_options.flat = true;
+                (span, None)
+            }
+            Query::Merged(span) => {
+                for span in log.identical_spans(span) {
+                    if output.truncated {
+                        break;
+                    }
+                    print_span_tree(&log, &mut output, 0, span, None, &print_options);
+                }
+                let result = Arc::new(output);
+                log.cur_rendered = Some(result.clone());
+                return result;
+            }
+        };
+
+        print_span_tree(&log, &mut output, 0, span_to_print, range, &print_options);
+
+        let result = Arc::new(output);
+        log.cur_rendered = Some(result.clone());
+        result
+    }
+}
+
+impl Default for Logs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogsInner {
+    pub fn new() -> Self {
+        const ROOT_SPAN: SpanId = 0;
+        const JSON_MESSAGE_KEY: &str = "message";
+        const JSON_SPAN_NAME_KEY: &str = "name";
+        const ROOT_SPAN_NAME: &str = "<all spans>";
+
+        let empty = IString(Arc::from(""));
+
+        let mut this = Self {
+            root_span: ROOT_SPAN,
+            spans: BTreeMap::new(),
+            messages: BTreeMap::new(),
+            last_query: None,
+            last_options: DisplayOptions::default(),
+            last_max_messages: None,
+            last_filter: Filter::default(),
+            last_expanded: BTreeSet::new(),
+            cur_rendered: None,
+            next_span_id: 1,
+            next_message_id: 0,
+            field_keys: HashSet::new(),
+            spans_by_key: HashMap::new(),
+            intern_values: true,
+            parse_errors: 0,
+            bad_lines: Vec::new(),
+            plain_text_fallback: false,
+            timestamp_key: JSON_TIMESTAMP_KEY.to_owned(),
+            spans_innermost_first: false,
+            time_range: None,
+            max_spans: None,
+            overflowed_messages: 0,
+            i_message: empty.clone(),
+            i_name: empty.clone(),
+            i_empty: empty,
+            interner: Interner::default(),
+        };
+
+        let root_span = SpanEntry {
+            parent: None,
+            name: this.interner.intern_str(ROOT_SPAN_NAME),
+            fields: PseudoMap::default(),
+            events: Vec::new(),
+            json_subspan_keys: HashMap::new(),
+            start: None,
+        };
+        this.spans.insert(ROOT_SPAN, root_span);
+
+        this.i_message = this.interner.intern_str(JSON_MESSAGE_KEY);
+        this.i_name = this.interner.intern_str(JSON_SPAN_NAME_KEY);
+        this.i_empty = this.interner.intern_str("");
+
+        this
+    }
+
+    pub fn add_json_message(&mut self, input: &str) {
+        self.add_json_line(None, None, input)
+    }
+
+    pub fn add_json_line(&mut self, file: Option<&Arc<Path>>, line: Option<u64>, input: &str) {
+        // Some producers write several objects to a line, so take all of them
+        let mut stream = serde_json::Deserializer::from_str(input).into_iter::<JsonMessage>();
+        loop {
+            let offset = stream.byte_offset();
+            match stream.next() {
+                None => return,
+                Some(Ok(json_message)) => self.add_parsed_message(json_message),
+                Some(Err(e)) => {
+                    let input = input[offset..].trim();
+                    self.parse_errors += 1;
+                    if self.bad_lines.len() < MAX_BAD_LINES {
+                        self.bad_lines.push(ParseError::new(file, line, input, &e));
+                    }
+                    if self.plain_text_fallback {
+                        self.add_plain_message(input);
+                    } else {
+                        eprintln!("WARN: failed to parse log line: {}\n{}", input, e);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    fn add_parsed_message(&mut self, mut json_message: JsonMessage) {
+        if self.spans_innermost_first {
+            json_message.spans.reverse();
+        }
+        let mut cur_span_id = self.root_span;
+        for json_span in json_message.spans {
+            let out_of_spans = self.max_spans.is_some_and(|max| self.spans.len() >= max);
+            let i_json_span = if !out_of_spans {
+                self.interner.intern_pseudo(json_span.0)
+            } else if let Some(i_json_span) = self.interner.get_pseudo(&json_span.0) {
+                // Only a span we already have will do, so don't intern anything new for it
+                i_json_span
+            } else {
+                // Something about it is new, so it can't be a span we already have
+                self.overflowed_messages += 1;
+                break;
+            };
+            let cur_span = self.spans.get_mut(&cur_span_id).unwrap();
+            cur_span_id = match cur_span.json_subspan_keys.entry(i_json_span) {
+                std::collections::hash_map::Entry::Occupied(e) => *e.get(),
+                std::collections::hash_map::Entry::Vacant(_) if out_of_spans => {
+                    // Out of spans, so the message goes in the innermost one it already has
+                    self.overflowed_messages += 1;
+                    break;
+                }
+                std::collections::hash_map::Entry::Vacant(e) => {
+                    // Make a new span
+                    let new_span_id = self.next_span_id;
+                    self.next_span_id += 1;
+
+                    // This is done in a weird way because if you have a span with "name" key
+                    // then tracing will emit two keys with the string "name". We assume the
+                    // one we want is first.
+                    let name = if let Some((k, IValue::S(name))) = e.key().vals.last() {
+                        if k == &self.i_name {
+                            name.clone()
+                        } else {
+                            self.i_empty.clone()
+                        }
+                    } else {
+                        self.i_empty.clone()
+                    };
+                    let mut fields = e.key().clone();
+                    if !name.is_empty() {
+                        fields.vals.pop();
+                    }
+                    let new_span = SpanEntry {
+                        parent: Some(cur_span_id),
+                        name,
+                        fields,
+                        events: Vec::new(),
+                        json_subspan_keys: HashMap::new(),
+                        start: None,
+                    };
+
+                    self.spans_by_key
+                        .entry((new_span.name.clone(), new_span.fields.clone()))
+                        .or_default()
+                        .push(new_span_id);
+                    e.insert(new_span_id);
+                    cur_span.events.push(EventEntry::Span(new_span_id));
+                    self.spans.insert(new_span_id, new_span);
+
+                    new_span_id
+                }
+            };
+        }
+
+        let span = self.spans.get_mut(&cur_span_id).unwrap();
+        let new_message_id = self.next_message_id;
+        self.next_message_id += 1;
+        let level = parse_level(json_message.level);
+        let raw_level = if level.is_none() && !json_message.level.is_empty() {
+            Some(self.interner.intern_str(json_message.level))
+        } else {
+            None
+        };
+        let lifecycle = SpanLifecycle::from_fields(&json_message.fields);
+        let new_message = MessageEntry {
+            lifecycle,
+            timestamp: json_message
+                .others
+                .iter()
+                .find(|(key, _)| *key == self.timestamp_key)
+                .and_then(|(_, timestamp)| timestamp.parse().ok()),
+            level,
+            raw_level,
+            target: self.interner.intern_str(json_message.target),
+            fields: if self.intern_values {
+                self.interner.intern_pseudo(json_message.fields)
+            } else {
+                self.interner.intern_keys_only(json_message.fields)
+            },
+        };
+        for (k, _v) in &new_message.fields.vals {
+            if !self.field_keys.contains(k) {
+                self.field_keys.insert(k.clone());
+            }
+        }
+        let timestamp = new_message.timestamp;
+        self.messages.insert(new_message_id, new_message);
+        span.events.push(EventEntry::Message(new_message_id));
+
+        if let Some(timestamp) = timestamp {
+            self.time_range = Some(match self.time_range {
+                Some((first, last)) => (first.min(timestamp), last.max(timestamp)),
+                None => (timestamp, timestamp),
+            });
+            // Pull the start of this span and its parents back, stopping once
+            // one already started earlier (since then all its parents did too)
+            let mut span_id = Some(cur_span_id);
+            while let Some(id) = span_id {
+                let span = self.spans.get_mut(&id).unwrap();
+                if span.start.is_some_and(|start| start <= timestamp) {
+                    break;
+                }
+                span.start = Some(timestamp);
+                span_id = span.parent;
+            }
+        }
+    }
+
+    /// Add a line that isn't JSON as a message with just the line as its text.
+    fn add_plain_message(&mut self, input: &str) {
+        let new_message_id = self.next_message_id;
+        self.next_message_id += 1;
+        let new_message = MessageEntry {
+            lifecycle: None,
+            timestamp: None,
+            level: None,
+            raw_level: None,
+            target: self.i_empty.clone(),
+            fields: PseudoMap {
+                vals: vec![(
+                    self.i_message.clone(),
+                    IValue::S(self.interner.intern_str(input)),
+                )],
+            },
+        };
+        self.field_keys.insert(self.i_message.clone());
+        self.messages.insert(new_message_id, new_message);
+        let root = self.spans.get_mut(&self.root_span).unwrap();
+        root.events.push(EventEntry::Message(new_message_id));
+    }
+}
+
+impl LogsInner {
+    /// Permanently throw away every message that doesn't match the filter,
+    /// along with any span that ends up with nothing in it.
+    ///
+    /// The root span is always kept.
+    pub fn retain_matching(&mut self, filter: &Filter, options: &DisplayOptions) {
+        let matcher = filter.matcher(self, options);
+        let error_spans = filter
+            .errors_only_spans
+            .then(|| self.error_spans(options.unknown_level.as_level()));
+        let mut keep_messages = HashSet::new();
+        let mut keep_spans = HashSet::new();
+        keep_spans.insert(self.root_span);
+        self.find_matching(
+            self.root_span,
+            &matcher,
+            error_spans.as_ref(),
+            &mut keep_messages,
+            &mut keep_spans,
+        );
+
+        self.messages.retain(|id, _| keep_messages.contains(id));
+        self.spans.retain(|id, _| keep_spans.contains(id));
+        let timestamps = || self.messages.values().filter_map(|entry| entry.timestamp);
+        self.time_range = timestamps().min().zip(timestamps().max());
+        // Children before parents, like `matching_counts`
+        let span_ids = self.spans.keys().rev().copied().collect::<Vec<_>>();
+        for span_id in span_ids {
+            let span = &self.spans[&span_id];
+            let start = span
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    EventEntry::Message(id) => self.messages[id].timestamp,
+                    EventEntry::Span(id) => self.spans[id].start,
+                })
+                .min();
+            self.spans.get_mut(&span_id).unwrap().start = start;
+        }
+        for span in self.spans.values_mut() {
+            span.events.retain(|event| match event {
+                EventEntry::Message(id) => keep_messages.contains(id),
+                EventEntry::Span(id) => keep_spans.contains(id),
+            });
+            span.json_subspan_keys
+                .retain(|_, id| keep_spans.contains(id));
+        }
+        self.spans_by_key.retain(|_, ids| {
+            ids.retain(|id| keep_spans.contains(id));
+            !ids.is_empty()
+        });
+        self.cur_rendered = None;
+    }
+
+    /// Split spans into groups of repeats, like the same span made on every
+    /// trip around a loop: spans with the same parent and name, and the same
+    /// fields other than `varying_keys` (or whatever their fields, if that's
+    /// empty). Groups keep the order of `span_ids`, going by their first span.
+    pub fn group_repeated_spans(
+        &self,
+        span_ids: &[SpanId],
+        varying_keys: &[&str],
+    ) -> Vec<Vec<SpanId>> {
+        let mut groups = Vec::<Vec<SpanId>>::new();
+        let mut group_of = HashMap::new();
+        for &span_id in span_ids {
+            let span = &self.spans[&span_id];
+            let fields = if varying_keys.is_empty() {
+                Vec::new()
+            } else {
+                span.fields
+                    .vals
+                    .iter()
+                    .filter(|(k, _)| !varying_keys.contains(&&**k))
+                    .collect()
+            };
+            let key = (span.parent, &span.name, fields);
+            let idx = *group_of.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[idx].push(span_id);
+        }
+        groups
+    }
+
+    /// Every span with the same name and fields as this one (including itself).
+    pub fn identical_spans(&self, span_id: SpanId) -> Vec<SpanId> {
+        let span = &self.spans[&span_id];
+        // The root isn't indexed, since it's one of a kind
+        self.spans_by_key
+            .get(&(span.name.clone(), span.fields.clone()))
+            .cloned()
+            .unwrap_or_else(|| vec![span_id])
+    }
+
+    /// The spans with an ERROR message somewhere in their subtree.
+    pub fn error_spans(&self, unknown_level: Option<Level>) -> HashSet<SpanId> {
+        let mut error_spans = HashSet::new();
+        // Children before parents, like `matching_counts`
+        for (span_id, span) in self.spans.iter().rev() {
+            let has_error = span.events.iter().any(|event| match event {
+                EventEntry::Message(id) => {
+                    self.messages[id].level.or(unknown_level) == Some(Level::ERROR)
+                }
+                EventEntry::Span(id) => error_spans.contains(id),
+            });
+            if has_error {
+                error_spans.insert(*span_id);
+            }
+        }
+        error_spans
+    }
+
+    /// How many messages match the filter in each span, and in its whole subtree.
+    pub fn matching_counts(&self, matcher: &FilterMatcher) -> HashMap<SpanId, MessageCounts> {
+        let mut counts = HashMap::<SpanId, MessageCounts>::new();
+        // Subspans are always created after their parents, so walking backwards
+        // means every child has been counted by the time its parent needs it.
+        for (span_id, span) in self.spans.iter().rev() {
+            let mut count = MessageCounts::default();
+            for event in &span.events {
+                match event {
+                    EventEntry::Message(id) => {
+                        let matches = matcher.matches(&self.messages[id]) as usize;
+                        count.direct += matches;
+                        count.total += matches;
+                    }
+                    EventEntry::Span(id) => count.total += counts[id].total,
+                }
+            }
+            counts.insert(*span_id, count);
+        }
+        counts
+    }
+
+    /// How many messages `matcher` lets through under each span (subspans
+    /// included) in each of `num_buckets` equal stretches of the logs' whole
+    /// time range. Messages without a timestamp aren't counted.
+    pub fn message_rates(
+        &self,
+        matcher: &FilterMatcher,
+        num_buckets: usize,
+    ) -> HashMap<SpanId, Vec<usize>> {
+        let mut rates = HashMap::<SpanId, Vec<usize>>::new();
+        let num_buckets = num_buckets.max(1);
+        let Some((start, end)) = self.time_range else {
+            return rates;
+        };
+        let range_ms = (end - start).num_milliseconds().max(1);
+        // Subspans are always created after their parents, see `matching_counts`
+        for (span_id, span) in self.spans.iter().rev() {
+            let mut buckets = vec![0; num_buckets];
+            for event in &span.events {
+                match event {
+                    EventEntry::Message(id) => {
+                        let entry = &self.messages[id];
+                        let Some(timestamp) = entry.timestamp else {
+                            continue;
+                        };
+                        if !matcher.matches(entry) {
+                            continue;
+                        }
+                        let offset_ms = (timestamp - start).num_milliseconds().clamp(0, range_ms);
+                        let bucket =
+                            (offset_ms as u128 * num_buckets as u128 / range_ms as u128) as usize;
+                        buckets[bucket.min(num_buckets - 1)] += 1;
+                    }
+                    EventEntry::Span(id) => {
+                        for (bucket, count) in buckets.iter_mut().zip(&rates[id]) {
+                            *bucket += count;
+                        }
+                    }
+                }
+            }
+            rates.insert(*span_id, buckets);
+        }
+        rates
+    }
+
+    /// The child of a span with the most messages under it (counting the ones
+    /// `matcher` lets through).
+    pub fn busiest_child(&self, span_id: SpanId, matcher: &FilterMatcher) -> Option<SpanId> {
+        let counts = self.matching_counts(matcher);
+        self.spans[&span_id]
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                EventEntry::Span(id) if counts[id].total > 0 => Some(*id),
+                _ => None,
+            })
+            // The first of any ties, so it's stable
+            .min_by_key(|id| std::cmp::Reverse(counts[id].total))
+    }
+
+    /// The span the first ERROR message is directly in.
+    pub fn first_error_span(&self, unknown_level: Option<Level>) -> Option<SpanId> {
+        let (first_error, _) = self
+            .messages
+            .iter()
+            .find(|(_, entry)| entry.level.or(unknown_level) == Some(Level::ERROR))?;
+        self.spans.iter().find_map(|(span_id, span)| {
+            span.events
+                .iter()
+                .any(|event| matches!(event, EventEntry::Message(id) if id == first_error))
+                .then_some(*span_id)
+        })
+    }
+
+    /// Group the messages that match the filter by when they happened, in order.
+    ///
+    /// Messages without a timestamp are left out.
+    pub fn time_buckets(&self, options: &PrintOptions, size: BucketSize) -> Vec<TimeBucket> {
+        let secs = size.secs();
+        let mut buckets = BTreeMap::new();
+        for (id, entry) in &self.messages {
+            let timestamp = match entry.timestamp {
+                Some(timestamp) => timestamp,
+                None => continue,
+            };
+            if !options.filter.matches(entry) {
+                continue;
+            }
+            let key = timestamp.timestamp().div_euclid(secs);
+            let bucket = buckets.entry(key).or_insert_with(|| TimeBucket {
+                start: Local
+                    .timestamp_opt(key * secs, 0)
+                    .single()
+                    .unwrap_or(timestamp),
+                levels: LevelCounts::default(),
+                messages: Vec::new(),
+            });
+            bucket.levels.add(options.level_of(entry));
+            bucket.messages.push(*id);
+        }
+        buckets.into_values().collect()
+    }
+
+    /// A string that identifies a span by the names and fields of it and its
+    /// ancestors, like `shaving_yaks[yaks=3] > shave[yak=1]`.
+    ///
+    /// `resolve_span_path` turns it back into the span. A `>` that's part of a
+    /// name or value is written as `\>`.
+    pub fn span_path(&self, span_id: SpanId) -> String {
+        let mut segments = Vec::new();
+        let mut cur = Some(span_id);
+        while let Some(span_id) = cur {
+            let span = &self.spans[&span_id];
+            if span.parent.is_some() {
+                let mut segment = String::new();
+                print_span_path_segment(&mut segment, span);
+                segments.push(segment);
+            }
+            cur = span.parent;
+        }
+        segments.reverse();
+        segments.join(SPAN_PATH_SEPARATOR)
+    }
+
+    /// Find the span a `span_path` refers to (an empty path is the root).
+    pub fn resolve_span_path(&self, path: &str) -> Option<SpanId> {
+        let mut cur = self.root_span;
+        let segments = split_span_path(path)
+            .into_iter()
+            .map(str::trim)
+            .filter(|segment| !segment.is_empty());
+        for segment in segments {
+            cur = self.spans[&cur]
+                .events
+                .iter()
+                .find_map(|event| match event {
+                    EventEntry::Span(id) => {
+                        let mut printed = String::new();
+                        print_span_path_segment(&mut printed, &self.spans[id]);
+                        (printed == segment).then_some(*id)
+                    }
+                    EventEntry::Message(_) => None,
+                })?;
+        }
+        Some(cur)
+    }
+
+    /// Everything under a span written back out in the tracing JSON format, one
+    /// message per line, with `spans` leading all the way down from the root.
+    pub fn export_jsonl(&self, span_id: SpanId) -> String {
+        let mut path = Vec::new();
+        let mut cur = Some(span_id);
+        while let Some(id) = cur {
+            let span = &self.spans[&id];
+            if span.parent.is_some() {
+                path.push(id);
+            }
+            cur = span.parent;
+        }
+        path.reverse();
+
+        let mut output = String::new();
+        // The spans being walked through, and the next event to look at in each
+        let mut to_visit = vec![(span_id, 0)];
+        while let Some((cur, next_event)) = to_visit.last_mut() {
+            let events = &self.spans[&*cur].events;
+            let Some(event) = events.get(*next_event) else {
+                to_visit.pop();
+                if !to_visit.is_empty() {
+                    path.pop();
+                }
+                continue;
+            };
+            *next_event += 1;
+            match event {
+                EventEntry::Message(id) => self.write_json_message(&mut output, id, &path),
+                EventEntry::Span(id) => {
+                    path.push(*id);
+                    to_visit.push((*id, 0));
+                }
+            }
+        }
+        output
+    }
+
+    /// The messages under some spans as CSV, with the usual columns followed by
+    /// one for each of `columns` (left blank where a message doesn't have it).
+    pub fn export_csv(
+        &self,
+        span_ids: &[SpanId],
+        columns: &[String],
+        matcher: &FilterMatcher,
+    ) -> String {
+        // A key that was never interned can't be on anything, so it's always blank
+        let column_keys = columns
+            .iter()
+            .map(|key| self.interner.get(key))
+            .collect::<Vec<_>>();
+        let mut output = String::new();
+        for (i, name) in ["timestamp", "level", "target", "span", "message"]
+            .into_iter()
+            .chain(columns.iter().map(|key| &**key))
+            .enumerate()
+        {
+            if i != 0 {
+                output.push(',');
+            }
+            write_csv_field(&mut output, name);
+        }
+        output.push('\n');
+
+        let find_value = |entry: &MessageEntry, key: &IString| {
+            let (_, val) = entry.fields.vals.iter().find(|(k, _)| k == key)?;
+            let mut printed = String::new();
+            print_val(&mut printed, 0, val, NumberFormat::Plain, false);
+            Some(printed)
+        };
+        for &span_id in span_ids {
+            // The spans being walked through, and the next event to look at in each
+            let mut to_visit = vec![(span_id, 0)];
+            let mut span_paths = HashMap::new();
+            while let Some((cur, next_event)) = to_visit.last_mut() {
+                let cur = *cur;
+                let Some(event) = self.spans[&cur].events.get(*next_event) else {
+                    to_visit.pop();
+                    continue;
+                };
+                *next_event += 1;
+                let message_id = match event {
+                    EventEntry::Message(id) => id,
+                    EventEntry::Span(id) => {
+                        to_visit.push((*id, 0));
+                        continue;
+                    }
+                };
+                let entry = &self.messages[message_id];
+                if !matcher.matches(entry) {
+                    continue;
+                }
+                let span_path = span_paths.entry(cur).or_insert_with(|| self.span_path(cur));
+                let message = matcher
+                    .message_keys
+                    .index(&entry.fields.vals)
+                    .and_then(|i| find_value(entry, &entry.fields.vals[i].0))
+                    .unwrap_or_default();
+                for (i, cell) in [
+                    &format_export_timestamp(entry),
+                    &export_level(entry),
+                    &*entry.target,
+                    &**span_path,
+                    &message,
+                ]
+                .into_iter()
+                .enumerate()
+                {
+                    if i != 0 {
+                        output.push(',');
+                    }
+                    write_csv_field(&mut output, cell);
+                }
+                for key in &column_keys {
+                    output.push(',');
+                    let value = key.as_ref().and_then(|key| find_value(entry, key));
+                    write_csv_field(&mut output, value.as_deref().unwrap_or_default());
+                }
+                output.push('\n');
+            }
+        }
+        output
+    }
+
+    fn write_json_message(&self, output: &mut String, message_id: &MessageId, spans: &[SpanId]) {
+        let entry = &self.messages[message_id];
+        write!(output, "{{").unwrap();
+        write_json_str(output, &self.timestamp_key);
+        write!(output, ":").unwrap();
+        write_json_str(output, &format_export_timestamp(entry));
+        write!(output, ",\"level\":").unwrap();
+        write_json_str(output, &export_level(entry));
+        write!(output, ",\"fields\":").unwrap();
+        write_json_object(output, entry.fields.vals.iter().map(|(k, v)| (k, v)));
+        write!(output, ",\"target\":").unwrap();
+        write_json_str(output, &entry.target);
+        write!(output, ",\"spans\":[").unwrap();
+        for (i, span_id) in spans.iter().enumerate() {
+            if i != 0 {
+                write!(output, ",").unwrap();
+            }
+            let span = &self.spans[span_id];
+            // The name goes last, like tracing-subscriber does it
+            let name = IValue::S(span.name.clone());
+            let name = (!span.name.is_empty()).then_some((&self.i_name, &name));
+            write_json_object(
+                output,
+                span.fields.vals.iter().map(|(k, v)| (k, v)).chain(name),
+            );
+        }
+        writeln!(output, "]}}").unwrap();
+    }
+
+    /// Roughly how much memory everything is taking up. These are lower bounds
+    /// that ignore allocator overhead and spare capacity.
+    pub fn memory_estimate(&self) -> MemoryEstimate {
+        use std::mem::size_of;
+        // BTreeMap nodes are about this much per entry on top of the key and value
+        const PER_MAP_ENTRY: usize = 16;
+
+        let fields_size = |fields: &PseudoMap<IString, IValue>| -> usize {
+            fields.vals.len() * size_of::<(IString, IValue)>()
+        };
+        let messages = self
+            .messages
+            .values()
+            .map(|entry| {
+                let uninterned = entry
+                    .fields
+                    .vals
+                    .iter()
+                    .map(|(_k, v)| match v {
+                        // See `Interner::intern_keys_only`
+                        IValue::U(s) => s.len() + 16,
+                        _ => 0,
+                    })
+                    .sum::<usize>();
+                size_of::<(MessageId, MessageEntry)>()
+                    + PER_MAP_ENTRY
+                    + fields_size(&entry.fields)
+                    + uninterned
+            })
+            .sum();
+        let spans = self
+            .spans
+            .values()
+            .map(|span| {
+                size_of::<(SpanId, SpanEntry)>()
+                    + PER_MAP_ENTRY
+                    + fields_size(&span.fields)
+                    + span.events.len() * size_of::<EventEntry>()
+                    + span
+                        .json_subspan_keys
+                        .keys()
+                        .map(|key| {
+                            size_of::<(PseudoMap<IString, IValue>, SpanId)>() + fields_size(key)
+                        })
+                        .sum::<usize>()
+            })
+            .sum();
+        let rendered = self.cur_rendered.as_ref().map_or(0, |rendered| {
+            rendered.text.len() + rendered.lines.len() * size_of::<RenderedLine>()
+        });
+        MemoryEstimate {
+            strings: self.interner.memory_estimate(),
+            messages,
+            spans,
+            rendered,
+        }
+    }
+
+    /// The earliest time of any message under a span.
+    pub fn first_timestamp(&self, span_id: SpanId) -> Option<DateTime<Local>> {
+        self.spans[&span_id].start
+    }
+
+    /// Aggregate stats about everything under a span.
+    pub fn span_summary(&self, span_id: SpanId) -> SpanSummary {
+        let mut summary = SpanSummary::default();
+        let mut field_keys = HashSet::new();
+        let mut numeric_fields = HashMap::<IString, FieldStats>::new();
+        let top_span = span_id;
+        let mut to_visit = vec![span_id];
+        while let Some(span_id) = to_visit.pop() {
+            for event in &self.spans[&span_id].events {
+                let entry = match event {
+                    EventEntry::Message(id) => &self.messages[id],
+                    EventEntry::Span(id) => {
+                        to_visit.push(*id);
+                        continue;
+                    }
+                };
+                if entry.lifecycle.is_some() {
+                    continue;
+                }
+                summary.messages += 1;
+                summary.direct_messages += (span_id == top_span) as usize;
+                match entry.level {
+                    Some(Level::ERROR) => summary.errors += 1,
+                    Some(Level::WARN) => summary.warnings += 1,
+                    _ => {}
+                }
+                if let Some(timestamp) = entry.timestamp {
+                    summary.first = Some(summary.first.map_or(timestamp, |t| t.min(timestamp)));
+                    summary.last = Some(summary.last.map_or(timestamp, |t| t.max(timestamp)));
+                }
+                for (k, v) in &entry.fields.vals {
+                    if k == &self.i_message {
+                        continue;
+                    }
+                    field_keys.insert(k.clone());
+                    let number = match v {
+                        IValue::I(i) => *i as f64,
+                        IValue::F(f) => f.0,
+                        _ => continue,
+                    };
+                    numeric_fields
+                        .entry(k.clone())
+                        .or_insert_with(|| FieldStats::new(k.clone()))
+                        .add(number);
+                }
+            }
+        }
+        summary.field_keys = field_keys.into_iter().collect();
+        summary.field_keys.sort_by_key(|key| key.0.clone());
+        summary.numeric_fields = numeric_fields.into_values().collect();
+        summary
+            .numeric_fields
+            .sort_by_key(|stats| stats.key.0.clone());
+        summary
+    }
+
+    /// Returns whether anything in this span matched.
+    fn find_matching(
+        &self,
+        span_id: SpanId,
+        matcher: &FilterMatcher,
+        error_spans: Option<&HashSet<SpanId>>,
+        keep_messages: &mut HashSet<MessageId>,
+        keep_spans: &mut HashSet<SpanId>,
+    ) -> bool {
+        // Every span under this one, parents before children. This is a loop
+        // instead of recursion so that absurdly deep logs can't blow the stack.
+        let mut to_check = vec![span_id];
+        let mut i = 0;
+        while i < to_check.len() {
+            for event in &self.spans[&to_check[i]].events {
+                if let EventEntry::Span(id) = event {
+                    if error_spans.is_none_or(|spans| spans.contains(id)) {
+                        to_check.push(*id);
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        // Children before parents, so each span knows whether its children matched
+        let mut matched = HashSet::new();
+        for span_id in to_check.iter().rev() {
+            let mut any_matched = false;
+            for event in &self.spans[span_id].events {
+                match event {
+                    EventEntry::Message(id) => {
+                        if matcher.matches(&self.messages[id]) {
+                            keep_messages.insert(*id);
+                            any_matched = true;
+                        }
+                    }
+                    EventEntry::Span(id) => {
+                        if matched.contains(id) {
+                            keep_spans.insert(*id);
+                            any_matched = true;
+                        }
+                    }
+                }
+            }
+            if any_matched {
+                matched.insert(*span_id);
+            }
+        }
+        matched.contains(&span_id)
+    }
+}
+
+/// Parse a level, accepting any casing and some common aliases other loggers use.
+pub fn parse_level(input: &str) -> Option<Level> {
+    match &*input.trim().to_ascii_lowercase() {
+        "error" | "err" | "fatal" => Some(Level::ERROR),
+        "warn" | "warning" => Some(Level::WARN),
+        "info" => Some(Level::INFO),
+        "debug" => Some(Level::DEBUG),
+        "trace" => Some(Level::TRACE),
+        _ => None,
+    }
+}
+
+impl Default for LogsInner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A string interner that makes all strings share the same Arc,
+// so they can be compared by address and deduplicated.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    facts: HashMap<IString, StringInfo>,
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// Roughly how many bytes the interned strings take up.
+    pub fn memory_estimate(&self) -> usize {
+        // The Arc's counts, plus an entry in each of the tables
+        const PER_STRING: usize =
+            16 + std::mem::size_of::<Arc<str>>() + std::mem::size_of::<(IString, StringInfo)>();
+        self.strings
+            .iter()
+            .map(|string| string.len() + PER_STRING)
+            .sum()
+    }
+
+    /// Get the interned version of a string, if it has ever been interned.
+    pub fn get(&self, val: &str) -> Option<IString> {
+        self.strings.get(val).map(|k| IString(k.clone()))
+    }
+    /// How many lines an interned string has.
+    pub fn num_lines(&self, val: &IString) -> usize {
+        self.facts.get(val).map_or(1, |info| info.num_lines)
+    }
+    pub fn intern_str(&mut self, val: &str) -> IString {
+        if let Some(k) = self.strings.get(val) {
+            IString(k.clone())
+        } else {
+            let num_lines = val.lines().count();
+            let k = Arc::from(val);
+            self.strings.insert(Arc::clone(&k));
+            self.facts
+                .insert(IString(Arc::clone(&k)), StringInfo { num_lines });
+            IString(k)
+        }
+    }
+    pub fn intern_val(&mut self, val: Value) -> IValue {
         match val {
             Value::S(v) => IValue::S(self.intern_str(&v)),
             Value::B(v) => IValue::B(v),
             Value::I(v) => IValue::I(v),
             Value::F(v) => IValue::F(v),
+            Value::J(v) => IValue::J(self.intern_str(&v.0)),
         }
     }
     pub fn intern_pseudo(&mut self, val: PseudoMap<&str, Value>) -> PseudoMap<IString, IValue> {
@@ -365,27 +3366,308 @@ impl Interner {
                 .collect(),
         }
     }
+    /// Like `intern_pseudo`, but only looking it up. If any of its strings were
+    /// never interned then nothing can be equal to it, so that's `None`.
+    pub fn get_pseudo(&self, val: &PseudoMap<&str, Value>) -> Option<PseudoMap<IString, IValue>> {
+        let vals = val
+            .vals
+            .iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    Value::S(v) => IValue::S(self.get(v)?),
+                    Value::B(v) => IValue::B(*v),
+                    Value::I(v) => IValue::I(*v),
+                    Value::F(v) => IValue::F(*v),
+                    Value::J(v) => IValue::J(self.get(&v.0)?),
+                };
+                Some((self.get(k)?, v))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(PseudoMap { vals })
+    }
+    /// Like `intern_pseudo` but only the keys get interned, with string values
+    /// stored as they are (see `IValue::U`).
+    pub fn intern_keys_only(&mut self, val: PseudoMap<&str, Value>) -> PseudoMap<IString, IValue> {
+        PseudoMap {
+            vals: val
+                .vals
+                .into_iter()
+                .map(|(k, v)| {
+                    let v = match v {
+                        Value::S(v) => IValue::U(Arc::from(&*v)),
+                        v => self.intern_val(v),
+                    };
+                    (self.intern_str(k), v)
+                })
+                .collect(),
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Debug, Clone)]
 struct JsonMessage<'a> {
-    timestamp: &'a str,
     level: &'a str,
-    fields: PseudoMap<&'a str, Value>,
+    fields: PseudoMap<&'a str, Value<'a>>,
     target: &'a str,
-    #[serde(default)]
     spans: Vec<JsonSpan<'a>>,
+    /// The other top-level keys with string values, one of which is
+    /// hopefully the timestamp (see `LogsInner::timestamp_key`).
+    others: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+// This is written out by hand so that the timestamp can be under whatever key
+// the formatter likes, without `#[serde(flatten)]` buffering up the whole object.
+impl<'de: 'a, 'a> Deserialize<'de> for JsonMessage<'a> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(JsonMessageVisitor)
+    }
+}
+
+struct JsonMessageVisitor;
+
+impl<'de> serde::de::Visitor<'de> for JsonMessageVisitor {
+    type Value = JsonMessage<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tracing JSON message")
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        use serde::de::Error;
+        let mut level = None;
+        let mut fields = None;
+        let mut target = None;
+        let mut spans = Vec::new();
+        let mut others = Vec::new();
+        while let Some(JsonStr(key)) = map.next_key()? {
+            match &*key {
+                "level" => level = Some(map.next_value()?),
+                "fields" => fields = Some(map.next_value()?),
+                "target" => target = Some(map.next_value()?),
+                "spans" => spans = map.next_value()?,
+                _ => {
+                    if let MaybeJsonStr(Some(value)) = map.next_value()? {
+                        others.push((key, value));
+                    }
+                }
+            }
+        }
+        Ok(JsonMessage {
+            level: level.ok_or_else(|| A::Error::missing_field("level"))?,
+            fields: fields.ok_or_else(|| A::Error::missing_field("fields"))?,
+            target: target.ok_or_else(|| A::Error::missing_field("target"))?,
+            spans,
+            others,
+        })
+    }
+}
+
+/// A string that borrows from the input if it can.
+struct JsonStr<'a>(Cow<'a, str>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for JsonStr<'a> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_str(JsonStrVisitor)?
+            .0
+            .map(JsonStr)
+            .ok_or_else(|| serde::de::Error::custom("expected a string"))
+    }
+}
+
+/// A value that's kept if it's a string, and otherwise skipped over.
+struct MaybeJsonStr<'a>(Option<Cow<'a, str>>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for MaybeJsonStr<'a> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(JsonStrVisitor)
+    }
+}
+
+struct JsonStrVisitor;
+
+impl<'de> serde::de::Visitor<'de> for JsonStrVisitor {
+    type Value = MaybeJsonStr<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_borrowed_str<E: serde::de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(MaybeJsonStr(Some(Cow::Borrowed(v))))
+    }
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(MaybeJsonStr(Some(Cow::Owned(v.to_owned()))))
+    }
+    fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(MaybeJsonStr(Some(Cow::Owned(v))))
+    }
+    fn visit_bool<E: serde::de::Error>(self, _: bool) -> Result<Self::Value, E> {
+        Ok(MaybeJsonStr(None))
+    }
+    fn visit_i64<E: serde::de::Error>(self, _: i64) -> Result<Self::Value, E> {
+        Ok(MaybeJsonStr(None))
+    }
+    fn visit_u64<E: serde::de::Error>(self, _: u64) -> Result<Self::Value, E> {
+        Ok(MaybeJsonStr(None))
+    }
+    fn visit_f64<E: serde::de::Error>(self, _: f64) -> Result<Self::Value, E> {
+        Ok(MaybeJsonStr(None))
+    }
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(MaybeJsonStr(None))
+    }
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        while seq.next_element::<serde::de::IgnoredAny>()?.is_some() {}
+        Ok(MaybeJsonStr(None))
+    }
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        while map
+            .next_entry::<serde::de::IgnoredAny, serde::de::IgnoredAny>()?
+            .is_some()
+        {}
+        Ok(MaybeJsonStr(None))
+    }
+}
+
+/// A span in a message's `spans`, which is usually an object of its fields
+/// (including its `name`), but some formatters only write out the name.
+#[derive(Debug, Clone)]
+struct JsonSpan<'a>(PseudoMap<&'a str, Value<'a>>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for JsonSpan<'a> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(JsonSpanVisitor)
+    }
+}
+
+struct JsonSpanVisitor;
+
+impl<'de> serde::de::Visitor<'de> for JsonSpanVisitor {
+    type Value = JsonSpan<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a span object or a span name")
+    }
+
+    fn visit_borrowed_str<E: serde::de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(JsonSpan::named(Cow::Borrowed(v)))
+    }
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(JsonSpan::named(Cow::Owned(v.to_owned())))
+    }
+    fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(JsonSpan::named(Cow::Owned(v)))
+    }
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        serde::de::Visitor::visit_map(MyMapVisitor::new(), map).map(JsonSpan)
+    }
 }
 
-type JsonSpan<'a> = PseudoMap<&'a str, Value>;
+impl<'a> JsonSpan<'a> {
+    fn named(name: Cow<'a, str>) -> Self {
+        JsonSpan(PseudoMap {
+            vals: vec![("name", Value::S(name))],
+        })
+    }
+}
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
-#[serde(untagged)]
-pub enum Value {
-    S(String),
+/// A field value, borrowing from the input line where possible since it's
+/// about to be interned anyway.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Value<'a> {
+    S(Cow<'a, str>),
     B(bool),
     I(i64),
     F(EqF64),
+    J(JsonBlob),
+}
+
+/// A nested object or array (or a null), kept as compact JSON text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JsonBlob(String);
+
+// This is written out by hand because `#[serde(untagged)]` buffers up every
+// value before trying each variant, which was a big chunk of parsing time.
+impl<'de: 'a, 'a> Deserialize<'de> for Value<'a> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> serde::de::Visitor<'de> for ValueVisitor {
+    type Value = Value<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Value::B(v))
+    }
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Value::I(v))
+    }
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(i64::try_from(v).map_or(Value::F(EqF64(v as f64)), Value::I))
+    }
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Value::F(EqF64(v)))
+    }
+    fn visit_borrowed_str<E: serde::de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(Value::S(Cow::Borrowed(v)))
+    }
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Value::S(Cow::Owned(v.to_owned())))
+    }
+    fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Value::S(Cow::Owned(v)))
+    }
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Value::J(JsonBlob("null".to_owned())))
+    }
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        let val =
+            serde_json::Value::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))?;
+        Ok(Value::J(JsonBlob(val.to_string())))
+    }
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let val =
+            serde_json::Value::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+        Ok(Value::J(JsonBlob(val.to_string())))
+    }
+}
+
+/// Indent compact JSON text for reading, or leave it alone if it isn't JSON.
+pub fn pretty_json(compact: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(compact)
+        .and_then(|val| serde_json::to_string_pretty(&val))
+        .unwrap_or_else(|_| compact.to_owned())
+}
+
+/// If a string holds a JSON object or array, pretty-print it. That JSON can
+/// itself have been encoded as a string, a few times over, as happens when one
+/// program logs another's JSON logs as a message.
+pub fn decode_json_string(s: &str) -> Option<String> {
+    const MAX_DEPTH: usize = 4;
+    let mut text = Cow::Borrowed(s);
+    for _ in 0..MAX_DEPTH {
+        let trimmed = text.trim();
+        // Don't bother parsing things that can't be what we're looking for
+        if !trimmed.starts_with(['{', '[', '"']) {
+            return None;
+        }
+        match serde_json::from_str::<serde_json::Value>(trimmed).ok()? {
+            serde_json::Value::String(inner) => text = Cow::Owned(inner),
+            val @ (serde_json::Value::Object(_) | serde_json::Value::Array(_)) => {
+                return serde_json::to_string_pretty(&val).ok();
+            }
+            _ => return None,
+        }
+    }
+    None
 }
 
 /// An interned string, where hashing/equality or by-address
@@ -447,58 +3729,283 @@ impl PartialEq for EqF64 {
         self.0.to_le_bytes() == other.0.to_le_bytes()
     }
 }
-impl Eq for EqF64 {}
+impl Eq for EqF64 {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IValue {
+    S(IString),
+    /// A string that wasn't interned (see `LogsInner::intern_values`), so
+    /// unlike `S` it's compared by its text
+    U(Arc<str>),
+    B(bool),
+    I(i64),
+    F(EqF64),
+    /// Compact JSON text for a nested value
+    J(IString),
+}
+
+/// This is kind of a map but `tracing` can end up with `name` twice so it's just `Vec<(K, V)>`
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct PseudoMap<K, V> {
+    pub vals: Vec<(K, V)>,
+}
+
+impl<K, V> Default for PseudoMap<K, V> {
+    fn default() -> Self {
+        Self {
+            vals: Default::default(),
+        }
+    }
+}
+
+#[test]
+fn test_parse_json_message_no_spans() {
+    let input = r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"preparing to shave yaks","number_of_yaks":3},"target":"fmt_json"}"###;
+
+    let _json_message: JsonMessage = serde_json::from_str(input).unwrap();
+}
+
+#[test]
+fn test_parse_json_message_spans() {
+    let input = r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello! I'm gonna shave a yak","excitement":"yay!"},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"},{"yak":1,"name":"shave"}]}"###;
+
+    let _json_message: JsonMessage = serde_json::from_str(input).unwrap();
+}
+
+#[test]
+fn test_parse_json_message_dupe_name() {
+    let input = r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello! I'm gonna shave a yak","excitement":"yay!"},"target":"fmt_json::yak_shave","spans":[{"name": "real_name", "yaks":3,"name":"shaving_yaks"}]}"###;
+
+    let json_message: JsonMessage = serde_json::from_str(input).unwrap();
+    assert_eq!(
+        &json_message.spans[0].0.vals[0],
+        &("name", Value::S("real_name".into()))
+    );
+    assert_eq!(&json_message.spans[0].0.vals[1], &("yak", Value::I(3)));
+    assert_eq!(
+        &json_message.spans[0].0.vals[2],
+        &("name", Value::S("shaving_yaks".into()))
+    );
+}
+
+#[test]
+fn test_parse_json_message_nested() {
+    let input = r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"got request","body":{"yaks":[1,2],"owner":null},"tags":["a","b"]},"target":"fmt_json"}"###;
+
+    let json_message: JsonMessage = serde_json::from_str(input).unwrap();
+    assert_eq!(
+        &json_message.fields.vals[1],
+        &(
+            "body",
+            Value::J(JsonBlob(r#"{"owner":null,"yaks":[1,2]}"#.to_owned()))
+        )
+    );
+    assert_eq!(
+        &json_message.fields.vals[2],
+        &("tags", Value::J(JsonBlob(r#"["a","b"]"#.to_owned())))
+    );
+    assert_eq!(pretty_json(r#"["a","b"]"#), "[\n  \"a\",\n  \"b\"\n]");
+    assert_eq!(
+        decode_json_string(r#"{"a":1}"#).as_deref(),
+        Some("{\n  \"a\": 1\n}")
+    );
+    assert_eq!(
+        decode_json_string(r#""{\"a\":1}""#).as_deref(),
+        Some("{\n  \"a\": 1\n}")
+    );
+    assert_eq!(decode_json_string(r#""just a string""#), None);
+    assert_eq!(decode_json_string("{not json"), None);
+    assert_eq!(decode_json_string("3"), None);
+}
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum IValue {
-    S(IString),
-    B(bool),
-    I(i64),
-    F(EqF64),
-}
+#[test]
+fn test_parse_json_message_string_spans() {
+    let input = r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello!"},"target":"fmt_json::yak_shave","spans":["shaving_yaks",{"yak":1,"name":"shave"}]}"###;
 
-/// This is kind of a map but `tracing` can end up with `name` twice so it's just `Vec<(K, V)>`
-#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
-pub struct PseudoMap<K, V> {
-    pub vals: Vec<(K, V)>,
+    let json_message: JsonMessage = serde_json::from_str(input).unwrap();
+    assert_eq!(
+        json_message.spans[0].0.vals,
+        vec![("name", Value::S("shaving_yaks".into()))]
+    );
+
+    let logs = Logs::new();
+    logs.add_json_message(input);
+    logs.add_json_message(input);
+    let logs = logs.inner.lock().unwrap();
+    let root = &logs.spans[&logs.root_span];
+    assert_eq!(root.events.len(), 1);
+    let EventEntry::Span(outer) = root.events[0] else {
+        panic!("expected a span");
+    };
+    let outer = &logs.spans[&outer];
+    assert_eq!(&*outer.name, "shaving_yaks");
+    assert!(outer.fields.vals.is_empty());
+    let EventEntry::Span(inner) = outer.events[0] else {
+        panic!("expected a span");
+    };
+    assert_eq!(&*logs.spans[&inner].name, "shave");
+    assert_eq!(logs.spans[&inner].message_count(), 2);
 }
 
-impl<K, V> Default for PseudoMap<K, V> {
-    fn default() -> Self {
-        Self {
-            vals: Default::default(),
-        }
+#[test]
+fn test_export_jsonl_roundtrip() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"preparing to shave yaks","number_of_yaks":3},"target":"fmt_json"}"###,
+    );
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello! I'm gonna shave a yak.","excitement":"yay!","ratio":0.5,"body":{"fur":[1,2]}},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"},{"yak":1,"name":"shave"}]}"###,
+    );
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821546Z","level":"WARN","fields":{"message":"could not locate yak"},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"}]}"###,
+    );
+    let (shaving_yaks, exported) = {
+        let log = logs.inner.lock().unwrap();
+        let shaving_yaks = log.resolve_span_path("shaving_yaks[yaks=3]").unwrap();
+        (shaving_yaks, log.export_jsonl(shaving_yaks))
+    };
+    // The message outside the span is left out
+    assert_eq!(exported.lines().count(), 2);
+    assert_eq!(
+        exported.lines().next().unwrap(),
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello! I'm gonna shave a yak.","excitement":"yay!","ratio":0.5,"body":{"fur":[1,2]}},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"},{"yak":1,"name":"shave"}]}"###
+    );
+
+    // Reading the export back in gets the same thing out again
+    let reimported = Logs::new();
+    for line in exported.lines() {
+        reimported.add_json_message(line);
     }
+    let log = reimported.inner.lock().unwrap();
+    let root_exported = log.export_jsonl(log.root_span);
+    assert_eq!(root_exported, exported);
+    assert_eq!(
+        log.resolve_span_path("shaving_yaks[yaks=3]"),
+        Some(shaving_yaks)
+    );
 }
 
 #[test]
-fn test_parse_json_message_no_spans() {
-    let input = r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"preparing to shave yaks","number_of_yaks":3},"target":"fmt_json"}"###;
+fn test_span_path_roundtrip() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello!"},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"},{"yak":1,"name":"shave"}]}"###,
+    );
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello!"},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"},{"yak":2,"name":"shave"}]}"###,
+    );
+    let span_ids = logs
+        .inner
+        .lock()
+        .unwrap()
+        .spans
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    assert_eq!(span_ids.len(), 4);
 
-    let _json_message: JsonMessage = serde_json::from_str(input).unwrap();
+    let path = logs.span_path(span_ids[3]);
+    assert_eq!(path, "shaving_yaks[yaks=3] > shave[yak=2]");
+    assert_eq!(logs.resolve_span_path(&path), Some(span_ids[3]));
+    assert_eq!(
+        logs.resolve_span_path("shaving_yaks[yaks=3]>shave[yak=1]"),
+        Some(span_ids[2])
+    );
+    assert_eq!(
+        logs.resolve_span_path("shaving_yaks[yaks=3] > shave[yak=3]"),
+        None
+    );
+    assert_eq!(logs.resolve_span_path(""), Some(span_ids[0]));
+
+    // A `>` in a value doesn't split the path there
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello!"},"target":"fmt_json::yak_shave","spans":[{"cmp":"a>b","name":"compare"},{"path":"C:\\yaks\\","name":"open"}]}"###,
+    );
+    let span_id = *logs.inner.lock().unwrap().spans.keys().last().unwrap();
+    let path = logs.span_path(span_id);
+    assert_eq!(path, r"compare[cmp=a\>b] > open[path=C:\\yaks\\]");
+    assert_eq!(logs.resolve_span_path(&path), Some(span_id));
 }
 
 #[test]
-fn test_parse_json_message_spans() {
-    let input = r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello! I'm gonna shave a yak","excitement":"yay!"},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"},{"yak":1,"name":"shave"}]}"###;
+fn test_parse_level() {
+    for input in ["ERROR", "error", "Error", "err", "ERR", "fatal", "FATAL"] {
+        assert_eq!(parse_level(input), Some(Level::ERROR), "{input}");
+    }
+    for input in ["WARN", "warn", "Warn", "warning", "WARNING", "Warning"] {
+        assert_eq!(parse_level(input), Some(Level::WARN), "{input}");
+    }
+    for input in ["INFO", "info", "Info"] {
+        assert_eq!(parse_level(input), Some(Level::INFO), "{input}");
+    }
+    for input in ["DEBUG", "debug", "Debug"] {
+        assert_eq!(parse_level(input), Some(Level::DEBUG), "{input}");
+    }
+    for input in ["TRACE", "trace", "Trace"] {
+        assert_eq!(parse_level(input), Some(Level::TRACE), "{input}");
+    }
+    for input in ["", "notice", "critical", "information", "warn!"] {
+        assert_eq!(parse_level(input), None, "{input}");
+    }
+}
 
-    let _json_message: JsonMessage = serde_json::from_str(input).unwrap();
+#[test]
+fn test_color_rule_comparison() {
+    let status = IValue::I(503);
+    assert!(Comparison::Ge.compare(&status, "500"));
+    assert!(Comparison::Ge.compare(&status, " 503 "));
+    assert!(!Comparison::Lt.compare(&status, "500"));
+    assert!(Comparison::Eq.compare(&IValue::F(EqF64(2.0)), "2"));
+    assert!(Comparison::Ne.compare(&status, "abc"));
+
+    let method = IValue::S(IString(Arc::from("POST")));
+    assert!(Comparison::Eq.compare(&method, "POST"));
+    assert!(!Comparison::Eq.compare(&method, "post"));
+    assert!(Comparison::Contains.compare(&method, "OS"));
+    assert!(Comparison::Eq.compare(&IValue::B(true), "true"));
 }
 
 #[test]
-fn test_parse_json_message_dupe_name() {
-    let input = r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello! I'm gonna shave a yak","excitement":"yay!"},"target":"fmt_json::yak_shave","spans":[{"name": "real_name", "yaks":3,"name":"shaving_yaks"}]}"###;
+fn test_format_numbers() {
+    assert_eq!(NumberFormat::Plain.format_int(1234567), "1234567");
+    assert_eq!(NumberFormat::Plain.format_float(1234567.89), "1234567.89");
 
-    let json_message: JsonMessage = serde_json::from_str(input).unwrap();
+    assert_eq!(NumberFormat::English.format_int(1234567), "1,234,567");
     assert_eq!(
-        &json_message.spans[0].vals[0],
-        &("name", Value::S("real_name".to_owned()))
+        NumberFormat::English.format_float(1234567.89),
+        "1,234,567.89"
     );
-    assert_eq!(&json_message.spans[0].vals[1], &("yak", Value::I(3)));
     assert_eq!(
-        &json_message.spans[0].vals[2],
-        &("name", Value::S("shaving_yaks".to_owned()))
+        NumberFormat::German.format_float(1234567.89),
+        "1.234.567,89"
     );
+    assert_eq!(
+        NumberFormat::French.format_float(1234567.89),
+        "1 234 567,89"
+    );
+    assert_eq!(NumberFormat::Swiss.format_float(1234567.89), "1'234'567.89");
+
+    assert_eq!(NumberFormat::German.format_int(-1234), "-1.234");
+    assert_eq!(NumberFormat::German.format_int(123), "123");
+    assert_eq!(NumberFormat::German.format_int(-123456), "-123.456");
+    assert_eq!(NumberFormat::German.format_float(0.5), "0,5");
+    assert_eq!(NumberFormat::German.format_float(f64::NAN), "NaN");
+}
+
+#[test]
+fn test_uninterned_values() {
+    let input = r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"preparing to shave yaks","number_of_yaks":3},"target":"fmt_json"}"###;
+
+    let logs = Logs::new();
+    logs.set_intern_values(false);
+    logs.add_json_message(input);
+    logs.add_json_message(input);
+    let log = logs.inner.lock().unwrap();
+    assert!(log.interner.get("preparing to shave yaks").is_none());
+    let (first, second) = (&log.messages[&0].fields, &log.messages[&1].fields);
+    assert_eq!(first.vals[0].1, IValue::U("preparing to shave yaks".into()));
+    // Still equal, even though they're separate copies
+    assert_eq!(first, second);
 }
 
 use std::fmt;
@@ -585,3 +4092,795 @@ where
         deserializer.deserialize_map(MyMapVisitor::new())
     }
 }
+
+#[test]
+fn test_key_style() {
+    assert_eq!(KeyStyle::Full.apply("otel.scope.name"), "otel.scope.name");
+    assert_eq!(KeyStyle::LastSegment.apply("otel.scope.name"), "name");
+    assert_eq!(KeyStyle::Abbreviated.apply("otel.scope.name"), "o.s.name");
+    assert_eq!(KeyStyle::Abbreviated.apply("yak"), "yak");
+}
+
+#[test]
+fn test_not_json() {
+    let logs = Logs::new();
+    for i in 0..10 {
+        logs.add_json_message(&format!("2022-02-15 INFO plain old line {}", i));
+    }
+    assert_eq!(logs.num_messages(), 0);
+    assert!(logs.mostly_not_json());
+
+    logs.clear();
+    logs.set_plain_text_fallback(true);
+    logs.add_json_message("2022-02-15 INFO plain old line");
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"preparing to shave yaks"},"target":"fmt_json"}"###,
+    );
+    assert_eq!(logs.num_messages(), 2);
+    assert!(!logs.mostly_not_json());
+    let log = logs.inner.lock().unwrap();
+    let (k, v) = &log.messages[&0].fields.vals[0];
+    assert_eq!(k, &log.i_message);
+    assert_eq!(
+        v,
+        &IValue::S(log.interner.get("2022-02-15 INFO plain old line").unwrap())
+    );
+}
+
+#[test]
+fn test_target_glob() {
+    let glob = TargetGlob::new("myapp::db::*").unwrap();
+    assert!(glob.matches("myapp::db::pool"));
+    assert!(glob.matches("myapp::db::pool::conn"));
+    assert!(!glob.matches("myapp::db"));
+    assert!(!glob.matches("myapp::http::db::pool"));
+
+    let glob = TargetGlob::new("*::db::*").unwrap();
+    assert!(glob.matches("myapp::db::pool"));
+    assert!(glob.matches("a::b::db::c"));
+    assert!(!glob.matches("myapp::dbx::pool"));
+
+    let glob = TargetGlob::new("fmt_json").unwrap();
+    assert!(glob.matches("fmt_json"));
+    assert!(!glob.matches("fmt_json::yak_shave"));
+
+    let glob = TargetGlob::new("app?::[a-c!]*").unwrap();
+    assert!(glob.matches("app1::b"));
+    assert!(glob.matches("app2::!x"));
+    assert!(!glob.matches("app::b"));
+    assert!(!glob.matches("app1::d"));
+
+    let glob = TargetGlob::new("[!x]**").unwrap();
+    assert!(glob.matches("y"));
+    assert!(!glob.matches("xy"));
+    assert!(!glob.matches(""));
+
+    assert!(TargetGlob::new("app[").is_err());
+    assert!(TargetGlob::new("app[a-").is_err());
+    assert!(TargetGlob::new("app[z-a]").is_err());
+}
+
+#[test]
+fn test_deep_span_tree() {
+    // Deep enough to overflow the stack if printing recursed per level
+    const DEPTH: usize = 50_000;
+    let spans = vec![r#"{}"#; DEPTH].join(",");
+    let input = format!(
+        r#"{{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{{"message":"way down"}},"target":"fmt_json","spans":[{}]}}"#,
+        spans
+    );
+    let logs = Logs::new();
+    logs.add_json_message(&input);
+    assert_eq!(logs.inner.lock().unwrap().spans.len(), DEPTH + 1);
+
+    let rendered = logs.render_query(
+        Query::All,
+        &DisplayOptions::default(),
+        &Filter::default(),
+        &BTreeSet::new(),
+        None,
+    );
+    let line = rendered
+        .text
+        .lines()
+        .find(|line| line.contains("way down"))
+        .unwrap();
+    assert_eq!(line.len() - line.trim_start().len(), (DEPTH + 1) * 4);
+
+    let filter = Filter {
+        search: "way down".to_owned(),
+        ..Filter::default()
+    };
+    logs.retain_matching(&filter, &DisplayOptions::default());
+    let log = logs.inner.lock().unwrap();
+    assert_eq!(log.messages.len(), 1);
+    assert_eq!(log.spans.len(), DEPTH + 1);
+}
+
+#[test]
+fn test_span_start_times() {
+    let logs = Logs::new();
+    for input in [
+        r###"{"timestamp":"2022-02-15T18:47:12Z","level":"INFO","fields":{"message":"second"},"target":"fmt_json","spans":[{"name":"outer"},{"name":"inner"}]}"###,
+        r###"{"timestamp":"2022-02-15T18:47:11Z","level":"INFO","fields":{"message":"first"},"target":"fmt_json","spans":[{"name":"outer"}]}"###,
+    ] {
+        logs.add_json_message(input);
+    }
+    {
+        let log = logs.inner.lock().unwrap();
+        let start_of = |name: &str| {
+            let span = log.spans.values().find(|span| &*span.name == name).unwrap();
+            span.start.unwrap().timestamp()
+        };
+        assert_eq!(start_of("inner"), 1644950832);
+        assert_eq!(start_of("outer"), 1644950831);
+        assert_eq!(
+            log.first_timestamp(log.root_span).unwrap().timestamp(),
+            1644950831
+        );
+    }
+
+    let options = DisplayOptions {
+        span_start_times: true,
+        ..DisplayOptions::default()
+    };
+    let rendered = logs.render_query(
+        Query::All,
+        &options,
+        &Filter::default(),
+        &BTreeSet::new(),
+        None,
+    );
+    let header = rendered
+        .text
+        .lines()
+        .find(|line| line.contains("[inner]"))
+        .unwrap();
+    let start = header.split(" @ ").nth(1).unwrap();
+    assert_eq!(
+        start.parse::<DateTime<Local>>().unwrap().timestamp(),
+        1644950832
+    );
+}
+
+#[test]
+fn test_time_format() {
+    assert!(check_time_format("%H:%M:%S%.3f").is_ok());
+    assert!(check_time_format("%Y-%m-%d %H:%M").is_ok());
+    assert!(check_time_format("%Q").is_err());
+    assert!(check_time_format("%H:%").is_err());
+
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"hi"},"target":"fmt_json"}"###,
+    );
+    let render = |time_format: &str| {
+        let options = DisplayOptions {
+            time_format: time_format.to_owned(),
+            ..DisplayOptions::default()
+        };
+        let rendered = logs.render_query(
+            Query::All,
+            &options,
+            &Filter::default(),
+            &BTreeSet::new(),
+            None,
+        );
+        rendered
+            .text
+            .lines()
+            .find(|line| line.contains("hi"))
+            .unwrap()
+            .to_owned()
+    };
+    assert!(render("%S%.3f").contains("[10.821]"));
+    // A bad format falls back to the usual one
+    assert!(render("%Q").contains("[2022-02-1"));
+}
+
+#[test]
+fn test_concatenated_objects() {
+    let logs = Logs::new();
+    logs.add_json_message(concat!(
+        r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"one"},"target":"fmt_json"}"###,
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"WARN","fields":{"message":"two"},"target":"fmt_json"} "###,
+    ));
+    let log = logs.inner.lock().unwrap();
+    assert_eq!(log.parse_errors, 0);
+    let messages = log
+        .messages
+        .values()
+        .map(|entry| (entry.level, entry.fields.vals[0].1.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            (
+                Some(Level::INFO),
+                IValue::S(log.interner.get("one").unwrap())
+            ),
+            (
+                Some(Level::WARN),
+                IValue::S(log.interner.get("two").unwrap())
+            ),
+        ]
+    );
+    drop(log);
+
+    // Whatever parsed before junk at the end still counts
+    logs.add_json_message(concat!(
+        r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"three"},"target":"fmt_json"}"###,
+        "not json",
+    ));
+    let log = logs.inner.lock().unwrap();
+    assert_eq!(log.messages.len(), 3);
+    assert_eq!(log.parse_errors, 1);
+    assert_eq!(log.bad_lines[0].snippet, "not json");
+}
+
+#[test]
+fn test_timestamp_key() {
+    let input = r###"{"@timestamp":"2022-02-15T18:47:10.821315Z","timestamp":"nonsense","level":"INFO","fields":{"message":"one"},"target":"fmt_json","span":{"name":"yak"}}"###;
+
+    let logs = Logs::new();
+    logs.add_json_message(input);
+    logs.set_timestamp_key("@timestamp");
+    logs.add_json_message(input);
+
+    let log = logs.inner.lock().unwrap();
+    assert_eq!(log.parse_errors, 0);
+    let timestamps = log
+        .messages
+        .values()
+        .map(|entry| entry.timestamp)
+        .collect::<Vec<_>>();
+    let expected = "2022-02-15T18:47:10.821315Z"
+        .parse::<DateTime<Local>>()
+        .unwrap();
+    assert_eq!(timestamps, [None, Some(expected)]);
+
+    // Exporting writes it back under the same key
+    let exported = log.export_jsonl(log.root_span);
+    assert!(exported
+        .lines()
+        .nth(1)
+        .unwrap()
+        .starts_with(r#"{"@timestamp":"2022-02-15T18:47:10.821315Z","#));
+}
+
+#[test]
+fn test_render_max_messages() {
+    let logs = Logs::new();
+    for i in 0..10 {
+        logs.add_json_message(&format!(
+            r###"{{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{{"message":"msg{}"}},"target":"fmt_json"}}"###,
+            i
+        ));
+    }
+    let options = DisplayOptions::default();
+    let count_messages = |rendered: &Rendered| {
+        rendered
+            .lines
+            .iter()
+            .filter(|line| matches!(line.kind, LineKind::Message(_)))
+            .count()
+    };
+
+    let rendered = logs.render_query(
+        Query::All,
+        &options,
+        &Filter::default(),
+        &BTreeSet::new(),
+        Some(4),
+    );
+    assert!(rendered.truncated);
+    assert_eq!(count_messages(&rendered), 4);
+
+    // Asking for more re-renders instead of reusing the capped one
+    let rendered = logs.render_query(
+        Query::All,
+        &options,
+        &Filter::default(),
+        &BTreeSet::new(),
+        Some(10),
+    );
+    assert!(!rendered.truncated);
+    assert_eq!(count_messages(&rendered), 10);
+}
+
+#[test]
+fn test_spans_innermost_first() {
+    let outermost_first = r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello!"},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"},{"yak":1,"name":"shave"}]}"###;
+    let innermost_first = r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello!"},"target":"fmt_json::yak_shave","spans":[{"yak":1,"name":"shave"},{"yaks":3,"name":"shaving_yaks"}]}"###;
+
+    // The names of the spans down to the message, outermost first
+    let nesting = |logs: &Logs| {
+        let log = logs.inner.lock().unwrap();
+        let mut names = Vec::new();
+        let mut span_id = log.root_span;
+        while let Some(EventEntry::Span(sub_span)) = log.spans[&span_id].events.first() {
+            span_id = *sub_span;
+            names.push(log.spans[&span_id].name.to_string());
+        }
+        names
+    };
+
+    let logs = Logs::new();
+    logs.add_json_message(outermost_first);
+    assert_eq!(nesting(&logs), ["shaving_yaks", "shave"]);
+
+    let logs = Logs::new();
+    logs.set_spans_innermost_first(true);
+    logs.add_json_message(innermost_first);
+    assert_eq!(nesting(&logs), ["shaving_yaks", "shave"]);
+    logs.add_json_message(innermost_first);
+    let log = logs.inner.lock().unwrap();
+    assert_eq!(log.spans.len(), 3);
+    let EventEntry::Span(outer) = log.spans[&log.root_span].events[0] else {
+        panic!("expected a span");
+    };
+    let EventEntry::Span(inner) = log.spans[&outer].events[0] else {
+        panic!("expected a span");
+    };
+    assert_eq!(log.spans[&inner].message_count(), 2);
+    drop(log);
+
+    // Taken the wrong way around, it's nested backwards
+    let logs = Logs::new();
+    logs.add_json_message(innermost_first);
+    assert_eq!(nesting(&logs), ["shave", "shaving_yaks"]);
+}
+
+#[test]
+fn test_merged_spans() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"first"},"target":"fmt_json","spans":[{"name":"a"},{"yak":1,"name":"shave"}]}"###,
+    );
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"second"},"target":"fmt_json","spans":[{"name":"b"},{"yak":1,"name":"shave"}]}"###,
+    );
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"other yak"},"target":"fmt_json","spans":[{"name":"b"},{"yak":2,"name":"shave"}]}"###,
+    );
+    let shave = {
+        let log = logs.inner.lock().unwrap();
+        let shave = log.resolve_span_path("a > shave[yak=1]").unwrap();
+        assert_eq!(log.identical_spans(shave).len(), 2);
+        assert_eq!(log.identical_spans(log.root_span), [log.root_span]);
+        shave
+    };
+
+    let rendered = logs.render_query(
+        Query::Merged(shave),
+        &DisplayOptions::default(),
+        &Filter::default(),
+        &BTreeSet::new(),
+        None,
+    );
+    assert!(rendered.text.contains("first"));
+    assert!(rendered.text.contains("second"));
+    assert!(!rendered.text.contains("other yak"));
+    assert!(!rendered.truncated);
+}
+
+#[test]
+fn test_export_csv() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{"message":"preparing, \"carefully\"","number_of_yaks":3},"target":"fmt_json"}"###,
+    );
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"TRACE","fields":{"message":"hello!\nbye","yak":1},"target":"fmt_json::yak_shave","spans":[{"yaks":3,"name":"shaving_yaks"}]}"###,
+    );
+    let options = DisplayOptions {
+        shown_keys: Some(vec![
+            "yak".to_owned(),
+            "number_of_yaks".to_owned(),
+            "nope".to_owned(),
+        ]),
+        ..DisplayOptions::default()
+    };
+    let csv = logs.export_csv(Query::All, &options, &Filter::default());
+    assert_eq!(
+        csv,
+        "timestamp,level,target,span,message,yak,number_of_yaks,nope\n\
+         2022-02-15T18:47:10.821315Z,INFO,fmt_json,,\"preparing, \"\"carefully\"\"\",,3,\n\
+         2022-02-15T18:47:10.821495Z,TRACE,fmt_json::yak_shave,shaving_yaks[yaks=3],\"hello!\nbye\",1,,\n"
+    );
+}
+
+#[test]
+fn test_matching_counts() {
+    let logs = Logs::new();
+    for spans in [
+        r#"[{"name":"outer"}]"#,
+        r#"[{"name":"outer"}]"#,
+        r#"[{"name":"outer"},{"name":"inner"}]"#,
+    ] {
+        logs.add_json_message(&format!(
+            r###"{{"timestamp":"2022-02-15T18:47:10.821315Z","level":"INFO","fields":{{"message":"hi"}},"target":"fmt_json","spans":{}}}"###,
+            spans
+        ));
+    }
+    let log = logs.inner.lock().unwrap();
+    let counts = log.matching_counts(&Filter::default().matcher(&log, &DisplayOptions::default()));
+    let outer = log.resolve_span_path("outer").unwrap();
+    let inner = log.resolve_span_path("outer > inner").unwrap();
+    assert_eq!(
+        counts[&log.root_span],
+        MessageCounts {
+            direct: 0,
+            total: 3
+        }
+    );
+    assert_eq!(
+        counts[&outer],
+        MessageCounts {
+            direct: 2,
+            total: 3
+        }
+    );
+    assert_eq!(
+        counts[&inner],
+        MessageCounts {
+            direct: 1,
+            total: 1
+        }
+    );
+    assert_eq!(log.span_summary(outer).direct_messages, 2);
+}
+
+#[test]
+fn test_group_repeated_spans() {
+    let logs = Logs::new();
+    for (i, yak) in [(0, 1), (1, 1), (2, 2)] {
+        logs.add_json_message(&format!(
+            r###"{{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{{"message":"hi"}},"target":"fmt_json","spans":[{{"i":{i},"yak":{yak},"name":"shave"}}]}}"###
+        ));
+    }
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"hi"},"target":"fmt_json","spans":[{"name":"nap"}]}"###,
+    );
+    let log = logs.inner.lock().unwrap();
+    let span_ids = log
+        .spans
+        .keys()
+        .copied()
+        .filter(|&id| id != log.root_span)
+        .collect::<Vec<_>>();
+    assert_eq!(span_ids.len(), 4);
+    let sizes = |varying_keys: &[&str]| {
+        log.group_repeated_spans(&span_ids, varying_keys)
+            .iter()
+            .map(Vec::len)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(sizes(&[]), [3, 1]);
+    assert_eq!(sizes(&["i"]), [2, 1, 1]);
+    assert_eq!(sizes(&["i", "yak"]), [3, 1]);
+    assert_eq!(sizes(&["yak"]), [1, 1, 1, 1]);
+}
+
+#[test]
+fn test_compare_messages() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"shaved","yak":1,"razor":"sharp"},"target":"fmt_json"}"###,
+    );
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"shaved","yak":2,"nicks":3},"target":"fmt_json"}"###,
+    );
+    let message_ids = logs
+        .inner
+        .lock()
+        .unwrap()
+        .messages
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    let comparison = logs.compare_messages(&message_ids);
+    let summary = comparison
+        .iter()
+        .map(|field| (field.key.as_str(), field.differs))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            ("message", false),
+            ("yak", true),
+            ("razor", true),
+            ("nicks", true)
+        ]
+    );
+    assert_eq!(comparison[1].values, [Some("1".into()), Some("2".into())]);
+    assert_eq!(comparison[3].values, [None, Some("3".into())]);
+}
+
+#[test]
+fn test_compare_uninterned_messages() {
+    let logs = Logs::new();
+    logs.set_intern_values(false);
+    for yak in [1, 2] {
+        logs.add_json_message(&format!(
+            r###"{{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{{"message":"shaved","yak":{yak},"razor":"sharp"}},"target":"fmt_json"}}"###
+        ));
+    }
+    let message_ids = logs
+        .inner
+        .lock()
+        .unwrap()
+        .messages
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    let summary = logs
+        .compare_messages(&message_ids)
+        .into_iter()
+        .map(|field| (field.key, field.differs))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            ("message".to_owned(), false),
+            ("yak".to_owned(), true),
+            ("razor".to_owned(), false)
+        ]
+    );
+}
+
+#[test]
+fn test_message_rates() {
+    let logs = Logs::new();
+    for (time, message) in [("10", "early"), ("10", "early"), ("19", "late")] {
+        logs.add_json_message(&format!(
+            r###"{{"timestamp":"2022-02-15T18:47:{time}.000000Z","level":"INFO","fields":{{"message":"{message}"}},"target":"fmt_json","spans":[{{"name":"shave"}}]}}"###
+        ));
+    }
+    logs.add_json_message(
+        r###"{"level":"INFO","fields":{"message":"whenever"},"target":"fmt_json"}"###,
+    );
+    let log = logs.inner.lock().unwrap();
+    let matcher = Filter::default().matcher(&log, &DisplayOptions::default());
+    let rates = log.message_rates(&matcher, 3);
+    let shave = log.resolve_span_path("shave").unwrap();
+    assert_eq!(rates[&shave], [2, 0, 1]);
+    assert_eq!(rates[&log.root_span], [2, 0, 1]);
+}
+
+#[test]
+fn test_max_spans() {
+    let logs = Logs::new();
+    logs.set_max_spans(Some(3));
+    for i in 0..4 {
+        logs.add_json_message(&format!(
+            r###"{{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{{"message":"hi"}},"target":"fmt_json","spans":[{{"name":"shave_all"}},{{"i":{i},"name":"shave"}}]}}"###
+        ));
+    }
+    // A span that can't be made doesn't leave its strings behind either
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"hi"},"target":"fmt_json","spans":[{"name":"mow"}]}"###,
+    );
+    assert_eq!(logs.overflowed_messages(), 4);
+    let log = logs.inner.lock().unwrap();
+    // The root, shave_all, and the first shave
+    assert_eq!(log.spans.len(), 3);
+    assert_eq!(log.messages.len(), 5);
+    let shave_all = log.resolve_span_path("shave_all").unwrap();
+    assert_eq!(log.spans[&shave_all].message_count(), 3);
+    assert!(log.interner.get("mow").is_none());
+}
+
+#[test]
+fn test_flat_query() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"outer"},"target":"fmt_json","spans":[{"name":"a"}]}"###,
+    );
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"inner"},"target":"fmt_json","spans":[{"name":"a"},{"name":"b"}]}"###,
+    );
+    let a = logs.resolve_span_path("a").unwrap();
+    let rendered = logs.render_query(
+        Query::Flat(a),
+        &DisplayOptions::default(),
+        &Filter::default(),
+        &BTreeSet::new(),
+        None,
+    );
+    assert_eq!(rendered.lines.len(), 2);
+    assert!(rendered
+        .lines
+        .iter()
+        .all(|line| matches!(line.kind, LineKind::Message(_))));
+    assert!(rendered.text.lines().all(|line| !line.starts_with(' ')));
+    assert!(rendered.text.contains("inner"));
+}
+
+#[test]
+fn test_message_fallback_keys() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"yak":1,"body":"shaved"},"target":"fmt_json"}"###,
+    );
+    let render = |fallback_keys: &str| {
+        let options = DisplayOptions {
+            message_fallback_keys: fallback_keys.to_owned(),
+            ..DisplayOptions::default()
+        };
+        let rendered = logs.render_query(
+            Query::All,
+            &options,
+            &Filter::default(),
+            &BTreeSet::new(),
+            None,
+        );
+        let line = rendered.lines.last().unwrap();
+        rendered.line_text(line).trim().to_owned()
+    };
+    assert!(render("").ends_with("[yak = 1] [body = shaved]"));
+    assert!(render("event, body").ends_with("[yak = 1] shaved"));
+    assert!(render("event, *").ends_with("[body = shaved] 1"));
+
+    // Searching just the message looks at the same field that's shown as it
+    let log = logs.inner.lock().unwrap();
+    let filter = Filter {
+        search: "shaved".to_owned(),
+        search_scope: SearchScope::Message,
+        ..Filter::default()
+    };
+    let found = |fallback_keys: &str| {
+        let options = DisplayOptions {
+            message_fallback_keys: fallback_keys.to_owned(),
+            ..DisplayOptions::default()
+        };
+        let matcher = filter.matcher(&log, &options);
+        log.messages.values().any(|entry| matcher.matches(entry))
+    };
+    assert!(!found(""));
+    assert!(found("event, body"));
+    assert!(!found("event, *"));
+}
+
+#[test]
+fn test_time_deltas() {
+    assert_eq!(
+        format_time_delta(chrono::Duration::microseconds(250)),
+        "+250µs"
+    );
+    assert_eq!(
+        format_time_delta(chrono::Duration::milliseconds(12)),
+        "+12ms"
+    );
+    assert_eq!(
+        format_time_delta(chrono::Duration::milliseconds(-1500)),
+        "-1.50s"
+    );
+    assert_eq!(format_time_delta(chrono::Duration::seconds(123)), "+2m03s");
+    assert_eq!(format_time_delta(chrono::Duration::minutes(62)), "+1h02m");
+
+    let logs = Logs::new();
+    for time in ["10.000", "10.012", "12.000"] {
+        logs.add_json_message(&format!(
+            r###"{{"timestamp":"2022-02-15T18:47:{time}000Z","level":"INFO","fields":{{"message":"hi"}},"target":"fmt_json","spans":[{{"name":"shave"}}]}}"###
+        ));
+    }
+    let options = DisplayOptions {
+        show_time_deltas: true,
+        slow_delta_ms: Some(1000),
+        ..DisplayOptions::default()
+    };
+    let rendered = logs.render_query(
+        Query::All,
+        &options,
+        &Filter::default(),
+        &BTreeSet::new(),
+        None,
+    );
+    let deltas = rendered
+        .lines
+        .iter()
+        .filter(|line| matches!(line.kind, LineKind::Message(_)))
+        .map(|line| {
+            let text = rendered.line_text(line);
+            let slow = line.slow_delta_token.is_some();
+            (text[..DELTA_GUTTER_WIDTH].trim().to_owned(), slow)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        deltas,
+        [
+            (String::new(), false),
+            ("+12ms".to_owned(), false),
+            ("+1.99s".to_owned(), true)
+        ]
+    );
+    // Span headers line up with the messages
+    assert!(rendered.text.starts_with(&" ".repeat(DELTA_GUTTER_WIDTH)));
+}
+
+#[test]
+fn test_markdown_table() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"a | b","yak":1},"target":"fmt_json"}"###,
+    );
+    logs.add_json_message(
+        r###"{"level":"WARN","fields":{"message":"two\nlines","razor":"dull"},"target":"fmt_json"}"###,
+    );
+    let message_ids = logs
+        .inner
+        .lock()
+        .unwrap()
+        .messages
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    let table = logs.markdown_table(&message_ids, &DisplayOptions::default());
+    assert_eq!(
+        table,
+        "| time | level | message | yak | razor |\n\
+         | --- | --- | --- | --- | --- |\n\
+         | 2022-02-15T18:47:10.821495Z | INFO | a \\| b | 1 |  |\n\
+         |  | WARN | two<br>lines |  | dull |\n"
+    );
+
+    // Messages fall back to other keys like they do in the rows, and showing
+    // `message` doesn't add a second column for it
+    logs.add_json_message(
+        r###"{"level":"INFO","fields":{"msg":"fell back","yak":2},"target":"fmt_json"}"###,
+    );
+    let message_ids = logs
+        .inner
+        .lock()
+        .unwrap()
+        .messages
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    let table = logs.markdown_table(&message_ids[2..], &DisplayOptions::default());
+    assert_eq!(
+        table,
+        "| time | level | message | msg | yak |\n\
+         | --- | --- | --- | --- | --- |\n\
+         |  | INFO | fell back | fell back | 2 |\n"
+    );
+    let options = DisplayOptions {
+        shown_keys: Some(vec!["message".to_owned(), "yak".to_owned()]),
+        ..DisplayOptions::default()
+    };
+    let table = logs.markdown_table(&message_ids[..1], &options);
+    assert_eq!(
+        table,
+        "| time | level | message | yak |\n\
+         | --- | --- | --- | --- |\n\
+         | 2022-02-15T18:47:10.821495Z | INFO | a \\| b | 1 |\n"
+    );
+}
+
+#[test]
+fn test_target_prefixes() {
+    let logs = Logs::new();
+    logs.add_json_message(
+        r###"{"timestamp":"2022-02-15T18:47:10.821495Z","level":"INFO","fields":{"message":"hi"},"target":"fmt_json::yak_shave::razor"}"###,
+    );
+    let render = |target_prefixes: &str| {
+        let options = DisplayOptions {
+            show_targets: true,
+            target_prefixes: target_prefixes.to_owned(),
+            ..DisplayOptions::default()
+        };
+        let rendered = logs.render_query(
+            Query::All,
+            &options,
+            &Filter::default(),
+            &BTreeSet::new(),
+            None,
+        );
+        let line = rendered.lines.last().unwrap();
+        rendered.line_text(line).trim().to_owned()
+    };
+    assert!(render("").ends_with("] fmt_json::yak_shave::razor: hi"));
+    assert!(render("fmt_json::, fmt_json::yak_shave::").ends_with("] razor: hi"));
+    assert!(render("fmt_json::yak_shave::razor").ends_with("] fmt_json::yak_shave::razor: hi"));
+}
@@ -1,15 +1,28 @@
-/*
+use std::path::PathBuf;
+
 use clap::Parser;
+use egui::Vec2;
+use tracing_gui::{App, StartupOptions, Tab};
 
 #[derive(Parser)]
-struct Cli {}
-*/
-
-use egui::Vec2;
-use tracing_gui::App;
+struct Cli {
+    /// A log file to open
+    path: Option<PathBuf>,
+    /// Scroll to and highlight this message (by its #id) once the logs are loaded
+    #[clap(long)]
+    goto: Option<u64>,
+    /// The tab to start on: settings, linear, or tree
+    #[clap(long)]
+    tab: Option<Tab>,
+}
 
 fn main() {
-    // let _cli = Cli::parse();
+    let cli = Cli::parse();
+    let startup = StartupOptions {
+        path: cli.path,
+        goto: cli.goto,
+        tab: cli.tab,
+    };
 
     let egui_options = eframe::NativeOptions {
         drag_and_drop_support: true,
@@ -21,6 +34,6 @@ fn main() {
     eframe::run_native(
         "tracing-gui",
         egui_options,
-        Box::new(|cc| Box::new(App::new(cc))),
+        Box::new(|cc| Box::new(App::new(cc, startup))),
     );
 }
@@ -0,0 +1,253 @@
+use std::time::{Duration, Instant};
+
+use egui::Ui;
+
+use crate::logs::{LevelFilter, Logs, SearchScope, SpanId, TargetGlob};
+
+use super::{Settings, Viewer};
+
+#[derive(Debug, Default, Clone)]
+pub struct FilterUi {
+    /// If "errors only" is on, what to go back to when it's turned off:
+    /// the levels, and the spans the tree and linear views had selected.
+    errors_only_restore: Option<(LevelFilter, Option<SpanId>, Option<SpanId>)>,
+    /// What's in the search box, which only goes into the filter once typing
+    /// pauses, along with when it was last edited if it hasn't yet.
+    search_text: String,
+    search_edited: Option<Instant>,
+    /// The target glob being typed in, and why it doesn't compile if it doesn't.
+    /// It only gets compiled into the filter when this changes.
+    target_text: String,
+    target_error: Option<String>,
+}
+
+/// How long typing has to pause before the search is re-run.
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(150);
+
+impl Viewer {
+    pub fn ui_filter_bar(&mut self, ui: &mut Ui, settings: &mut Settings) {
+        ui.horizontal(|ui| {
+            ui.label("search:");
+            self.ui_search_box(ui);
+            let search_scope = &mut self.filter.search_scope;
+            egui::ComboBox::from_id_source("search_scope")
+                .selected_text(search_scope.label())
+                .show_ui(ui, |ui| {
+                    for scope in SearchScope::ALL {
+                        ui.selectable_value(search_scope, scope, scope.label());
+                    }
+                });
+
+            ui.add_enabled_ui(!self.filter.is_empty(), |ui| {
+                if ui
+                    .button("✂ retain only matching")
+                    .on_hover_text(
+                        "permanently discard everything that doesn't match (reload to restore)",
+                    )
+                    .clicked()
+                {
+                    self.retain_matching(&settings.display);
+                }
+            });
+            ui.add_enabled_ui(self.loaded_path.is_some(), |ui| {
+                if ui.button("🔄 reload").clicked() {
+                    self.reload(&settings.processor);
+                }
+                if ui
+                    .button("⏬ reload to end")
+                    .on_hover_text("reload and scroll to the newest messages (R)")
+                    .clicked()
+                {
+                    self.reload_to_end(&settings.processor);
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("field:");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.filter.field_key)
+                    .hint_text("key")
+                    .desired_width(120.0),
+            );
+            ui.label("=");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.filter.field_value)
+                    .hint_text("value contains")
+                    .desired_width(120.0),
+            );
+            ui_field_key_completions(
+                ui,
+                "field_key",
+                &response,
+                &self.logs,
+                &mut self.filter.field_key,
+            );
+            ui.separator();
+            ui.label("has field:");
+            let response = ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.filter.has_field)
+                        .hint_text("key")
+                        .desired_width(120.0),
+                )
+                .on_hover_text("only show messages with this field, whatever its value");
+            ui_field_key_completions(
+                ui,
+                "has_field",
+                &response,
+                &self.logs,
+                &mut self.filter.has_field,
+            );
+            ui.separator();
+            ui.label("target:");
+            self.ui_target_glob(ui);
+            ui.separator();
+            ui.label("show fields:");
+            crate::ui_field_preset_picker(ui, settings);
+        });
+        ui.horizontal(|ui| {
+            let errors_only = self.filter_ui.errors_only_restore.is_some();
+            if ui
+                .selectable_label(errors_only, "🔥 errors only")
+                .on_hover_text("show only ERROR messages from all spans (click again to go back)")
+                .clicked()
+            {
+                self.toggle_errors_only();
+            }
+            ui.toggle_value(&mut self.filter.errors_only_spans, "🧯 spans with errors")
+                .on_hover_text(
+                    "show only spans with an ERROR somewhere inside, unfolded down to it",
+                );
+            ui.separator();
+
+            let levels = &mut self.filter.levels;
+            let old_levels = *levels;
+            ui.checkbox(&mut levels.error, "ERROR");
+            ui.checkbox(&mut levels.warn, "WARN");
+            ui.checkbox(&mut levels.info, "INFO");
+            ui.checkbox(&mut levels.debug, "DEBUG");
+            ui.checkbox(&mut levels.trace, "TRACE");
+            ui.checkbox(&mut levels.other, "other");
+            if *levels != old_levels {
+                // Fiddling with the levels by hand takes us out of "errors only"
+                self.filter_ui.errors_only_restore = None;
+            }
+        });
+    }
+
+    fn ui_search_box(&mut self, ui: &mut Ui) {
+        let ui_state = &mut self.filter_ui;
+        if ui_state.search_edited.is_none() && ui_state.search_text != self.filter.search {
+            // The filter was changed out from under us
+            ui_state.search_text = self.filter.search.clone();
+        }
+        if ui.text_edit_singleline(&mut ui_state.search_text).changed() {
+            ui_state.search_edited = Some(Instant::now());
+        }
+        if let Some(edited) = ui_state.search_edited {
+            if edited.elapsed() >= SEARCH_DEBOUNCE {
+                self.filter.search = ui_state.search_text.clone();
+                ui_state.search_edited = None;
+            } else {
+                // Come back around to apply it once the wait is over
+                ui.ctx().request_repaint();
+            }
+        }
+    }
+
+    fn ui_target_glob(&mut self, ui: &mut Ui) {
+        let ui_state = &mut self.filter_ui;
+        let compiled = self
+            .filter
+            .target
+            .as_ref()
+            .map_or("", |glob| glob.pattern());
+        if ui_state.target_error.is_none() && ui_state.target_text.trim() != compiled {
+            // The filter was changed out from under us
+            ui_state.target_text = compiled.to_owned();
+        }
+        let response = ui
+            .add(
+                egui::TextEdit::singleline(&mut ui_state.target_text)
+                    .hint_text("myapp::db::*")
+                    .desired_width(150.0),
+            )
+            .on_hover_text("only show messages whose target matches this glob (* ? [a-z])");
+        if response.changed() {
+            if ui_state.target_text.trim().is_empty() {
+                self.filter.target = None;
+                ui_state.target_error = None;
+            } else {
+                match TargetGlob::new(ui_state.target_text.trim()) {
+                    Ok(glob) => {
+                        self.filter.target = Some(glob);
+                        ui_state.target_error = None;
+                    }
+                    Err(e) => {
+                        self.filter.target = None;
+                        ui_state.target_error = Some(e);
+                    }
+                }
+            }
+        }
+        if let Some(error) = &ui_state.target_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+
+    fn toggle_errors_only(&mut self) {
+        if let Some((levels, tree_span, linear_span)) = self.filter_ui.errors_only_restore.take() {
+            self.filter.levels = levels;
+            self.tree_logs_ui.cur_span = tree_span;
+            self.linear_logs_ui.cur_span = linear_span;
+        } else {
+            self.filter_ui.errors_only_restore = Some((
+                self.filter.levels,
+                self.tree_logs_ui.cur_span,
+                self.linear_logs_ui.cur_span,
+            ));
+            self.filter.levels = LevelFilter::errors_only();
+            self.tree_logs_ui.cur_span = None;
+            self.linear_logs_ui.cur_span = None;
+        }
+    }
+}
+
+/// Pop up the known field keys that match what's been typed into `key` so far.
+fn ui_field_key_completions(
+    ui: &mut Ui,
+    id_source: &str,
+    response: &egui::Response,
+    logs: &Logs,
+    key: &mut String,
+) {
+    const MAX_COMPLETIONS: usize = 20;
+
+    let popup_id = ui.make_persistent_id((id_source, "completions"));
+    if response.gained_focus() || response.changed() {
+        ui.memory().open_popup(popup_id);
+    }
+    if !ui.memory().is_popup_open(popup_id) {
+        return;
+    }
+
+    let typed = key.to_lowercase();
+    let completions = logs
+        .field_keys()
+        .into_iter()
+        .filter(|known| known.to_lowercase().contains(&typed) && **known != **key)
+        .take(MAX_COMPLETIONS)
+        .collect::<Vec<_>>();
+    if completions.is_empty() {
+        return;
+    }
+
+    egui::popup_below_widget(ui, popup_id, response, |ui| {
+        for known in completions {
+            if ui.selectable_label(false, &*known).clicked() {
+                *key = known.to_string();
+                ui.memory().close_popup();
+            }
+        }
+    });
+}
@@ -1,34 +1,150 @@
-use crate::logs::{Query, SpanId};
-use egui::{TextStyle, Ui};
+use std::sync::Arc;
 
-use super::App;
+use crate::logs::{self, BucketSize, DisplayOptions, Filter, PrintOptions, SpanId, TimeBucket};
+use egui::Ui;
+
+use super::{Settings, Tab, Viewer, COMMAND};
 
 #[derive(Debug, Default, Clone)]
 pub struct LinearLogsUi {
-    cur_span: Option<SpanId>,
+    pub cur_span: Option<SpanId>,
+    /// Show the messages grouped into buckets of time instead of one per row
+    pub bucket_size: Option<BucketSize>,
+    buckets: Option<CachedBuckets>,
+}
+
+/// The time buckets, along with what they were computed for so we know when
+/// they're out of date.
+#[derive(Debug, Clone)]
+struct CachedBuckets {
+    filter: Filter,
+    options: DisplayOptions,
+    size: BucketSize,
+    num_messages: usize,
+    buckets: Arc<Vec<TimeBucket>>,
 }
 
-impl App {
-    pub fn ui_logs_linear(&mut self, ui: &mut Ui, ctx: &egui::Context) {
+impl Viewer {
+    pub fn ui_logs_linear(
+        &mut self,
+        ui: &mut Ui,
+        ctx: &egui::Context,
+        settings: &mut Settings,
+        focus_mode: &mut bool,
+    ) {
         // Print the logs
-        self.ui_logs_linear_text(ui, ctx)
+        self.ui_logs_linear_text(ui, ctx, settings, focus_mode)
     }
 
-    fn ui_logs_linear_text(&mut self, ui: &mut Ui, _ctx: &egui::Context) {
+    fn ui_logs_linear_text(
+        &mut self,
+        ui: &mut Ui,
+        _ctx: &egui::Context,
+        settings: &mut Settings,
+        focus_mode: &mut bool,
+    ) {
         ui.label("TODO");
+        self.ui_filter_bar(ui, settings);
+        ui.horizontal(|ui| {
+            let bucket_size = &mut self.linear_logs_ui.bucket_size;
+            let mut grouped = bucket_size.is_some();
+            ui.checkbox(&mut grouped, "group by time");
+            if grouped != bucket_size.is_some() {
+                *bucket_size = grouped.then(BucketSize::default);
+            }
+            if let Some(size) = bucket_size {
+                ui.label("per");
+                egui::ComboBox::from_id_source("bucket_size")
+                    .selected_text(size.label())
+                    .show_ui(ui, |ui| {
+                        for choice in BucketSize::ALL {
+                            ui.selectable_value(size, choice, choice.label());
+                        }
+                    });
+            }
+            ui.separator();
+            ui.toggle_value(focus_mode, "⛶ focus")
+                .on_hover_text(format!(
+                    "fill the window with just the logs ({}+Shift+F)",
+                    COMMAND
+                ));
+        });
+
+        if let Some(size) = self.linear_logs_ui.bucket_size {
+            self.ui_logs_linear_buckets(ui, size, settings);
+            return;
+        }
+        let query = self.current_query(Tab::LinearLogs, settings);
+        self.ui_logs_rows(ui, query, settings);
+    }
+
+    fn ui_logs_linear_buckets(&mut self, ui: &mut Ui, size: BucketSize, settings: &Settings) {
+        /// Don't lay out more than this many messages when a bucket is opened.
+        const MAX_BUCKET_MESSAGES: usize = 1000;
+
+        let logs = self.logs.inner.lock().unwrap();
+        let display = &settings.display;
+        let print_options =
+            PrintOptions::cached(&logs, display, &self.filter, &mut self.error_spans);
+
         let ui_state = &mut self.linear_logs_ui;
-        egui::ScrollArea::vertical().show(ui, |ui| {
-            let query = if let Some(span) = ui_state.cur_span {
-                Query::Span(span)
-            } else {
-                Query::All
-            };
-            let text = self.logs.string_query(query);
-            ui.add(
-                egui::TextEdit::multiline(&mut &**text)
-                    .font(TextStyle::Monospace)
-                    .desired_width(f32::INFINITY),
-            );
+        let up_to_date = ui_state.buckets.as_ref().is_some_and(|cached| {
+            cached.filter == self.filter
+                && &cached.options == display
+                && cached.size == size
+                && cached.num_messages == logs.messages.len()
         });
+        if !up_to_date {
+            ui_state.buckets = Some(CachedBuckets {
+                filter: self.filter.clone(),
+                options: display.clone(),
+                size,
+                num_messages: logs.messages.len(),
+                buckets: Arc::new(logs.time_buckets(&print_options, size)),
+            });
+        }
+        let buckets = ui_state.buckets.as_ref().unwrap().buckets.clone();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for bucket in &*buckets {
+                    let levels = &bucket.levels;
+                    let header = format!(
+                        "{}  {:6} messages  (E {} / W {} / I {} / D {} / T {} / other {})",
+                        bucket.start.format("%Y-%m-%d %H:%M:%S"),
+                        bucket.messages.len(),
+                        levels.error,
+                        levels.warn,
+                        levels.info,
+                        levels.debug,
+                        levels.trace,
+                        levels.other,
+                    );
+                    egui::CollapsingHeader::new(egui::RichText::new(header).monospace())
+                        .id_source(bucket.start.timestamp())
+                        .show(ui, |ui| {
+                            for message_id in bucket.messages.iter().take(MAX_BUCKET_MESSAGES) {
+                                let entry = &logs.messages[message_id];
+                                let mut text = String::new();
+                                logs::print_message(
+                                    &logs,
+                                    &mut text,
+                                    0,
+                                    *message_id,
+                                    entry,
+                                    &print_options,
+                                );
+                                ui.monospace(text);
+                            }
+                            if bucket.messages.len() > MAX_BUCKET_MESSAGES {
+                                ui.weak(format!(
+                                    "… and {} more (try a smaller bucket size)",
+                                    bucket.messages.len() - MAX_BUCKET_MESSAGES
+                                ));
+                            }
+                        });
+                }
+            });
     }
 }
@@ -0,0 +1,686 @@
+use std::collections::BTreeSet;
+use std::ops::{Range, RangeInclusive};
+
+use crate::highlight::{Format, TokenKind};
+use crate::logs::{KeyStyle, LineKind, MessageId, Query, Rendered, RenderedLine};
+use egui::{text::LayoutJob, Color32, Rect, Sense, Shape, TextFormat, TextStyle, Ui};
+use serde::{Deserialize, Serialize};
+use tracing::Level;
+
+use super::{ProcessorStatus, Settings, Viewer};
+
+#[derive(Debug, Default, Clone)]
+pub struct RowsUi {
+    /// The row the selection started from, and the row it was extended to.
+    ///
+    /// These are stored as what the rows are rather than their indices so that
+    /// the selection survives the rows being re-rendered.
+    selection: Option<(LineKind, LineKind)>,
+    /// A row to scroll to next time we're shown.
+    scroll_to: Option<LineKind>,
+    /// Scroll to the bottom next time we're shown.
+    scroll_to_end: bool,
+    /// A nested field value being shown pretty-printed in its own window.
+    expanded: Option<ExpandedValue>,
+    /// Messages whose long multi-line values have been expanded.
+    expanded_messages: BTreeSet<MessageId>,
+    /// Messages whose fields are being shown side by side in their own window.
+    compared: Vec<MessageId>,
+    /// Only show the fields that differ between the compared messages.
+    compare_differences_only: bool,
+    /// The row at the top of the view as of the last frame.
+    first_visible_row: usize,
+    /// How many messages to show for a query, if it's been raised past
+    /// `RENDER_CAP` with "show more".
+    shown_messages: Option<(Query, usize)>,
+}
+
+/// A field value shown in its own window.
+#[derive(Debug, Clone)]
+struct ExpandedValue {
+    key: String,
+    text: String,
+    /// The value's tokens, if it's in a format we can highlight.
+    tokens: Option<Vec<(Range<usize>, TokenKind)>>,
+}
+
+impl ExpandedValue {
+    fn new(key: String, text: String) -> Self {
+        let tokens = Format::detect(&text).map(|format| format.tokenize(&text));
+        Self { key, text, tokens }
+    }
+}
+
+/// How many messages to print at first, and how many more to print at a time,
+/// so a huge span doesn't lock up the UI.
+const RENDER_CAP: usize = 50_000;
+
+/// The colors levels are drawn in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelPalette {
+    pub error: [u8; 3],
+    pub warn: [u8; 3],
+    pub info: [u8; 3],
+    pub debug: [u8; 3],
+    pub trace: [u8; 3],
+}
+
+impl Default for LevelPalette {
+    fn default() -> Self {
+        Self::BUILTIN[0].1
+    }
+}
+
+impl LevelPalette {
+    pub const BUILTIN: [(&'static str, LevelPalette); 3] = [
+        (
+            "default",
+            LevelPalette {
+                error: [230, 70, 70],
+                warn: [220, 170, 40],
+                info: [80, 170, 80],
+                debug: [90, 140, 220],
+                trace: [160, 160, 160],
+            },
+        ),
+        (
+            // The Okabe-Ito colors, which stay apart for all the common kinds
+            // of colorblindness
+            "colorblind-friendly",
+            LevelPalette {
+                error: [213, 94, 0],
+                warn: [240, 228, 66],
+                info: [0, 158, 115],
+                debug: [86, 180, 233],
+                trace: [160, 160, 160],
+            },
+        ),
+        (
+            "high contrast",
+            LevelPalette {
+                error: [255, 40, 40],
+                warn: [255, 220, 0],
+                info: [0, 220, 0],
+                debug: [0, 200, 255],
+                trace: [200, 200, 200],
+            },
+        ),
+    ];
+
+    pub fn color_mut(&mut self, level: Level) -> &mut [u8; 3] {
+        match level {
+            Level::ERROR => &mut self.error,
+            Level::WARN => &mut self.warn,
+            Level::INFO => &mut self.info,
+            Level::DEBUG => &mut self.debug,
+            Level::TRACE => &mut self.trace,
+        }
+    }
+
+    fn color(mut self, level: Level) -> Color32 {
+        let [r, g, b] = *self.color_mut(level);
+        Color32::from_rgb(r, g, b)
+    }
+
+    /// A subtle background for rows that deserve attention, tuned so it doesn't
+    /// drown out the text in either dark or light mode.
+    fn row_shade(mut self, ui: &Ui, level: Level) -> Option<Color32> {
+        match level {
+            Level::ERROR | Level::WARN => {
+                let [r, g, b] = *self.color_mut(level);
+                Some(Color32::from_rgba_unmultiplied(r, g, b, shade_alpha(ui)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl RowsUi {
+    /// Select a row and scroll it into view.
+    pub fn jump_to(&mut self, kind: LineKind) {
+        self.selection = Some((kind, kind));
+        self.scroll_to = Some(kind);
+    }
+
+    /// Scroll to the newest messages.
+    pub fn jump_to_end(&mut self) {
+        self.scroll_to = None;
+        self.scroll_to_end = true;
+    }
+
+    /// Collapse every message again, since other logs are being loaded and
+    /// the same ids will mean different messages.
+    pub fn collapse_all(&mut self) {
+        self.expanded_messages.clear();
+    }
+
+    /// Find the selected rows in this rendering, if they're in it.
+    fn selected_rows(&self, rendered: &Rendered) -> Option<RangeInclusive<usize>> {
+        let (anchor, head) = self.selection?;
+        let anchor = rendered.lines.iter().position(|line| line.kind == anchor)?;
+        let head = rendered.lines.iter().position(|line| line.kind == head)?;
+        let start = anchor.min(head);
+        let mut end = anchor.max(head);
+        // Include the rest of the last entry if it's split over several rows
+        while rendered
+            .lines
+            .get(end + 1)
+            .is_some_and(|line| line.kind == rendered.lines[end].kind)
+        {
+            end += 1;
+        }
+        Some(start..=end)
+    }
+
+    /// The messages in the selected rows.
+    fn selected_messages(&self, rendered: &Rendered) -> Vec<MessageId> {
+        let lines = self
+            .selected_rows(rendered)
+            .map_or(&[][..], |rows| &rendered.lines[rows]);
+        messages_in(lines)
+    }
+
+    /// The next (or previous) ERROR message from the selection, or from the
+    /// top of the view if nothing's selected, wrapping around at the ends.
+    fn next_error(&self, rendered: &Rendered, backwards: bool) -> Option<LineKind> {
+        let num_rows = rendered.lines.len();
+        if num_rows == 0 {
+            return None;
+        }
+        let from = match (self.selected_rows(rendered), backwards) {
+            (Some(rows), false) => *rows.end(),
+            (Some(rows), true) => *rows.start(),
+            // Count the top row itself as a candidate
+            (None, false) => (self.first_visible_row + num_rows - 1) % num_rows,
+            (None, true) => self.first_visible_row,
+        };
+        let skip = self.selection.map(|(_, head)| head);
+        (1..=num_rows)
+            .map(|offset| {
+                if backwards {
+                    (from + num_rows - offset) % num_rows
+                } else {
+                    (from + offset) % num_rows
+                }
+            })
+            .map(|row| &rendered.lines[row])
+            .find(|line| {
+                matches!(line.kind, LineKind::Message(_))
+                    && line.level == Some(Level::ERROR)
+                    && Some(line.kind) != skip
+            })
+            .map(|line| line.kind)
+    }
+}
+
+impl Viewer {
+    fn export_csv(&self, query: Query, settings: &Settings) {
+        // FIXME(WASM): this has to be made async in wasm, and be a download
+        if let Some(path) = rfd::FileDialog::new().set_file_name("logs.csv").save_file() {
+            let csv = self.logs.export_csv(query, &settings.display, &self.filter);
+            if let Err(e) = std::fs::write(&path, csv) {
+                eprintln!("WARN: failed to write {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Show the logs for a query as a scrollable list of rows.
+    pub fn ui_logs_rows(&mut self, ui: &mut Ui, query: Query, settings: &mut Settings) {
+        let max_messages = match self.rows_ui.shown_messages {
+            Some((shown_query, shown)) if shown_query == query => shown,
+            _ => RENDER_CAP,
+        };
+        let rendered = self.logs.render_query(
+            query,
+            &settings.display,
+            &self.filter,
+            &self.rows_ui.expanded_messages,
+            Some(max_messages),
+        );
+        let selected_rows = self.rows_ui.selected_rows(&rendered);
+        self.ui_not_json_hint(ui, settings);
+
+        let copy_shortcut = ui.memory().focus().is_none()
+            && ui
+                .input()
+                .events
+                .iter()
+                .any(|event| matches!(event, egui::Event::Copy));
+        let error_shortcut = (ui.memory().focus().is_none()
+            && ui.input().key_pressed(egui::Key::E))
+        .then(|| ui.input().modifiers.shift);
+        let (copy_button, refresh, error_button) = ui
+            .horizontal(|ui| {
+                let copy_button = ui
+                    .button("📋 copy")
+                    .on_hover_text("copy the selected rows (or everything if nothing is selected)")
+                    .clicked();
+                if ui
+                    .button("📋 markdown")
+                    .on_hover_text(
+                        "copy the selected messages (or everything if nothing is selected) \
+                         as a Markdown table, for pasting into an issue",
+                    )
+                    .clicked()
+                {
+                    let mut messages = self.rows_ui.selected_messages(&rendered);
+                    if messages.is_empty() {
+                        messages = messages_in(&rendered.lines);
+                    }
+                    ui.output().copied_text =
+                        self.logs.markdown_table(&messages, &settings.display);
+                }
+                if ui
+                    .button("💾 CSV...")
+                    .on_hover_text(
+                        "export the matching messages as a spreadsheet, \
+                         with a column for each shown field (see the field presets)",
+                    )
+                    .clicked()
+                {
+                    self.export_csv(query, settings);
+                }
+                let selected_messages = self.rows_ui.selected_messages(&rendered);
+                if ui
+                    .add_enabled(selected_messages.len() >= 2, egui::Button::new("⚖ compare"))
+                    .on_hover_text(
+                        "show the fields of the selected messages side by side \
+                         (shift-click to select several, or right-click to add one)",
+                    )
+                    .clicked()
+                {
+                    self.rows_ui.compared = selected_messages;
+                }
+                let mut error_button = None;
+                if ui
+                    .button("⏮")
+                    .on_hover_text("jump to the previous ERROR (Shift+E)")
+                    .clicked()
+                {
+                    error_button = Some(true);
+                }
+                if ui
+                    .button("⏭ next error")
+                    .on_hover_text("jump to the next ERROR (E)")
+                    .clicked()
+                {
+                    error_button = Some(false);
+                }
+                let refresh = ui_render_freshness(ui, &rendered, self.logs.num_messages());
+                if rendered.truncated {
+                    ui.separator();
+                    ui.colored_label(
+                        Color32::from_rgb(220, 170, 40),
+                        format!("showing the first {} messages", max_messages),
+                    );
+                    if ui
+                        .small_button(format!("⏬ show {} more", RENDER_CAP))
+                        .clicked()
+                    {
+                        self.rows_ui.shown_messages = Some((query, max_messages + RENDER_CAP));
+                    }
+                }
+                (copy_button, refresh, error_button)
+            })
+            .inner;
+        if let Some(backwards) = error_button.or(error_shortcut) {
+            if let Some(kind) = self.rows_ui.next_error(&rendered, backwards) {
+                self.rows_ui.jump_to(kind);
+            }
+        }
+        if refresh {
+            self.logs.invalidate_render();
+            ui.ctx().request_repaint();
+        }
+        if copy_button || (copy_shortcut && selected_rows.is_some()) {
+            ui.output().copied_text = match &selected_rows {
+                Some(rows) => self.logs.plain_text(
+                    &rendered.lines[rows.clone()],
+                    &settings.display,
+                    &self.filter,
+                ),
+                None => rendered.text.clone(),
+            };
+        }
+
+        let options = &settings.display;
+        let palette = settings.level_palette;
+        let highlight_values = settings.highlight_values;
+        let decode_json_strings = settings.decode_json_strings;
+        let ui_state = &mut self.rows_ui;
+        let mut toggle_expanded = None;
+
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let mut scroll_area = egui::ScrollArea::both().auto_shrink([false; 2]);
+        let row_spacing = row_height + ui.spacing().item_spacing.y;
+        if let Some(target) = ui_state.scroll_to.take() {
+            if let Some(row) = rendered.lines.iter().position(|line| line.kind == target) {
+                scroll_area = scroll_area.vertical_scroll_offset(row as f32 * row_spacing);
+            }
+        } else if std::mem::take(&mut ui_state.scroll_to_end) {
+            // The scroll area clamps this so the last row ends up at the bottom
+            scroll_area =
+                scroll_area.vertical_scroll_offset(rendered.lines.len() as f32 * row_spacing);
+        }
+        scroll_area.show_rows(ui, row_height, rendered.lines.len(), |ui, row_range| {
+            ui_state.first_visible_row = row_range.start;
+            for row in row_range {
+                let line = &rendered.lines[row];
+                // Reserve a spot under the text for the background
+                let background = ui.painter().add(Shape::Noop);
+                let mut response = ui.add(
+                    egui::Label::new(line_layout(ui, &rendered, line, palette))
+                        .wrap(false)
+                        .sense(Sense::click()),
+                );
+                if options.key_style != KeyStyle::Full {
+                    // Say what the shortened keys are short for
+                    let logs = &self.logs;
+                    response = response.on_hover_ui(|ui| {
+                        for (short, full) in logs.shortened_keys(line.kind, options.key_style) {
+                            ui.monospace(format!("{} = {}", short, full));
+                        }
+                    });
+                }
+                let logs = &self.logs;
+                response.clone().context_menu(|ui| {
+                    let values = logs.field_values(line.kind);
+                    if values.is_empty() {
+                        ui.label("no fields");
+                    }
+                    for (key, value) in values {
+                        let label = format!("📋 {} = {}", key, truncate_label(&value));
+                        if ui.button(label).on_hover_text("copy this value").clicked() {
+                            ui.output().copied_text = value;
+                            ui.close_menu();
+                        }
+                    }
+                    if let LineKind::Message(message_id) = line.kind {
+                        ui.separator();
+                        let compared = &mut ui_state.compared;
+                        match compared.iter().position(|id| *id == message_id) {
+                            Some(i) => {
+                                if ui.button("⚖ remove from comparison").clicked() {
+                                    compared.remove(i);
+                                    ui.close_menu();
+                                }
+                            }
+                            None => {
+                                if ui.button("⚖ add to comparison").clicked() {
+                                    compared.push(message_id);
+                                    ui.close_menu();
+                                }
+                            }
+                        }
+                        let nested =
+                            logs.nested_fields(message_id, decode_json_strings, highlight_values);
+                        if !nested.is_empty() {
+                            ui.separator();
+                        }
+                        for (key, pretty) in nested {
+                            if ui.button(format!("expand {}", key)).clicked() {
+                                ui_state.expanded = Some(ExpandedValue::new(key, pretty));
+                                ui.close_menu();
+                            }
+                        }
+                    }
+                });
+                if let (true, LineKind::Message(message_id)) =
+                    (response.double_clicked(), line.kind)
+                {
+                    toggle_expanded = Some(message_id);
+                }
+                if response.clicked() {
+                    ui_state.selection = match ui_state.selection {
+                        Some((anchor, _)) if ui.input().modifiers.shift => {
+                            Some((anchor, line.kind))
+                        }
+                        _ => Some((line.kind, line.kind)),
+                    };
+                }
+
+                let is_selected = selected_rows
+                    .as_ref()
+                    .is_some_and(|rows| rows.contains(&row));
+                let shade = if is_selected {
+                    Some(ui.visuals().selection.bg_fill)
+                } else if let Some([r, g, b]) = line.color {
+                    Some(Color32::from_rgba_unmultiplied(r, g, b, shade_alpha(ui)))
+                } else {
+                    line.level
+                        .and_then(|level| palette.row_shade(ui, level))
+                        .filter(|_| options.shade_rows)
+                };
+                if let Some(color) = shade {
+                    let rect =
+                        Rect::from_x_y_ranges(ui.max_rect().x_range(), response.rect.y_range());
+                    ui.painter()
+                        .set(background, Shape::rect_filled(rect, 0.0, color));
+                }
+            }
+        });
+
+        if let Some(message_id) = toggle_expanded {
+            let expanded = &mut ui_state.expanded_messages;
+            if !expanded.remove(&message_id) {
+                expanded.insert(message_id);
+            }
+        }
+
+        if let Some(expanded) = &ui_state.expanded {
+            let mut open = true;
+            egui::Window::new(expanded.key.as_str())
+                .id(egui::Id::new(("expanded_value", self.id)))
+                .open(&mut open)
+                .vscroll(true)
+                .show(ui.ctx(), |ui| match &expanded.tokens {
+                    Some(tokens) if highlight_values => {
+                        ui.label(highlighted_layout(ui, &expanded.text, tokens));
+                    }
+                    _ => {
+                        ui.monospace(expanded.text.as_str());
+                    }
+                });
+            if !open {
+                ui_state.expanded = None;
+            }
+        }
+        self.ui_compare_messages(ui);
+    }
+
+    /// The compared messages' fields side by side, with the ones that differ
+    /// picked out.
+    fn ui_compare_messages(&mut self, ui: &mut Ui) {
+        let ui_state = &mut self.rows_ui;
+        if ui_state.compared.is_empty() {
+            return;
+        }
+        let comparison = self.logs.compare_messages(&ui_state.compared);
+        let mut open = true;
+        let mut jump_to = None;
+        egui::Window::new("compare messages")
+            .id(egui::Id::new(("compare_messages", self.id)))
+            .open(&mut open)
+            .vscroll(true)
+            .hscroll(true)
+            .show(ui.ctx(), |ui| {
+                ui.checkbox(&mut ui_state.compare_differences_only, "only differences");
+                let highlight = ui.visuals().selection.bg_fill;
+                egui::Grid::new("compare_messages")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("");
+                        for message_id in &ui_state.compared {
+                            if ui
+                                .link(format!("#{}", message_id))
+                                .on_hover_text("jump to this message")
+                                .clicked()
+                            {
+                                jump_to = Some(*message_id);
+                            }
+                        }
+                        ui.end_row();
+                        for field in &comparison {
+                            if ui_state.compare_differences_only && !field.differs {
+                                continue;
+                            }
+                            ui.monospace(&field.key);
+                            for value in &field.values {
+                                let text = egui::RichText::new(value.as_deref().unwrap_or("—"))
+                                    .monospace();
+                                if field.differs {
+                                    ui.label(text.background_color(highlight));
+                                } else {
+                                    ui.label(text);
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        if let Some(message_id) = jump_to {
+            ui_state.jump_to(LineKind::Message(message_id));
+        }
+        if !open {
+            ui_state.compared.clear();
+        }
+    }
+}
+
+impl Viewer {
+    /// If the file doesn't look like tracing JSON, say so instead of silently
+    /// showing nothing, and offer to read it as plain text.
+    fn ui_not_json_hint(&mut self, ui: &mut Ui, settings: &mut Settings) {
+        if self.cur_status != ProcessorStatus::Done || !self.logs.mostly_not_json() {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.colored_label(
+                Color32::from_rgb(220, 170, 40),
+                "⚠ almost none of this file parsed: it may not be tracing-subscriber JSON",
+            );
+            if ui.button("read it as plain text").clicked() {
+                settings.processor.plain_text_fallback = true;
+                self.reload(&settings.processor);
+            }
+        });
+    }
+}
+
+/// Say whether `rendered` has all the messages that are loaded, with a button
+/// to re-render it. Returns whether the button was clicked.
+fn ui_render_freshness(ui: &mut Ui, rendered: &Rendered, num_messages: usize) -> bool {
+    ui.separator();
+    if rendered.num_messages == num_messages {
+        ui.weak(format!("up to date as of {} messages", num_messages));
+    } else {
+        ui.colored_label(
+            Color32::from_rgb(220, 170, 40),
+            format!(
+                "⚠ view is behind: rendered at {} messages, now {}",
+                rendered.num_messages, num_messages
+            ),
+        );
+    }
+    ui.small_button("🔄 refresh")
+        .on_hover_text("re-render the view with everything loaded so far")
+        .clicked()
+}
+
+/// The messages on some rendered lines, in order.
+fn messages_in(lines: &[RenderedLine]) -> Vec<MessageId> {
+    let mut messages = Vec::new();
+    for line in lines {
+        if let LineKind::Message(message_id) = line.kind {
+            if messages.last() != Some(&message_id) {
+                messages.push(message_id);
+            }
+        }
+    }
+    messages
+}
+
+/// Cut a value down to something that fits in a menu.
+fn truncate_label(value: &str) -> String {
+    const MAX_CHARS: usize = 40;
+    let first_line = value.lines().next().unwrap_or_default();
+    let mut label = first_line.chars().take(MAX_CHARS).collect::<String>();
+    if label.len() < value.len() {
+        label.push('…');
+    }
+    label
+}
+
+fn line_layout(
+    ui: &Ui,
+    rendered: &Rendered,
+    line: &RenderedLine,
+    palette: LevelPalette,
+) -> LayoutJob {
+    let font_id = TextStyle::Monospace.resolve(ui.style());
+    let text_color = if line.muted {
+        ui.visuals().weak_text_color()
+    } else {
+        ui.visuals().text_color()
+    };
+    let normal = TextFormat::simple(font_id.clone(), text_color);
+
+    // The parts of the line drawn in their own color, in order
+    let mut highlights = Vec::new();
+    if let Some(token) = &line.slow_delta_token {
+        highlights.push((token.clone(), palette.color(Level::WARN)));
+    }
+    if let (Some(level), Some(token)) = (line.level, &line.level_token) {
+        highlights.push((token.clone(), palette.color(level)));
+    }
+
+    let mut job = LayoutJob::default();
+    let mut pos = line.range.start;
+    for (token, color) in highlights {
+        job.append(&rendered.text[pos..token.start], 0.0, normal.clone());
+        job.append(
+            &rendered.text[token.clone()],
+            0.0,
+            TextFormat::simple(font_id.clone(), color),
+        );
+        pos = token.end;
+    }
+    job.append(&rendered.text[pos..line.range.end], 0.0, normal);
+    job
+}
+
+/// Color a value by its tokens (see `highlight`).
+fn highlighted_layout(ui: &Ui, text: &str, tokens: &[(Range<usize>, TokenKind)]) -> LayoutJob {
+    let font_id = TextStyle::Monospace.resolve(ui.style());
+    let dark_mode = ui.visuals().dark_mode;
+    let mut job = LayoutJob::default();
+    for (range, kind) in tokens {
+        let color = match (kind, dark_mode) {
+            (TokenKind::Plain, _) => ui.visuals().text_color(),
+            (TokenKind::Punct, _) => ui.visuals().weak_text_color(),
+            (TokenKind::Key, true) => Color32::from_rgb(120, 180, 255),
+            (TokenKind::Key, false) => Color32::from_rgb(20, 90, 190),
+            (TokenKind::String, true) => Color32::from_rgb(140, 200, 120),
+            (TokenKind::String, false) => Color32::from_rgb(40, 120, 30),
+            (TokenKind::Number, true) => Color32::from_rgb(230, 170, 90),
+            (TokenKind::Number, false) => Color32::from_rgb(170, 90, 0),
+            (TokenKind::Keyword, true) => Color32::from_rgb(200, 140, 230),
+            (TokenKind::Keyword, false) => Color32::from_rgb(130, 40, 160),
+        };
+        job.append(
+            &text[range.clone()],
+            0.0,
+            TextFormat::simple(font_id.clone(), color),
+        );
+    }
+    job
+}
+
+fn shade_alpha(ui: &Ui) -> u8 {
+    if ui.visuals().dark_mode {
+        40
+    } else {
+        60
+    }
+}
@@ -1,56 +1,829 @@
-use crate::logs::{self, Query, SpanId};
-use egui::{TextStyle, Ui};
+use std::collections::{HashMap, HashSet};
 
-use super::App;
+use crate::logs::{
+    self, EventEntry, Filter, LogsInner, MessageCounts, PrintOptions, SpanId, SpanSummary,
+    UnknownLevel,
+};
+use egui::Ui;
+use egui_extras::{Size, TableBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::ui_status::format_elapsed;
+
+use super::{Settings, Tab, Viewer, COMMAND};
 
 #[derive(Debug, Default, Clone)]
 pub struct TreeLogsUi {
-    cur_span: Option<SpanId>,
+    pub cur_span: Option<SpanId>,
+    /// The spans that have been focused into, innermost last. The innermost one
+    /// stands in for the root: the span list only has what's inside it, and
+    /// it's what's shown when no span is selected.
+    pub focus: Vec<SpanId>,
+    /// Show just the messages, not the spans they're in (see `Query::Flat`).
+    pub flat: bool,
+    matching_counts: Option<MatchingCounts>,
+    /// The summary of `cur_span`, and how many messages there were when it was made.
+    summary: Option<(SpanId, usize, SpanSummary)>,
+    /// A span path being typed in to go to, and whether it failed to resolve
+    goto_span_path: String,
+    goto_span_path_failed: bool,
+}
+
+/// How many messages match the filter under each span, along with what it
+/// was computed for so we know when it's out of date.
+#[derive(Debug, Clone)]
+struct MatchingCounts {
+    filter: Filter,
+    unknown_level: UnknownLevel,
+    show_span_lifecycle: bool,
+    /// What picks out the message's text (see `MessageKeys`).
+    message_fallback_keys: String,
+    hidden_keys: Vec<String>,
+    shown_keys: Option<Vec<String>>,
+    num_messages: usize,
+    counts: HashMap<SpanId, MessageCounts>,
+    /// See `LogsInner::message_rates`, if sparklines were wanted.
+    rates: Option<HashMap<SpanId, Vec<usize>>>,
+}
+
+/// How many bars are in each span's sparkline.
+const SPARKLINE_BUCKETS: usize = 24;
+
+/// What order to list spans in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpanSort {
+    /// The order the spans were first seen in
+    #[default]
+    Created,
+    Name,
+    /// Most messages first
+    MessageCount,
+}
+
+impl SpanSort {
+    fn label(self) -> &'static str {
+        match self {
+            SpanSort::Created => "creation order",
+            SpanSort::Name => "name",
+            SpanSort::MessageCount => "message count",
+        }
+    }
+}
+
+/// Where the tree view starts out once a file is loaded.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LandOn {
+    /// Every span at once
+    #[default]
+    Everything,
+    /// The top-level span with the most messages
+    BusiestSpan,
+    /// The span with the first ERROR in it
+    FirstError,
+}
+
+impl TreeLogsUi {
+    pub fn focus_root(&self) -> Option<SpanId> {
+        self.focus.last().copied()
+    }
+
+    /// Make a span the root of the span list, until it's popped back out of.
+    fn focus_on(&mut self, span_id: SpanId) {
+        self.focus.push(span_id);
+        self.cur_span = None;
+    }
+}
+
+impl LandOn {
+    fn label(self) -> &'static str {
+        match self {
+            LandOn::Everything => "everything",
+            LandOn::BusiestSpan => "the busiest span",
+            LandOn::FirstError => "the first error",
+        }
+    }
 }
 
-impl App {
-    pub fn ui_logs_tree(&mut self, ui: &mut Ui, ctx: &egui::Context) {
+impl Viewer {
+    /// Select a span in the tree view per `Settings::land_on`, for a file that
+    /// was just loaded.
+    pub fn land_on_span(&mut self, settings: &Settings) {
+        let logs = self.logs.inner.lock().unwrap();
+        let display = &settings.display;
+        let span = match settings.land_on {
+            LandOn::Everything => return,
+            LandOn::BusiestSpan => {
+                let everything = Filter::default().matcher(&logs, display);
+                logs.busiest_child(logs.root_span, &everything)
+            }
+            LandOn::FirstError => logs.first_error_span(display.unknown_level.as_level()),
+        };
+        // Stay put if there's nothing to go to
+        if let Some(span) = span.filter(|&span| span != logs.root_span) {
+            self.tree_logs_ui.cur_span = Some(span);
+        }
+    }
+
+    pub fn ui_logs_tree(
+        &mut self,
+        ui: &mut Ui,
+        ctx: &egui::Context,
+        settings: &mut Settings,
+        focus_mode: &mut bool,
+    ) {
         // Print the logs
-        egui::SidePanel::left("my_left_panel")
-            .show_inside(ui, |ui| self.ui_logs_tree_list(ui, ctx));
-        egui::CentralPanel::default().show_inside(ui, |ui| self.ui_logs_tree_text(ui, ctx));
+        let mut span_list = egui::SidePanel::left(("my_left_panel", self.id)).resizable(true);
+        if let Some(width) = settings.span_list_width {
+            span_list = span_list.default_width(width);
+        }
+        let response = span_list.show_inside(ui, |ui| self.ui_logs_tree_list(ui, ctx, settings));
+        settings.span_list_width = Some(response.response.rect.width());
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            self.ui_logs_tree_text(ui, ctx, settings, focus_mode)
+        });
     }
 
-    fn ui_logs_tree_list(&mut self, ui: &mut Ui, _ctx: &egui::Context) {
+    fn ui_logs_tree_list(&mut self, ui: &mut Ui, _ctx: &egui::Context, settings: &mut Settings) {
+        let mut focus_to = None;
         ui.push_id(1, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
                 ui.label("choose a span: ");
+                ui.horizontal(|ui| {
+                    let span_sort = &mut settings.span_sort;
+                    ui.label("sort by:");
+                    egui::ComboBox::from_id_source("span_sort")
+                        .selected_text(span_sort.label())
+                        .show_ui(ui, |ui| {
+                            for sort in [SpanSort::Created, SpanSort::Name, SpanSort::MessageCount]
+                            {
+                                ui.selectable_value(span_sort, sort, sort.label());
+                            }
+                        });
+                });
+                ui.checkbox(
+                    &mut settings.hide_unmatched_spans,
+                    "hide spans with no matches",
+                );
+                ui.checkbox(&mut settings.span_list_tree, "show as a tree");
+                ui.checkbox(
+                    &mut settings.show_span_counts,
+                    "show message counts (direct / total)",
+                )
+                .on_hover_text(
+                    "how many matching messages are right in each span, \
+                     and how many are in it and its subspans",
+                );
+                ui.checkbox(
+                    &mut settings.show_span_sparklines,
+                    "show when messages happened",
+                )
+                .on_hover_text(
+                    "a little chart by each span of how many matching messages \
+                     were under it over the course of the logs",
+                );
+                ui.checkbox(
+                    &mut settings.merge_identical_spans,
+                    "merge spans with the same name and fields",
+                )
+                .on_hover_text("show everything that happened in any span like the selected one");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut settings.group_repeated_spans, "group repeats")
+                        .on_hover_text(
+                            "list spans with the same name under the same parent as one entry",
+                        );
+                    ui.add_enabled(
+                        settings.group_repeated_spans,
+                        egui::TextEdit::singleline(&mut settings.varying_fields)
+                            .hint_text("any fields")
+                            .desired_width(100.0),
+                    )
+                    .on_hover_text(
+                        "the fields that can differ between repeats, like `i, attempt` \
+                         (blank for any of them)",
+                    );
+                });
+                ui.horizontal(|ui| {
+                    let land_on = &mut settings.land_on;
+                    ui.label("after loading, select:");
+                    egui::ComboBox::from_id_source("land_on")
+                        .selected_text(land_on.label())
+                        .show_ui(ui, |ui| {
+                            for choice in
+                                [LandOn::Everything, LandOn::BusiestSpan, LandOn::FirstError]
+                            {
+                                ui.selectable_value(land_on, choice, choice.label());
+                            }
+                        });
+                });
+                self.ui_logs_tree_goto_path(ui);
                 ui.add_space(10.0);
+                self.ui_focus_breadcrumb(ui);
 
                 let ui_state = &mut self.tree_logs_ui;
                 let logs = self.logs.inner.lock().unwrap();
-                for (span_id, entry) in &logs.spans {
+                let print_options = PrintOptions::cached(
+                    &logs,
+                    &settings.display,
+                    &self.filter,
+                    &mut self.error_spans,
+                );
+
+                // (a reload might not have gotten to the focused span yet)
+                let top_span = ui_state
+                    .focus_root()
+                    .filter(|id| logs.spans.contains_key(id))
+                    .unwrap_or(logs.root_span);
+                let mut span_ids = logs.spans.keys().copied().collect::<Vec<_>>();
+                if top_span != logs.root_span {
+                    let mut in_focus = HashSet::new();
+                    let mut to_visit = vec![top_span];
+                    while let Some(span_id) = to_visit.pop() {
+                        in_focus.insert(span_id);
+                        to_visit.extend(logs.spans[&span_id].events.iter().filter_map(|event| {
+                            match event {
+                                EventEntry::Span(id) => Some(*id),
+                                EventEntry::Message(_) => None,
+                            }
+                        }));
+                    }
+                    span_ids.retain(|id| in_focus.contains(id));
+                }
+                match settings.span_sort {
+                    SpanSort::Created => {}
+                    SpanSort::Name => span_ids.sort_by_key(|id| &*logs.spans[id].name),
+                    SpanSort::MessageCount => {
+                        span_ids.sort_by_key(|id| std::cmp::Reverse(logs.spans[id].message_count()))
+                    }
+                }
+
+                let hide_unmatched = settings.hide_unmatched_spans && !self.filter.is_empty();
+                let show_sparklines = settings.show_span_sparklines;
+                if hide_unmatched || settings.show_span_counts || show_sparklines {
+                    let display = &settings.display;
+                    let up_to_date = ui_state.matching_counts.as_ref().is_some_and(|cached| {
+                        cached.filter == self.filter
+                            && cached.unknown_level == display.unknown_level
+                            && cached.show_span_lifecycle == display.show_span_lifecycle
+                            && cached.message_fallback_keys == display.message_fallback_keys
+                            && cached.hidden_keys == display.hidden_keys
+                            && cached.shown_keys == display.shown_keys
+                            && cached.num_messages == logs.messages.len()
+                            && (cached.rates.is_some() || !show_sparklines)
+                    });
+                    if !up_to_date {
+                        let matcher = self.filter.matcher(&logs, display);
+                        ui_state.matching_counts = Some(MatchingCounts {
+                            filter: self.filter.clone(),
+                            unknown_level: display.unknown_level,
+                            show_span_lifecycle: display.show_span_lifecycle,
+                            message_fallback_keys: display.message_fallback_keys.clone(),
+                            hidden_keys: display.hidden_keys.clone(),
+                            shown_keys: display.shown_keys.clone(),
+                            num_messages: logs.messages.len(),
+                            counts: logs.matching_counts(&matcher),
+                            rates: show_sparklines
+                                .then(|| logs.message_rates(&matcher, SPARKLINE_BUCKETS)),
+                        });
+                    }
+                }
+                let rates = ui_state
+                    .matching_counts
+                    .as_ref()
+                    .and_then(|cached| cached.rates.as_ref())
+                    .filter(|_| show_sparklines);
+                let counts = ui_state
+                    .matching_counts
+                    .as_ref()
+                    .map(|cached| &cached.counts)
+                    .filter(|_| hide_unmatched || settings.show_span_counts);
+                if let (true, Some(counts)) = (hide_unmatched, counts) {
+                    span_ids.retain(|id| counts.get(id).is_some_and(|count| count.total > 0));
+                }
+                let counts = counts.filter(|_| settings.show_span_counts);
+                let varying_keys = settings.group_repeated_spans.then(|| {
+                    settings
+                        .varying_fields
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|key| !key.is_empty())
+                        .collect::<Vec<_>>()
+                });
+                if let Some(error_spans) = &print_options.error_spans {
+                    span_ids.retain(|id| error_spans.contains(id));
+                }
+
+                if settings.span_list_tree {
+                    let tree = SpanTree {
+                        logs: &logs,
+                        print_options: &print_options,
+                        order: span_ids
+                            .iter()
+                            .enumerate()
+                            .map(|(i, id)| (*id, i))
+                            .collect(),
+                        counts,
+                        rates,
+                        varying_keys: varying_keys.as_deref(),
+                    };
+                    if tree.order.contains_key(&top_span) {
+                        ui_span_tree_node(
+                            ui,
+                            &tree,
+                            top_span,
+                            0,
+                            &mut ui_state.cur_span,
+                            &mut focus_to,
+                        );
+                    }
+                    return;
+                }
+                if settings.merge_identical_spans {
+                    // Just one entry for each group of identical spans
+                    let mut seen = HashSet::new();
+                    span_ids.retain(|id| {
+                        let entry = &logs.spans[id];
+                        seen.insert((&entry.name, &entry.fields))
+                    });
+                }
+                let merge_identical_spans = settings.merge_identical_spans;
+                let mut ui_span = |ui: &mut Ui, span_id: SpanId| {
+                    let entry = &logs.spans[&span_id];
                     let mut header = String::new();
-                    logs::print_span_header(&mut header, 0, entry, false);
-                    if ui.link(header).clicked() {
-                        ui_state.cur_span = Some(*span_id);
+                    logs::print_span_header(&mut header, 0, entry, false, &print_options);
+                    if merge_identical_spans {
+                        let count = logs.identical_spans(span_id).len();
+                        if count > 1 {
+                            header.push_str(&format!(" (×{})", count));
+                        }
+                    }
+                    print_span_counts(&mut header, counts, span_id);
+                    ui.horizontal(|ui| {
+                        let response = ui.link(header);
+                        if response.clicked() {
+                            ui_state.cur_span = Some(span_id);
+                        }
+                        response
+                            .on_hover_ui(|ui| ui_span_tooltip(ui, &logs, span_id))
+                            .context_menu(|ui| {
+                                ui_span_context_menu(ui, &logs, span_id, &mut focus_to)
+                            });
+                        ui_sparkline(ui, rates, span_id);
+                    });
+                };
+                match &varying_keys {
+                    Some(varying_keys) => {
+                        for group in logs.group_repeated_spans(&span_ids, varying_keys) {
+                            ui_span_group(ui, &logs, &group, &mut ui_span);
+                        }
+                    }
+                    None => {
+                        for span_id in span_ids {
+                            ui_span(ui, span_id);
+                        }
                     }
                 }
             });
         });
+        if let Some(span_id) = focus_to {
+            self.tree_logs_ui.focus_on(span_id);
+        }
     }
 
-    fn ui_logs_tree_text(&mut self, ui: &mut Ui, _ctx: &egui::Context) {
+    /// Where the span list is focused, with a way back out.
+    fn ui_focus_breadcrumb(&mut self, ui: &mut Ui) {
         let ui_state = &mut self.tree_logs_ui;
-        egui::ScrollArea::both()
-            .auto_shrink([true; 2])
-            .show(ui, |ui| {
-                let query = if let Some(span) = ui_state.cur_span {
-                    Query::Span(span)
-                } else {
-                    Query::All
-                };
-                let text = self.logs.string_query(query);
-                ui.add(
-                    egui::TextEdit::multiline(&mut &**text)
-                        .font(TextStyle::Monospace)
-                        .desired_width(f32::INFINITY),
+        if ui_state.focus.is_empty() {
+            return;
+        }
+        let logs = self.logs.inner.lock().unwrap();
+        let mut keep = None;
+        ui.horizontal_wrapped(|ui| {
+            if ui
+                .small_button("⏶")
+                .on_hover_text("back out of the innermost focus")
+                .clicked()
+            {
+                keep = Some(ui_state.focus.len() - 1);
+            }
+            if ui.link("all spans").clicked() {
+                keep = Some(0);
+            }
+            for (i, span_id) in ui_state.focus.iter().enumerate() {
+                ui.label(">");
+                let name = logs.spans.get(span_id).map_or("", |span| &*span.name);
+                let name = if name.is_empty() { "(no name)" } else { name };
+                if ui.link(name).clicked() {
+                    keep = Some(i + 1);
+                }
+            }
+        });
+        ui.separator();
+        if let Some(keep) = keep {
+            ui_state.focus.truncate(keep);
+        }
+    }
+
+    fn ui_logs_tree_text(
+        &mut self,
+        ui: &mut Ui,
+        _ctx: &egui::Context,
+        settings: &mut Settings,
+        focus_mode: &mut bool,
+    ) {
+        self.ui_filter_bar(ui, settings);
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut settings.show_span_summary, "summarize span");
+            ui.checkbox(&mut self.tree_logs_ui.flat, "only messages")
+                .on_hover_text(
+                    "leave out the span headers and indentation, just the messages in order",
                 );
-            });
+            ui.checkbox(
+                &mut settings.display.span_relative_times,
+                "times relative to span start",
+            );
+            ui.separator();
+            let display = &mut settings.display;
+            ui.checkbox(&mut display.show_time_deltas, "time between messages")
+                .on_hover_text("start each message with how long it's been since the one above it");
+            if display.show_time_deltas {
+                let slow_delta_ms = &mut display.slow_delta_ms;
+                let mut limited = slow_delta_ms.is_some();
+                ui.checkbox(&mut limited, "pick out gaps over");
+                if limited != slow_delta_ms.is_some() {
+                    *slow_delta_ms = limited.then_some(100);
+                }
+                if let Some(slow_delta_ms) = slow_delta_ms {
+                    ui.add(egui::DragValue::new(slow_delta_ms).suffix(" ms"));
+                }
+            }
+            ui.separator();
+            let max_depth = &mut settings.display.max_depth;
+            let mut limited = max_depth.is_some();
+            ui.checkbox(&mut limited, "fold spans deeper than");
+            if limited != max_depth.is_some() {
+                *max_depth = limited.then_some(2);
+            }
+            if let Some(max_depth) = max_depth {
+                ui.add(egui::DragValue::new(max_depth).clamp_range(0..=100));
+            }
+            ui.separator();
+            ui.toggle_value(focus_mode, "⛶ focus")
+                .on_hover_text(format!(
+                    "fill the window with just the logs ({}+Shift+F)",
+                    COMMAND
+                ));
+        });
+        if let Some(span) = self.tree_logs_ui.cur_span {
+            if settings.show_span_summary {
+                self.ui_logs_tree_summary(ui, span, settings);
+            }
+        }
+        let query = self.current_query(Tab::TreeLogs, settings);
+        self.ui_logs_rows(ui, query, settings);
+    }
+
+    /// Select a span by pasting in a path from "copy span path".
+    fn ui_logs_tree_goto_path(&mut self, ui: &mut Ui) {
+        let ui_state = &mut self.tree_logs_ui;
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut ui_state.goto_span_path)
+                    .hint_text("span path")
+                    .desired_width(150.0),
+            );
+            if response.changed() {
+                ui_state.goto_span_path_failed = false;
+            }
+            let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            if ui.button("go").clicked() || submitted {
+                match self.logs.resolve_span_path(&ui_state.goto_span_path) {
+                    Some(span_id) => {
+                        let root = self.logs.inner.lock().unwrap().root_span;
+                        ui_state.cur_span = (span_id != root).then_some(span_id);
+                        ui_state.goto_span_path_failed = false;
+                    }
+                    None => ui_state.goto_span_path_failed = true,
+                }
+            }
+        });
+        if ui_state.goto_span_path_failed {
+            ui.colored_label(egui::Color32::RED, "no span with that path");
+        }
+    }
+
+    fn ui_logs_tree_summary(&mut self, ui: &mut Ui, span_id: SpanId, settings: &mut Settings) {
+        let logs = self.logs.inner.lock().unwrap();
+        let num_messages = logs.messages.len();
+        let summary = &mut self.tree_logs_ui.summary;
+        let up_to_date = summary
+            .as_ref()
+            .is_some_and(|(id, count, _)| *id == span_id && *count == num_messages);
+        if !up_to_date {
+            *summary = Some((span_id, num_messages, logs.span_summary(span_id)));
+        }
+        let (_, _, summary) = summary.as_ref().unwrap();
+        ui.add(egui::Label::new(egui::RichText::new(summary.to_string()).monospace()).wrap(true));
+        if !summary.numeric_fields.is_empty() {
+            ui_field_stats_table(ui, summary, &mut settings.stat_column_widths);
+        }
+    }
+}
+
+/// What the tree-shaped span list is showing, for each of its nodes to refer to.
+struct SpanTree<'a> {
+    logs: &'a LogsInner,
+    print_options: &'a PrintOptions,
+    /// Where each span comes in the sorted list, for ordering siblings. Spans
+    /// that aren't in here aren't shown.
+    order: HashMap<SpanId, usize>,
+    counts: Option<&'a HashMap<SpanId, MessageCounts>>,
+    rates: Option<&'a HashMap<SpanId, Vec<usize>>>,
+    /// Group repeated spans, letting these fields vary (see `Settings::group_repeated_spans`)
+    varying_keys: Option<&'a [&'a str]>,
+}
+
+/// Some repeats of a span, as one entry in the span list that opens up into
+/// all of them (unless it's only the one).
+fn ui_span_group(
+    ui: &mut Ui,
+    logs: &LogsInner,
+    group: &[SpanId],
+    mut ui_span: impl FnMut(&mut Ui, SpanId),
+) {
+    if let [span_id] = group {
+        ui_span(ui, *span_id);
+        return;
     }
+    let name = &logs.spans[&group[0]].name;
+    let id = ui.make_persistent_id(("span_group", group[0]));
+    egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+        .show_header(ui, |ui| {
+            ui.label(format!("[{}] ×{}", name, group.len()))
+                .on_hover_text("repeats of the same span");
+        })
+        .body(|ui| {
+            for &span_id in group {
+                ui_span(ui, span_id);
+            }
+        });
+}
+
+/// Follow a span's name in the span list with how many messages are right in
+/// it and how many are under it altogether.
+fn print_span_counts(
+    header: &mut String,
+    counts: Option<&HashMap<SpanId, MessageCounts>>,
+    span_id: SpanId,
+) {
+    if let Some(count) = counts.and_then(|counts| counts.get(&span_id)) {
+        header.push_str(&format!("  {} / {}", count.direct, count.total));
+    }
+}
+
+/// A tiny bar chart of when the messages under a span happened, see
+/// `LogsInner::message_rates`.
+fn ui_sparkline(ui: &mut Ui, rates: Option<&HashMap<SpanId, Vec<usize>>>, span_id: SpanId) {
+    let Some(buckets) = rates.and_then(|rates| rates.get(&span_id)) else {
+        return;
+    };
+    let max = buckets.iter().copied().max().unwrap_or(0);
+    if max == 0 {
+        return;
+    }
+    const BAR_WIDTH: f32 = 2.0;
+    let height = ui.text_style_height(&egui::TextStyle::Body);
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(BAR_WIDTH * buckets.len() as f32, height),
+        egui::Sense::hover(),
+    );
+    let color = ui.visuals().weak_text_color();
+    for (i, &count) in buckets.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        // Even one message should be visible
+        let bar_height = (height * count as f32 / max as f32).max(1.0);
+        let left = rect.left() + BAR_WIDTH * i as f32;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(left, rect.bottom() - bar_height),
+            egui::pos2(left + BAR_WIDTH, rect.bottom()),
+        );
+        ui.painter().rect_filled(bar, 0.0, color);
+    }
+    response.on_hover_text(format!(
+        "when the messages under this span happened, from the start of the logs to the end \
+         (at most {} in a bar)",
+        max
+    ));
+}
+
+/// How many levels deep the tree-shaped span list goes before it stops and
+/// offers to focus on what's further in. Each level is another few nested calls,
+/// so this is what keeps a very deep tree from overflowing the stack.
+const MAX_SPAN_TREE_DEPTH: usize = 32;
+
+/// One span in the tree-shaped span list, along with the spans inside it if
+/// it's been opened. Only spans in `order` are shown, in that order.
+fn ui_span_tree_node(
+    ui: &mut Ui,
+    tree: &SpanTree,
+    span_id: SpanId,
+    depth: usize,
+    cur_span: &mut Option<SpanId>,
+    focus_to: &mut Option<SpanId>,
+) {
+    let SpanTree {
+        logs,
+        print_options,
+        order,
+        counts,
+        rates,
+        varying_keys,
+    } = tree;
+    let span = &logs.spans[&span_id];
+    let mut children = span
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            EventEntry::Span(id) if order.contains_key(id) => Some(*id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    children.sort_by_key(|id| order[id]);
+
+    let mut header = String::new();
+    logs::print_span_header(&mut header, 0, span, false, print_options);
+    if header.is_empty() {
+        header = "(no name)".to_owned();
+    }
+    print_span_counts(&mut header, *counts, span_id);
+    let add_header = |ui: &mut Ui| {
+        let response = ui.selectable_label(*cur_span == Some(span_id), header);
+        if response.clicked() {
+            *cur_span = Some(span_id);
+        }
+        response
+            .on_hover_ui(|ui| ui_span_tooltip(ui, logs, span_id))
+            .context_menu(|ui| ui_span_context_menu(ui, logs, span_id, focus_to));
+        ui_sparkline(ui, *rates, span_id);
+    };
+
+    if children.is_empty() || depth >= MAX_SPAN_TREE_DEPTH {
+        let focus_deeper = ui
+            .horizontal(|ui| {
+                // Line up with the headers that have an arrow
+                ui.add_space(ui.spacing().indent);
+                add_header(ui);
+                !children.is_empty()
+                    && ui
+                        .small_button("⏵ deeper spans")
+                        .on_hover_text(
+                            "the tree is too deep to show here, focus on this span to see \
+                             what's inside it",
+                        )
+                        .clicked()
+            })
+            .inner;
+        if focus_deeper {
+            *focus_to = Some(span_id);
+        }
+        return;
+    }
+    let id = ui.make_persistent_id(("span_tree", span_id));
+    // The top of the list starts open
+    let default_open = span
+        .parent
+        .is_none_or(|parent| !order.contains_key(&parent));
+    egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, default_open)
+        .show_header(ui, add_header)
+        .body(|ui| {
+            let mut ui_child = |ui: &mut Ui, child| {
+                ui_span_tree_node(ui, tree, child, depth + 1, cur_span, focus_to);
+            };
+            match varying_keys {
+                Some(varying_keys) => {
+                    for group in logs.group_repeated_spans(&children, varying_keys) {
+                        ui_span_group(ui, logs, &group, &mut ui_child);
+                    }
+                }
+                None => {
+                    for child in children {
+                        ui_child(ui, child);
+                    }
+                }
+            }
+        });
+}
+
+/// A quick look at a span from the span list, without having to select it.
+fn ui_span_tooltip(ui: &mut Ui, logs: &LogsInner, span_id: SpanId) {
+    let span = &logs.spans[&span_id];
+    let summary = logs.span_summary(span_id);
+    ui.label(format!(
+        "{} messages ({} directly), {} errors, {} warnings",
+        summary.messages, summary.direct_messages, summary.errors, summary.warnings
+    ));
+    if let (Some(first), Some(last)) = (summary.first, summary.last) {
+        ui.label(format!(
+            "🕑 {} → {} ({})",
+            first.format("%Y-%m-%d %H:%M:%S%.3f"),
+            last.format("%H:%M:%S%.3f"),
+            format_elapsed((last - first).to_std().unwrap_or_default()),
+        ));
+    }
+    if !span.fields.vals.is_empty() {
+        ui.separator();
+        for (key, val) in &span.fields.vals {
+            let mut printed = String::new();
+            logs::print_val(&mut printed, 0, val, logs::NumberFormat::Plain, false);
+            ui.monospace(format!("{} = {}", key, printed));
+        }
+    }
+    if !summary.field_keys.is_empty() {
+        ui.separator();
+        let keys = summary
+            .field_keys
+            .iter()
+            .map(|key| &**key)
+            .collect::<Vec<_>>();
+        ui.weak(format!("message fields: {}", keys.join(", ")));
+    }
+}
+
+/// What can be done with a span from the span list.
+fn ui_span_context_menu(
+    ui: &mut Ui,
+    logs: &LogsInner,
+    span_id: SpanId,
+    focus_to: &mut Option<SpanId>,
+) {
+    if ui
+        .button("🔍 focus here")
+        .on_hover_text("only list what's inside this span")
+        .clicked()
+    {
+        *focus_to = Some(span_id);
+        ui.close_menu();
+    }
+    if ui.button("📋 copy span path").clicked() {
+        ui.output().copied_text = logs.span_path(span_id);
+        ui.close_menu();
+    }
+    if ui.button("💾 export as JSONL...").clicked() {
+        ui.close_menu();
+        // FIXME(WASM): this has to be made async in wasm
+        if let Some(path) = rfd::FileDialog::new()
+            .set_file_name("span.jsonl")
+            .save_file()
+        {
+            if let Err(e) = std::fs::write(&path, logs.export_jsonl(span_id)) {
+                eprintln!("WARN: failed to write {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Show the numeric field stats of a span summary, remembering the column widths.
+fn ui_field_stats_table(ui: &mut Ui, summary: &SpanSummary, widths: &mut Vec<f32>) {
+    const COLUMNS: [&str; 5] = ["field", "count", "min", "max", "mean"];
+    let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+
+    let mut table = TableBuilder::new(ui).striped(true).resizable(true);
+    for i in 0..COLUMNS.len() {
+        let default_width = if i == 0 { 150.0 } else { 80.0 };
+        let width = widths.get(i).copied().unwrap_or(default_width);
+        table = table.column(Size::initial(width).at_least(30.0));
+    }
+    let mut new_widths = Vec::with_capacity(COLUMNS.len());
+    table
+        .header(row_height, |mut header| {
+            for name in COLUMNS {
+                new_widths.push(
+                    header
+                        .col(|ui| {
+                            ui.strong(name);
+                        })
+                        .rect
+                        .width(),
+                );
+            }
+        })
+        .body(|mut body| {
+            for stats in &summary.numeric_fields {
+                body.row(row_height, |mut row| {
+                    row.col(|ui| {
+                        ui.monospace(&*stats.key);
+                    });
+                    row.col(|ui| {
+                        ui.monospace(stats.count.to_string());
+                    });
+                    for value in [stats.min, stats.max, stats.mean()] {
+                        row.col(|ui| {
+                            ui.monospace(value.to_string());
+                        });
+                    }
+                });
+            }
+        });
+    *widths = new_widths;
 }
@@ -0,0 +1,49 @@
+use egui::Ui;
+
+use crate::logs::MAX_BAD_LINES;
+
+use super::Viewer;
+
+impl Viewer {
+    /// The lines that failed to parse, in a panel along the bottom.
+    pub fn ui_parse_errors(&mut self, ui: &mut Ui) {
+        let logs = self.logs.inner.lock().unwrap();
+        ui.horizontal(|ui| {
+            ui.strong(format!("{} lines failed to parse", logs.parse_errors));
+            if logs.parse_errors > logs.bad_lines.len() {
+                ui.weak(format!("(only the first {} are shown)", MAX_BAD_LINES));
+            }
+            ui.with_layout(egui::Layout::right_to_left(), |ui| {
+                if ui.small_button("❌").on_hover_text("hide").clicked() {
+                    self.show_parse_errors = false;
+                }
+            });
+        });
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for bad_line in &logs.bad_lines {
+                    ui.horizontal(|ui| {
+                        let mut location = String::new();
+                        if let Some(file) = &bad_line.file {
+                            let name = file.file_name().unwrap_or(file.as_os_str());
+                            location.push_str(&name.to_string_lossy());
+                        }
+                        if let Some(line) = bad_line.line {
+                            location.push_str(&format!(":{}", line));
+                        }
+                        let response = ui.monospace(location);
+                        if let Some(file) = &bad_line.file {
+                            response.on_hover_text(file.display().to_string());
+                        }
+                        ui.colored_label(egui::Color32::from_rgb(230, 70, 70), &bad_line.error);
+                    });
+                    ui.add(
+                        egui::Label::new(egui::RichText::new(&bad_line.snippet).monospace().weak())
+                            .wrap(false),
+                    );
+                }
+            });
+    }
+}
@@ -1,11 +1,25 @@
+use std::path::Path;
+
 use egui::Ui;
+use tracing::Level;
 
-use crate::ProcessorStatus;
+use crate::logs::{
+    check_time_format, ColorRule, Comparison, KeyStyle, NumberFormat, TimestampPrecision,
+    UnknownLevel, JSON_TIMESTAMP_KEY,
+};
+use crate::ui_logs_rows::LevelPalette;
+use crate::{FieldPreset, Framing, LoadRange, ProcessorStatus, Tab, DEFAULT_MAX_SPANS};
 
 use super::App;
 
 #[derive(Debug, Default, Clone)]
-pub struct SettingsUi {}
+pub struct SettingsUi {
+    new_hidden_key: String,
+    /// A field preset being written: its name, and a comma-separated list of keys
+    new_preset: (String, String),
+    /// The index of the available path whose handle is being dragged
+    dragging_path: Option<usize>,
+}
 
 impl App {
     pub fn ui_settings(&mut self, ui: &mut Ui, ctx: &egui::Context) {
@@ -13,29 +27,63 @@ impl App {
         ui.heading("choose log.json");
         ui.add_space(10.0);
 
+        if let Some(path) = &self.viewer().loaded_path {
+            ui.horizontal(|ui| {
+                ui.label(format!("current: {}", path.display()));
+                ui_open_containing_folder(ui, path);
+            });
+            ui.add_space(10.0);
+        }
+
         // Show a listing of currently known minidumps to inspect
         let mut do_set_path = None;
+        let mut do_remove = None;
+        let mut row_rects = Vec::new();
         for (i, path) in self.settings.available_paths.iter().enumerate() {
-            if ui
-                .button(&*path.file_name().unwrap().to_string_lossy())
-                .clicked()
-            {
-                do_set_path = Some(i);
-            }
+            let row = ui.horizontal(|ui| {
+                let handle = ui
+                    .add(egui::Label::new("☰").sense(egui::Sense::drag()))
+                    .on_hover_text("drag to reorder");
+                if handle.drag_started() {
+                    self.settings_ui.dragging_path = Some(i);
+                }
+                if ui.small_button("❌").on_hover_text("forget").clicked() {
+                    do_remove = Some(i);
+                }
+                if ui
+                    .button(&*path.file_name().unwrap().to_string_lossy())
+                    .clicked()
+                {
+                    do_set_path = Some(i);
+                }
+                ui_open_containing_folder(ui, path);
+            });
+            row_rects.push(row.response.rect);
+        }
+        if let Some(from) = self.settings_ui.dragging_path {
+            self.ui_settings_drag_path(ui, from, &row_rects);
         }
-        if let Some(i) = do_set_path {
+        if let Some(i) = do_remove {
+            self.settings.available_paths.remove(i);
+        } else if let Some(i) = do_set_path {
             self.set_path(i);
         }
         ui.add_space(10.0);
         ui.horizontal(|ui| {
             // ui.label(message);
 
-            let cancellable = matches!(self.cur_status, ProcessorStatus::Reading);
+            let cur_status = &self.viewer().cur_status;
+            let cancellable = *cur_status == ProcessorStatus::Reading || cur_status.is_following();
             ui.add_enabled_ui(cancellable, |ui| {
                 if ui.button("❌ cancel").clicked() {
-                    self.cancel_processing();
+                    self.viewer_mut().cancel_processing();
                 }
             });
+            ui.checkbox(
+                &mut self.settings.processor.clear_on_cancel,
+                "and throw away what was read",
+            )
+            .on_hover_text("otherwise cancelling keeps the part of the logs that was read");
             /*
             let reprocessable = matches!(&self.minidump, Some(Ok(_)));
             ui.add_enabled_ui(reprocessable, |ui| {
@@ -46,15 +94,279 @@ impl App {
              */
         });
 
+        ui.add_space(10.0);
+        self.ui_settings_load_range(ui);
+        ui.horizontal(|ui| {
+            ui.label("check for cancellation every");
+            ui.add(
+                egui::DragValue::new(&mut self.settings.processor.checkin_interval_ms)
+                    .clamp_range(1..=1000)
+                    .suffix(" ms"),
+            );
+        });
+        ui.checkbox(
+            &mut self.settings.processor.intern_values,
+            "dedupe field values (saves memory, turn off to load one-off files faster)",
+        );
+        ui.checkbox(
+            &mut self.settings.processor.blank_line_records,
+            "records are separated by blank lines (for pretty-printed JSON)",
+        );
+        ui.horizontal(|ui| {
+            ui.label("records are framed by");
+            let framing = &mut self.settings.processor.framing;
+            egui::ComboBox::from_id_source("framing")
+                .selected_text(framing.label())
+                .show_ui(ui, |ui| {
+                    for choice in Framing::ALL {
+                        ui.selectable_value(framing, choice, choice.label());
+                    }
+                });
+        })
+        .response
+        .on_hover_text("for binary transports that length-prefix each record instead of ending it with a newline");
+        ui.checkbox(
+            &mut self.settings.processor.plain_text_fallback,
+            "keep lines that aren't JSON as plain text",
+        );
+        ui.checkbox(
+            &mut self.settings.processor.spans_innermost_first,
+            "span lists go from the innermost span out",
+        )
+        .on_hover_text("for formatters that list a message's spans backwards");
+        ui.horizontal(|ui| {
+            let max_spans = &mut self.settings.processor.max_spans;
+            let mut limited = max_spans.is_some();
+            if ui
+                .checkbox(&mut limited, "stop making new spans after")
+                .changed()
+            {
+                *max_spans = limited.then_some(DEFAULT_MAX_SPANS);
+            }
+            if let Some(max) = max_spans {
+                ui.add(
+                    egui::DragValue::new(max)
+                        .clamp_range(1..=usize::MAX)
+                        .speed(1000),
+                );
+            }
+        })
+        .response
+        .on_hover_text(
+            "a span field that's different every time can make more spans than there's memory for; \
+             past this, messages go in the innermost span they already have",
+        );
+        ui.horizontal(|ui| {
+            ui.label("timestamps are under the key");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.settings.processor.timestamp_key)
+                    .hint_text(JSON_TIMESTAMP_KEY)
+                    .desired_width(100.0),
+            )
+            .on_hover_text("like `ts`, `time`, or `@timestamp`; takes effect on the next load");
+        });
+        ui.checkbox(
+            &mut self.settings.processor.follow,
+            "follow the file as it grows",
+        );
+        ui.add_enabled_ui(self.settings.processor.follow, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("check for new lines every");
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.processor.follow_interval_ms)
+                        .clamp_range(100..=5000)
+                        .speed(10)
+                        .suffix(" ms"),
+                )
+                .on_hover_text("lower to see new lines sooner, raise to use less CPU");
+            });
+            ui.checkbox(
+                &mut self.settings.processor.discard_while_paused,
+                "drop new lines while paused (instead of catching up on resume)",
+            );
+        });
         ui.add_space(10.0);
 
-        if ui.button("Open log file...").clicked() {
-            // FIXME(WASM): this has to be made async in wasm
-            if let Some(path) = rfd::FileDialog::new().pick_file() {
-                self.settings.available_paths.push(path);
-                self.set_path(self.settings.available_paths.len() - 1);
+        ui.horizontal(|ui| {
+            if ui.button("Open log file...").clicked() {
+                self.pick_file();
             }
-        }
+            if ui
+                .button("Open log directory...")
+                .on_hover_text("read all the rotated log files in a directory, oldest first")
+                .clicked()
+            {
+                // FIXME(WASM): this has to be made async in wasm
+                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                    self.settings.available_paths.push(path);
+                    self.set_path(self.settings.available_paths.len() - 1);
+                }
+            }
+        });
+
+        ui.add_space(20.0);
+        ui.heading("display");
+        ui.add_space(10.0);
+        self.ui_settings_tabs(ui);
+        ui.horizontal(|ui| {
+            ui.label("start on:");
+            ui_tab_picker(
+                ui,
+                "startup_tab",
+                &mut self.settings.startup_tab,
+                Tab::Settings,
+            );
+            ui.label("after opening a file,");
+            ui.checkbox(&mut self.settings.keep_tab_on_open, "stay put");
+            ui.add_enabled_ui(!self.settings.keep_tab_on_open, |ui| {
+                ui.label("or go to:");
+                ui_tab_picker(
+                    ui,
+                    "opened_file_tab",
+                    &mut self.settings.opened_file_tab,
+                    Tab::TreeLogs,
+                );
+            });
+        });
+        ui.checkbox(
+            &mut self.settings.display.shade_rows,
+            "shade ERROR/WARN rows",
+        );
+        ui.checkbox(
+            &mut self.settings.display.show_message_ids,
+            "show message numbers (#123, usable with --goto)",
+        );
+        ui.checkbox(
+            &mut self.settings.display.show_span_lifecycle,
+            "show span lifecycle (new/enter/exit/close) events",
+        );
+        ui.horizontal(|ui| {
+            let number_format = &mut self.settings.display.number_format;
+            ui.label("numbers:");
+            egui::ComboBox::from_id_source("number_format")
+                .selected_text(number_format.label())
+                .show_ui(ui, |ui| {
+                    for format in NumberFormat::ALL {
+                        ui.selectable_value(number_format, format, format.label());
+                    }
+                });
+        });
+        ui.checkbox(
+            &mut self.settings.display.label_nameless_spans,
+            "show spans without a name, labeled by their fields",
+        );
+        ui.checkbox(
+            &mut self.settings.display.span_start_times,
+            "show when each span started in its header",
+        );
+        ui.checkbox(
+            &mut self.settings.highlight_values,
+            "color values that hold JSON, SQL, or URLs when expanded",
+        )
+        .on_hover_text("right-click a message to expand its values");
+        ui.checkbox(
+            &mut self.settings.decode_json_strings,
+            "decode strings that hold JSON when expanded",
+        )
+        .on_hover_text("for messages that are another program's JSON logs, escaped as a string");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.settings.display.show_targets, "show targets");
+            ui.add_enabled_ui(self.settings.display.show_targets, |ui| {
+                ui.label("without the prefixes:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.settings.display.target_prefixes)
+                        .hint_text("my_crate::")
+                        .desired_width(150.0),
+                )
+                .on_hover_text(
+                    "comma-separated, to shorten long targets (filtering still uses the whole target)",
+                );
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("without a message, show:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.settings.display.message_fallback_keys)
+                    .hint_text("msg, body, *")
+                    .desired_width(150.0),
+            )
+            .on_hover_text(
+                "fields to show in place of `message` for messages that don't have one, \
+                 the first one found wins (`*` is whichever field comes first)",
+            );
+        });
+        ui.checkbox(
+            &mut self.settings.display.collapse_whitespace,
+            "collapse whitespace in values (strictly one line per message)",
+        );
+        ui.horizontal(|ui| {
+            let key_style = &mut self.settings.display.key_style;
+            ui.label("field keys:");
+            egui::ComboBox::from_id_source("key_style")
+                .selected_text(key_style.label())
+                .show_ui(ui, |ui| {
+                    for style in KeyStyle::ALL {
+                        ui.selectable_value(key_style, style, style.label());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            let precision = &mut self.settings.display.timestamp_precision;
+            ui.label("timestamp precision:");
+            egui::ComboBox::from_id_source("timestamp_precision")
+                .selected_text(precision.label())
+                .show_ui(ui, |ui| {
+                    for choice in TimestampPrecision::ALL {
+                        ui.selectable_value(precision, choice, choice.label());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            let format = &mut self.settings.display.time_format;
+            ui.label("custom time format:");
+            ui.add(
+                egui::TextEdit::singleline(format)
+                    .hint_text("%H:%M:%S%.3f")
+                    .desired_width(150.0),
+            )
+            .on_hover_text("a chrono strftime format, overrides the precision when set");
+            if !format.trim().is_empty() {
+                match check_time_format(format.trim()) {
+                    Ok(()) => {
+                        ui.weak(format!(
+                            "e.g. {}",
+                            chrono::Local::now().format(format.trim())
+                        ));
+                    }
+                    Err(e) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            let unknown_level = &mut self.settings.display.unknown_level;
+            ui.label("unrecognized levels:");
+            egui::ComboBox::from_id_source("unknown_level")
+                .selected_text(unknown_level.label())
+                .show_ui(ui, |ui| {
+                    for choice in UnknownLevel::ALL {
+                        ui.selectable_value(unknown_level, choice, choice.label());
+                    }
+                });
+        });
+
+        ui.add_space(20.0);
+        self.ui_settings_hidden_keys(ui);
+
+        ui.add_space(20.0);
+        self.ui_settings_field_presets(ui);
+
+        ui.add_space(20.0);
+        self.ui_settings_level_palette(ui);
+
+        ui.add_space(20.0);
+        self.ui_settings_color_rules(ui);
 
         ui.add_space(20.0);
         preview_files_being_dropped(ctx);
@@ -73,6 +385,334 @@ impl App {
     }
 }
 
+impl App {
+    /// Show where the dragged path would land, and move it there when it's dropped.
+    fn ui_settings_drag_path(&mut self, ui: &mut Ui, from: usize, row_rects: &[egui::Rect]) {
+        let pointer = ui.input().pointer.interact_pos();
+        // The gap between rows that the pointer is closest to
+        let to = pointer.map(|pos| {
+            row_rects
+                .iter()
+                .position(|rect| pos.y < rect.center().y)
+                .unwrap_or(row_rects.len())
+        });
+        if let Some(to) = to {
+            let y = match row_rects.get(to) {
+                Some(rect) => rect.top(),
+                None => row_rects.last().map_or(0.0, |rect| rect.bottom()),
+            };
+            let x_range = ui.max_rect().x_range();
+            ui.painter().hline(
+                x_range,
+                y,
+                egui::Stroke::new(2.0, ui.visuals().selection.stroke.color),
+            );
+        }
+        if ui.input().pointer.any_released() {
+            self.settings_ui.dragging_path = None;
+            if let Some(to) = to {
+                let paths = &mut self.settings.available_paths;
+                if from < paths.len() {
+                    let path = paths.remove(from);
+                    // Removing the path shifts everything after it up by one
+                    let to = if to > from { to - 1 } else { to };
+                    paths.insert(to.min(paths.len()), path);
+                }
+            }
+        }
+    }
+
+    fn ui_settings_load_range(&mut self, ui: &mut Ui) {
+        let range = &mut self.load_range;
+        ui.horizontal(|ui| {
+            ui.label("load:");
+            if ui.radio(*range == LoadRange::All, "whole file").clicked() {
+                *range = LoadRange::All;
+            }
+            if ui
+                .radio(matches!(range, LoadRange::Lines { .. }), "lines")
+                .clicked()
+            {
+                *range = LoadRange::Lines {
+                    start: 0,
+                    end: 100_000,
+                };
+            }
+            if ui
+                .radio(matches!(range, LoadRange::Bytes { .. }), "bytes")
+                .clicked()
+            {
+                *range = LoadRange::Bytes {
+                    start: 0,
+                    end: 100_000_000,
+                };
+            }
+            if ui
+                .radio(matches!(range, LoadRange::Tail { .. }), "last lines")
+                .clicked()
+            {
+                *range = LoadRange::Tail { lines: 10_000 };
+            }
+            match range {
+                LoadRange::All => {}
+                LoadRange::Tail { lines } => {
+                    ui.add(egui::DragValue::new(lines).speed(1000.0));
+                }
+                LoadRange::Lines { start, end } | LoadRange::Bytes { start, end } => {
+                    ui.add(egui::DragValue::new(start).speed(1000.0));
+                    ui.label("..");
+                    ui.add(egui::DragValue::new(end).speed(1000.0));
+                    *end = (*end).max(*start);
+                }
+            }
+        });
+        if self.load_range != LoadRange::All {
+            ui.label("applies to the next file opened; spans and timestamps from before the range won't be known");
+        }
+    }
+
+    fn ui_settings_hidden_keys(&mut self, ui: &mut Ui) {
+        ui.heading("hidden fields");
+        ui.add_space(10.0);
+
+        let ui_state = &mut self.settings_ui;
+        let hidden_keys = &mut self.settings.display.hidden_keys;
+        let mut do_remove = None;
+        for (i, key) in hidden_keys.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("❌").clicked() {
+                    do_remove = Some(i);
+                }
+                ui.monospace(key);
+            });
+        }
+        if let Some(i) = do_remove {
+            hidden_keys.remove(i);
+        }
+        ui.horizontal(|ui| {
+            let response = ui.text_edit_singleline(&mut ui_state.new_hidden_key);
+            let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            if ui.button("hide").clicked() || submitted {
+                let key = ui_state.new_hidden_key.trim();
+                if !key.is_empty() && !hidden_keys.iter().any(|k| k == key) {
+                    hidden_keys.push(key.to_owned());
+                }
+                ui_state.new_hidden_key.clear();
+            }
+        });
+    }
+}
+
+impl App {
+    fn ui_settings_field_presets(&mut self, ui: &mut Ui) {
+        ui.heading("field presets");
+        ui.add_space(10.0);
+
+        ui.horizontal(|ui| {
+            ui.label("showing:");
+            crate::ui_field_preset_picker(ui, &mut self.settings);
+        });
+        let presets = &mut self.settings.field_presets;
+        let mut do_remove = None;
+        for (i, preset) in presets.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("❌").clicked() {
+                    do_remove = Some(i);
+                }
+                ui.label(&preset.name);
+                let keys = preset.shown_keys.as_deref().unwrap_or_default();
+                ui.monospace(keys.join(", "));
+            });
+        }
+        if let Some(i) = do_remove {
+            presets.remove(i);
+        }
+
+        let (name, keys) = &mut self.settings_ui.new_preset;
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(name)
+                    .hint_text("name")
+                    .desired_width(100.0),
+            );
+            ui.add(
+                egui::TextEdit::singleline(keys)
+                    .hint_text("fields to show, comma separated")
+                    .desired_width(250.0),
+            );
+            let name = name.trim();
+            ui.add_enabled_ui(!name.is_empty(), |ui| {
+                if ui.button("add preset").clicked() {
+                    let shown_keys = keys
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|key| !key.is_empty())
+                        .map(str::to_owned)
+                        .collect();
+                    presets.retain(|preset| preset.name != name);
+                    presets.push(FieldPreset {
+                        name: name.to_owned(),
+                        shown_keys: Some(shown_keys),
+                    });
+                }
+            });
+        });
+    }
+
+    /// Pick which tabs are in the tab bar, and their order.
+    fn ui_settings_tabs(&mut self, ui: &mut Ui) {
+        let shown = self
+            .settings
+            .tabs
+            .clone()
+            .unwrap_or_else(|| Tab::DEFAULT_SHOWN.to_vec());
+        // The shown tabs in order, then the hidden ones
+        let mut order = shown.iter().map(|&tab| (tab, true)).collect::<Vec<_>>();
+        order.extend(
+            Tab::ALL
+                .into_iter()
+                .filter(|tab| !shown.contains(tab))
+                .map(|tab| (tab, false)),
+        );
+
+        let mut changed = false;
+        let mut move_up = None;
+        ui.horizontal(|ui| {
+            ui.label("tabs:");
+            for (i, (tab, enabled)) in order.iter_mut().enumerate() {
+                ui.add_enabled_ui(*tab != Tab::Settings, |ui| {
+                    changed |= ui
+                        .checkbox(enabled, tab.name())
+                        .on_disabled_hover_text("the settings tab is always shown")
+                        .changed();
+                });
+                if i > 0 && *enabled && ui.small_button("⏴").on_hover_text("move left").clicked()
+                {
+                    move_up = Some(i);
+                }
+                ui.add_space(10.0);
+            }
+        });
+        if let Some(i) = move_up {
+            order.swap(i - 1, i);
+            changed = true;
+        }
+        if changed {
+            let tabs = order
+                .into_iter()
+                .filter(|&(_, enabled)| enabled)
+                .map(|(tab, _)| tab)
+                .collect();
+            self.settings.tabs = Some(tabs);
+        }
+    }
+
+    fn ui_settings_level_palette(&mut self, ui: &mut Ui) {
+        ui.heading("level colors");
+        ui.add_space(10.0);
+
+        let palette = &mut self.settings.level_palette;
+        ui.horizontal(|ui| {
+            ui.label("palette:");
+            let current = LevelPalette::BUILTIN
+                .iter()
+                .find(|(_, builtin)| builtin == palette)
+                .map_or("custom", |(name, _)| name);
+            egui::ComboBox::from_id_source("level_palette")
+                .selected_text(current)
+                .show_ui(ui, |ui| {
+                    for (name, builtin) in LevelPalette::BUILTIN {
+                        ui.selectable_value(palette, builtin, name);
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            for level in [
+                Level::ERROR,
+                Level::WARN,
+                Level::INFO,
+                Level::DEBUG,
+                Level::TRACE,
+            ] {
+                ui.color_edit_button_srgb(palette.color_mut(level));
+                ui.label(level.as_str());
+                ui.add_space(10.0);
+            }
+        });
+    }
+
+    fn ui_settings_color_rules(&mut self, ui: &mut Ui) {
+        ui.heading("coloring rules");
+        ui.add_space(10.0);
+
+        let rules = &mut self.settings.display.color_rules;
+        let mut do_remove = None;
+        for (i, rule) in rules.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("❌").clicked() {
+                    do_remove = Some(i);
+                }
+                ui.add(egui::TextEdit::singleline(&mut rule.key).desired_width(100.0));
+                egui::ComboBox::from_id_source(("color_rule_comparison", i))
+                    .selected_text(rule.comparison.label())
+                    .width(80.0)
+                    .show_ui(ui, |ui| {
+                        for comparison in Comparison::ALL {
+                            ui.selectable_value(
+                                &mut rule.comparison,
+                                comparison,
+                                comparison.label(),
+                            );
+                        }
+                    });
+                ui.add(egui::TextEdit::singleline(&mut rule.value).desired_width(100.0));
+                ui.color_edit_button_srgb(&mut rule.color);
+            });
+        }
+        if let Some(i) = do_remove {
+            rules.remove(i);
+        }
+        if ui.button("add rule").clicked() {
+            rules.push(ColorRule::default());
+        }
+    }
+}
+
+/// Pick a tab for a setting, showing `default` when it's unset.
+fn ui_tab_picker(ui: &mut Ui, id: &str, tab: &mut Option<Tab>, default: Tab) {
+    let mut picked = tab.unwrap_or(default);
+    egui::ComboBox::from_id_source(id)
+        .selected_text(picked.name())
+        .show_ui(ui, |ui| {
+            for choice in Tab::ALL {
+                ui.selectable_value(&mut picked, choice, choice.name());
+            }
+        });
+    if picked != tab.unwrap_or(default) {
+        *tab = Some(picked);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn ui_open_containing_folder(ui: &mut Ui, path: &Path) {
+    if ui
+        .small_button("📂")
+        .on_hover_text("open containing folder")
+        .clicked()
+    {
+        if let Some(dir) = path.parent() {
+            if let Err(e) = opener::open(dir) {
+                eprintln!("WARN: failed to open {}: {}", dir.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn ui_open_containing_folder(_ui: &mut Ui, _path: &Path) {
+    // There's no file manager to open on the web
+}
+
 fn preview_files_being_dropped(ctx: &egui::Context) {
     use egui::*;
     use std::fmt::Write as _;
@@ -0,0 +1,208 @@
+use std::time::{Duration, Instant};
+
+use egui::{Color32, RichText, Ui};
+
+use crate::{ProcessorStatus, ReloadChanges};
+
+use super::Viewer;
+
+impl Viewer {
+    pub fn ui_status_bar(&mut self, ui: &mut Ui) {
+        let throughput = self.cur_throughput;
+        ui.horizontal(|ui| {
+            let status = match self.cur_status {
+                ProcessorStatus::NotStarted => "no logs loaded",
+                ProcessorStatus::IoFailed => "failed to read logs",
+                ProcessorStatus::Cancelled if self.logs.num_messages() > 0 => {
+                    "⚠ cancelled, only part of the logs were read"
+                }
+                ProcessorStatus::Cancelled => "cancelled",
+                ProcessorStatus::Reading => "reading...",
+                ProcessorStatus::Done => "done",
+                ProcessorStatus::Following => "following...",
+                ProcessorStatus::Paused { .. } => "⏸ paused",
+                ProcessorStatus::Crashed(_) => "💥 crashed while reading",
+            };
+            let response = ui.label(status);
+            if let ProcessorStatus::Crashed(message) = &self.cur_status {
+                response.on_hover_text(format!(
+                    "{}\n\n(whatever was read before that is still here)",
+                    message
+                ));
+            }
+            if self.cur_status == ProcessorStatus::NotStarted {
+                return;
+            }
+            self.ui_follow_pause(ui);
+            self.ui_reload_changes(ui);
+            if let Some(range) = self.loaded_range.partial_description() {
+                ui.separator();
+                ui.label(format!("⚠ only {}", range)).on_hover_text(
+                    "only part of the file was loaded, so spans that began before it may be missing or misattributed",
+                );
+            }
+            let overflowed = self.logs.overflowed_messages();
+            if overflowed > 0 {
+                ui.separator();
+                ui.colored_label(
+                    Color32::from_rgb(220, 170, 40),
+                    format!("⚠ span limit reached ({} messages misplaced)", overflowed),
+                )
+                .on_hover_text(
+                    "those messages are in an outer span instead of their own, \
+                     see the span limit in the settings",
+                );
+            }
+            ui.separator();
+            ui.label(format!(
+                "{} lines, {:.1} MB",
+                throughput.lines,
+                throughput.bytes as f64 / 1_000_000.0
+            ));
+            ui.separator();
+            ui.label(format!(
+                "{:.0} lines/s, {:.1} MB/s",
+                throughput.lines_per_sec,
+                throughput.bytes_per_sec / 1_000_000.0
+            ));
+            self.ui_time_range(ui);
+            self.ui_memory_estimate(ui);
+            if self.cur_status == ProcessorStatus::Reading {
+                if let Some(total_bytes) = throughput.total_bytes.filter(|&total| total > 0) {
+                    ui.separator();
+                    let progress = throughput.bytes as f32 / total_bytes as f32;
+                    ui.add(egui::ProgressBar::new(progress).show_percentage());
+                }
+            }
+        });
+    }
+
+    /// Pause and resume following, and say how much has piled up while paused.
+    fn ui_follow_pause(&mut self, ui: &mut Ui) {
+        if !self.cur_status.is_following() {
+            return;
+        }
+        if let ProcessorStatus::Paused {
+            new_lines,
+            discarding,
+        } = self.cur_status
+        {
+            let what = if discarding { "dropped" } else { "waiting" };
+            ui.colored_label(
+                Color32::from_rgb(220, 170, 40),
+                format!("({} new lines {})", new_lines, what),
+            );
+            if ui.button("▶ resume").clicked() {
+                self.toggle_follow_paused();
+            }
+        } else if ui
+            .button("⏸ pause")
+            .on_hover_text("stop adding new lines until resumed")
+            .clicked()
+        {
+            self.toggle_follow_paused();
+        }
+    }
+
+    /// What stretch of time the loaded logs cover.
+    fn ui_time_range(&mut self, ui: &mut Ui) {
+        let Some((first, last)) = self.logs.time_range() else {
+            return;
+        };
+        // Don't repeat the date if it's all on one day
+        let last_format = if first.date_naive() == last.date_naive() {
+            "%H:%M:%S"
+        } else {
+            "%Y-%m-%d %H:%M:%S"
+        };
+        ui.separator();
+        ui.label(format!(
+            "🕑 {} → {} ({})",
+            first.format("%Y-%m-%d %H:%M:%S"),
+            last.format(last_format),
+            format_elapsed((last - first).to_std().unwrap_or_default()),
+        ))
+        .on_hover_text("the earliest and latest message timestamps");
+    }
+
+    /// Roughly how much memory the logs are using, updated whenever reading stops.
+    fn ui_memory_estimate(&mut self, ui: &mut Ui) {
+        /// Estimating goes through all the logs with them locked, so it's
+        /// redone at most this often.
+        const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+        if self.cur_status != ProcessorStatus::Reading {
+            let num_messages = self.logs.num_messages();
+            match self.memory_estimate {
+                Some((count, _, _)) if count == num_messages => {}
+                Some((_, at, _)) if at.elapsed() < MIN_INTERVAL => {
+                    // Otherwise the old estimate could stay up until something else repaints
+                    ui.ctx().request_repaint();
+                }
+                _ => {
+                    let estimate = self.logs.inner.lock().unwrap().memory_estimate();
+                    self.memory_estimate = Some((num_messages, Instant::now(), estimate));
+                }
+            }
+        }
+        let Some((_, _, estimate)) = self.memory_estimate else {
+            return;
+        };
+        let mb = |bytes: usize| bytes as f64 / 1_000_000.0;
+        ui.separator();
+        ui.label(format!("~{:.1} MB in memory", mb(estimate.total())))
+            .on_hover_text(format!(
+                "strings: {:.1} MB\nmessages: {:.1} MB\nspans: {:.1} MB\nrendered view: {:.1} MB\n\n\
+                 (a lower bound, not counting allocator overhead)",
+                mb(estimate.strings),
+                mb(estimate.messages),
+                mb(estimate.spans),
+                mb(estimate.rendered),
+            ));
+    }
+
+    /// A badge for what the last reload brought in, until it gets old or clicked.
+    fn ui_reload_changes(&mut self, ui: &mut Ui) {
+        let changes = match self.reload_changes {
+            Some(changes) if changes.at.elapsed() < ReloadChanges::SHOW_FOR => changes,
+            _ => {
+                self.reload_changes = None;
+                return;
+            }
+        };
+        let color = if changes.errors > 0 {
+            Color32::from_rgb(230, 70, 70)
+        } else {
+            Color32::from_rgb(80, 170, 80)
+        };
+        ui.separator();
+        let text = RichText::new(format!(
+            "{:+} messages, {:+} errors",
+            changes.messages, changes.errors
+        ))
+        .color(color);
+        if ui
+            .add(egui::Label::new(text).sense(egui::Sense::click()))
+            .on_hover_text("since before the last reload (click to dismiss)")
+            .clicked()
+        {
+            self.reload_changes = None;
+        }
+    }
+}
+
+/// A short human-readable length of time, like `5m23s`.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs == 0 {
+        format!("{}ms", elapsed.as_millis())
+    } else if secs < 60 {
+        format!("{:.1}s", elapsed.as_secs_f64())
+    } else if secs < 60 * 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{}h{:02}m", secs / (60 * 60), secs / 60 % 60)
+    } else {
+        format!("{}d{:02}h", secs / (24 * 60 * 60), secs / (60 * 60) % 24)
+    }
+}
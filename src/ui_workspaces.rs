@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+
+use crate::logs::{DisplayOptions, Filter, SpanId, TargetGlob};
+use crate::ui_filter::FilterUi;
+use crate::Tab;
+
+use super::App;
+
+/// A saved investigation: the file, the filter, how things are displayed, and
+/// what was selected, to pick back up later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Workspace {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub filter: Filter,
+    /// The target glob, which `Filter` doesn't save itself
+    pub target: Option<String>,
+    pub display: DisplayOptions,
+    pub tab: Tab,
+    /// The selected spans, which only mean anything for the same file (span ids
+    /// are handed out in the order they're read, so they survive a reload).
+    pub tree_span: Option<SpanId>,
+    pub linear_span: Option<SpanId>,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            path: None,
+            filter: Filter::default(),
+            target: None,
+            display: DisplayOptions::default(),
+            tab: Tab::TreeLogs,
+            tree_span: None,
+            linear_span: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct WorkspacesUi {
+    /// The workspace last saved or switched to
+    current: Option<String>,
+    /// The name to save the next workspace as
+    new_name: String,
+}
+
+impl App {
+    pub fn ui_workspaces(&mut self, ui: &mut Ui) {
+        let title = match &self.workspaces_ui.current {
+            Some(name) => format!("🗂 {}", name),
+            None => "🗂 workspaces".to_owned(),
+        };
+        ui.menu_button(title, |ui| {
+            let mut picked = None;
+            let mut removed = None;
+            for (idx, workspace) in self.settings.workspaces.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let current = self.workspaces_ui.current.as_ref() == Some(&workspace.name);
+                    let response = ui.selectable_label(current, &workspace.name);
+                    if let Some(path) = &workspace.path {
+                        response.clone().on_hover_text(path.display().to_string());
+                    }
+                    if response.clicked() {
+                        picked = Some(idx);
+                    }
+                    if ui.small_button("🗑").on_hover_text("forget it").clicked() {
+                        removed = Some(idx);
+                    }
+                });
+            }
+            if let Some(idx) = picked {
+                self.load_workspace(self.settings.workspaces[idx].clone());
+                ui.close_menu();
+            }
+            if let Some(idx) = removed {
+                let workspace = self.settings.workspaces.remove(idx);
+                if self.workspaces_ui.current.as_ref() == Some(&workspace.name) {
+                    self.workspaces_ui.current = None;
+                }
+            }
+            if !self.settings.workspaces.is_empty() {
+                ui.separator();
+            }
+
+            ui.horizontal(|ui| {
+                let ui_state = &mut self.workspaces_ui;
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut ui_state.new_name)
+                        .hint_text("name")
+                        .desired_width(120.0),
+                );
+                let name = ui_state.new_name.trim().to_owned();
+                let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+                let overwrites = self.settings.workspaces.iter().any(|w| w.name == name);
+                let label = if overwrites {
+                    "💾 overwrite"
+                } else {
+                    "💾 save"
+                };
+                let clicked = ui
+                    .add_enabled(!name.is_empty(), egui::Button::new(label))
+                    .on_hover_text("save the file, filter, display settings, and selected spans")
+                    .clicked();
+                if (clicked || submitted) && !name.is_empty() {
+                    self.save_workspace(name);
+                    self.workspaces_ui.new_name.clear();
+                    ui.close_menu();
+                }
+            });
+        });
+    }
+
+    /// Save the current setup under `name`, replacing any workspace already called that.
+    fn save_workspace(&mut self, name: String) {
+        let viewer = self.viewer();
+        let workspace = Workspace {
+            name: name.clone(),
+            path: viewer.loaded_path.clone(),
+            filter: viewer.filter.clone(),
+            target: viewer
+                .filter
+                .target
+                .as_ref()
+                .map(|glob| glob.pattern().to_owned()),
+            display: self.settings.display.clone(),
+            tab: self.tab,
+            tree_span: viewer.tree_logs_ui.cur_span,
+            linear_span: viewer.linear_logs_ui.cur_span,
+        };
+        let workspaces = &mut self.settings.workspaces;
+        match workspaces.iter_mut().find(|w| w.name == name) {
+            Some(existing) => *existing = workspace,
+            None => workspaces.push(workspace),
+        }
+        self.workspaces_ui.current = Some(name);
+    }
+
+    fn load_workspace(&mut self, workspace: Workspace) {
+        if let Some(path) = workspace.path {
+            if self.viewer().loaded_path.as_ref() != Some(&path) {
+                if !self.settings.available_paths.contains(&path) {
+                    self.settings.available_paths.push(path.clone());
+                }
+                self.settings.picked_path = Some(path.display().to_string());
+                self.open_logs(path);
+            }
+        }
+        let viewer = &mut self.viewers[self.cur_viewer];
+        viewer.filter = workspace.filter;
+        viewer.filter.target =
+            workspace
+                .target
+                .and_then(|pattern| match TargetGlob::new(&pattern) {
+                    Ok(glob) => Some(glob),
+                    Err(e) => {
+                        eprintln!("WARN: couldn't restore target filter {:?}: {}", pattern, e);
+                        None
+                    }
+                });
+        viewer.filter_ui = FilterUi::default();
+        viewer.tree_logs_ui.cur_span = workspace.tree_span;
+        viewer.linear_logs_ui.cur_span = workspace.linear_span;
+        self.settings.display = workspace.display;
+        self.tab = workspace.tab;
+        self.workspaces_ui.current = Some(workspace.name);
+    }
+}